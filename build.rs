@@ -1,7 +1,7 @@
 use std::process::Command;
 fn main() {
     let git_describe = Command::new("git")
-        .args(&["describe"])
+        .args(["describe"])
         .output()
         .ok()
         .filter(|out| out.status.success());
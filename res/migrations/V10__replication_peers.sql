@@ -0,0 +1,9 @@
+-- Pods that this Pod replicates changes from, and how far it got in their change feed.
+-- See the `replicate` endpoint.
+CREATE TABLE replicationPeers (
+    url TEXT NOT NULL,
+    ownerKey TEXT NOT NULL,
+    changeSeq INTEGER NOT NULL, /* the last change of the peer that was replicated */
+    dateReplicated INTEGER /* datetime */ NOT NULL
+);
+CREATE UNIQUE INDEX idx_replicationPeers_url_ownerKey on replicationPeers(url, ownerKey);
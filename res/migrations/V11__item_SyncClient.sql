@@ -0,0 +1,58 @@
+-- Items of type "SyncClient" have property "name" (text)
+INSERT INTO items(id, type, dateCreated, dateModified, dateServerModified, deleted) VALUES(
+    "40045af1f47a88432aa923cfd587519b",
+    "ItemPropertySchema", 0, 0, 0, 0
+);
+INSERT INTO strings(item, name, value) VALUES(
+    (SELECT rowid FROM items WHERE id = "40045af1f47a88432aa923cfd587519b"),
+    "itemType", "SyncClient"
+);
+INSERT INTO strings(item, name, value) VALUES(
+    (SELECT rowid FROM items WHERE id = "40045af1f47a88432aa923cfd587519b"),
+    "propertyName", "name"
+);
+INSERT INTO strings(item, name, value) VALUES(
+    (SELECT rowid FROM items WHERE id = "40045af1f47a88432aa923cfd587519b"),
+    "valueType", "Text"
+);
+
+-- Items of type "SyncClient" have property "ackedChangeSeq" (integer)
+INSERT INTO items(id, type, dateCreated, dateModified, dateServerModified, deleted) VALUES(
+    "e34d6a253ad7094f3d6c505dc45c669a",
+    "ItemPropertySchema", 0, 0, 0, 0
+);
+INSERT INTO strings(item, name, value) VALUES(
+    (SELECT rowid FROM items WHERE id = "e34d6a253ad7094f3d6c505dc45c669a"),
+    "itemType", "SyncClient"
+);
+INSERT INTO strings(item, name, value) VALUES(
+    (SELECT rowid FROM items WHERE id = "e34d6a253ad7094f3d6c505dc45c669a"),
+    "propertyName", "ackedChangeSeq"
+);
+INSERT INTO strings(item, name, value) VALUES(
+    (SELECT rowid FROM items WHERE id = "e34d6a253ad7094f3d6c505dc45c669a"),
+    "valueType", "Integer"
+);
+
+-- Items of type "SyncClient" have property "dateAcked" (datetime)
+INSERT INTO items(id, type, dateCreated, dateModified, dateServerModified, deleted) VALUES(
+    "5c2e1c4a1578fe9bf9d0ce1a76310b91",
+    "ItemPropertySchema", 0, 0, 0, 0
+);
+INSERT INTO strings(item, name, value) VALUES(
+    (SELECT rowid FROM items WHERE id = "5c2e1c4a1578fe9bf9d0ce1a76310b91"),
+    "itemType", "SyncClient"
+);
+INSERT INTO strings(item, name, value) VALUES(
+    (SELECT rowid FROM items WHERE id = "5c2e1c4a1578fe9bf9d0ce1a76310b91"),
+    "propertyName", "dateAcked"
+);
+INSERT INTO strings(item, name, value) VALUES(
+    (SELECT rowid FROM items WHERE id = "5c2e1c4a1578fe9bf9d0ce1a76310b91"),
+    "valueType", "DateTime"
+);
+
+-- Give the new schema items a place in the change sequence, for clients to sync them
+UPDATE items SET changeSeq = (SELECT value FROM changeSequence WHERE rowid = 1) + rowid
+    WHERE changeSeq = 0;
+UPDATE changeSequence SET value = (SELECT MAX(changeSeq) FROM items) WHERE rowid = 1;
@@ -0,0 +1,10 @@
+-- Operations of client operation logs that were applied, to not apply retried ones again.
+-- See the `apply_operations` endpoint.
+CREATE TABLE appliedOperations (
+    clientId TEXT NOT NULL,
+    opId TEXT NOT NULL,
+    result TEXT NOT NULL, /* JSON result of the operation, returned again for retries */
+    dateApplied INTEGER /* datetime */ NOT NULL
+);
+CREATE UNIQUE INDEX idx_appliedOperations_clientId_opId on appliedOperations(clientId, opId);
+CREATE INDEX idx_appliedOperations_dateApplied on appliedOperations(dateApplied);
@@ -0,0 +1,17 @@
+-- The part of the database that a client syncs, see the `set_sync_scope` endpoint.
+CREATE TABLE syncScopes (
+    clientId TEXT NOT NULL,
+    scope TEXT NOT NULL, /* JSON */
+    dateSet INTEGER /* datetime */ NOT NULL
+);
+CREATE UNIQUE INDEX idx_syncScopes_clientId on syncScopes(clientId);
+
+-- Whether the client was last told that an item is in its scope or has left it,
+-- and in which change that happened, for `changes_since` to send it again on retries.
+CREATE TABLE syncScopeItems (
+    clientId TEXT NOT NULL,
+    id TEXT NOT NULL, /* item id, kept after the item is purged */
+    inScope INTEGER /* boolean */ NOT NULL,
+    changeSeq INTEGER NOT NULL
+);
+CREATE UNIQUE INDEX idx_syncScopeItems_clientId_id on syncScopeItems(clientId, id);
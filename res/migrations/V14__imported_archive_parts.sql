@@ -0,0 +1,8 @@
+-- Parts of archives that were imported, to resume an interrupted import where it stopped.
+-- See the `import` command.
+CREATE TABLE importedArchiveParts (
+    archive TEXT NOT NULL, /* sha256 of the archive's manifest.json */
+    part TEXT NOT NULL, /* path of the part in the archive */
+    dateImported INTEGER /* datetime */ NOT NULL
+);
+CREATE UNIQUE INDEX idx_importedArchiveParts_archive_part on importedArchiveParts(archive, part);
@@ -0,0 +1,8 @@
+-- Items that were purged, for `changes_since` to tell clients to remove them.
+-- See the `purge_item` endpoint.
+CREATE TABLE purgedItems (
+    id TEXT NOT NULL,
+    changeSeq INTEGER NOT NULL
+);
+CREATE UNIQUE INDEX idx_purgedItems_id on purgedItems(id);
+CREATE INDEX idx_purgedItems_changeSeq on purgedItems(changeSeq);
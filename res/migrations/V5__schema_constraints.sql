@@ -0,0 +1,93 @@
+-- Items of type "ItemPropertySchema" have property "isRequired" (bool)
+INSERT INTO items(id, type, dateCreated, dateModified, dateServerModified, deleted) VALUES(
+    "8b715a56c6102bd8e040b257a6a96edc",
+    "ItemPropertySchema", 0, 0, 0, 0
+);
+INSERT INTO strings(item, name, value) VALUES(
+    (SELECT rowid FROM items WHERE id = "8b715a56c6102bd8e040b257a6a96edc"),
+    "itemType", "ItemPropertySchema"
+);
+INSERT INTO strings(item, name, value) VALUES(
+    (SELECT rowid FROM items WHERE id = "8b715a56c6102bd8e040b257a6a96edc"),
+    "propertyName", "isRequired"
+);
+INSERT INTO strings(item, name, value) VALUES(
+    (SELECT rowid FROM items WHERE id = "8b715a56c6102bd8e040b257a6a96edc"),
+    "valueType", "Bool"
+);
+
+
+-- Items of type "ItemPropertySchema" have property "isUnique" (bool)
+INSERT INTO items(id, type, dateCreated, dateModified, dateServerModified, deleted) VALUES(
+    "405929a30de9851cc61822c35f34ddca",
+    "ItemPropertySchema", 0, 0, 0, 0
+);
+INSERT INTO strings(item, name, value) VALUES(
+    (SELECT rowid FROM items WHERE id = "405929a30de9851cc61822c35f34ddca"),
+    "itemType", "ItemPropertySchema"
+);
+INSERT INTO strings(item, name, value) VALUES(
+    (SELECT rowid FROM items WHERE id = "405929a30de9851cc61822c35f34ddca"),
+    "propertyName", "isUnique"
+);
+INSERT INTO strings(item, name, value) VALUES(
+    (SELECT rowid FROM items WHERE id = "405929a30de9851cc61822c35f34ddca"),
+    "valueType", "Bool"
+);
+
+
+-- Items of type "ItemPropertySchema" have property "pattern" (text)
+INSERT INTO items(id, type, dateCreated, dateModified, dateServerModified, deleted) VALUES(
+    "5977c6ed9c8914766c38b916e924a3d3",
+    "ItemPropertySchema", 0, 0, 0, 0
+);
+INSERT INTO strings(item, name, value) VALUES(
+    (SELECT rowid FROM items WHERE id = "5977c6ed9c8914766c38b916e924a3d3"),
+    "itemType", "ItemPropertySchema"
+);
+INSERT INTO strings(item, name, value) VALUES(
+    (SELECT rowid FROM items WHERE id = "5977c6ed9c8914766c38b916e924a3d3"),
+    "propertyName", "pattern"
+);
+INSERT INTO strings(item, name, value) VALUES(
+    (SELECT rowid FROM items WHERE id = "5977c6ed9c8914766c38b916e924a3d3"),
+    "valueType", "Text"
+);
+
+
+-- Items of type "ItemPropertySchema" have property "minimum" (real)
+INSERT INTO items(id, type, dateCreated, dateModified, dateServerModified, deleted) VALUES(
+    "f2207dacf825c8156bc221ff0213899d",
+    "ItemPropertySchema", 0, 0, 0, 0
+);
+INSERT INTO strings(item, name, value) VALUES(
+    (SELECT rowid FROM items WHERE id = "f2207dacf825c8156bc221ff0213899d"),
+    "itemType", "ItemPropertySchema"
+);
+INSERT INTO strings(item, name, value) VALUES(
+    (SELECT rowid FROM items WHERE id = "f2207dacf825c8156bc221ff0213899d"),
+    "propertyName", "minimum"
+);
+INSERT INTO strings(item, name, value) VALUES(
+    (SELECT rowid FROM items WHERE id = "f2207dacf825c8156bc221ff0213899d"),
+    "valueType", "Real"
+);
+
+
+-- Items of type "ItemPropertySchema" have property "maximum" (real)
+INSERT INTO items(id, type, dateCreated, dateModified, dateServerModified, deleted) VALUES(
+    "d2d8558c8d050e571c4a52841fad99d7",
+    "ItemPropertySchema", 0, 0, 0, 0
+);
+INSERT INTO strings(item, name, value) VALUES(
+    (SELECT rowid FROM items WHERE id = "d2d8558c8d050e571c4a52841fad99d7"),
+    "itemType", "ItemPropertySchema"
+);
+INSERT INTO strings(item, name, value) VALUES(
+    (SELECT rowid FROM items WHERE id = "d2d8558c8d050e571c4a52841fad99d7"),
+    "propertyName", "maximum"
+);
+INSERT INTO strings(item, name, value) VALUES(
+    (SELECT rowid FROM items WHERE id = "d2d8558c8d050e571c4a52841fad99d7"),
+    "valueType", "Real"
+);
@@ -0,0 +1,36 @@
+-- Items of type "ItemPropertySchema" have property "defaultValue" (text)
+INSERT INTO items(id, type, dateCreated, dateModified, dateServerModified, deleted) VALUES(
+    "20cf9c7223d5484d528856eb674ea68c",
+    "ItemPropertySchema", 0, 0, 0, 0
+);
+INSERT INTO strings(item, name, value) VALUES(
+    (SELECT rowid FROM items WHERE id = "20cf9c7223d5484d528856eb674ea68c"),
+    "itemType", "ItemPropertySchema"
+);
+INSERT INTO strings(item, name, value) VALUES(
+    (SELECT rowid FROM items WHERE id = "20cf9c7223d5484d528856eb674ea68c"),
+    "propertyName", "defaultValue"
+);
+INSERT INTO strings(item, name, value) VALUES(
+    (SELECT rowid FROM items WHERE id = "20cf9c7223d5484d528856eb674ea68c"),
+    "valueType", "Text"
+);
+
+
+-- Items of type "ItemPropertySchema" have property "formula" (text)
+INSERT INTO items(id, type, dateCreated, dateModified, dateServerModified, deleted) VALUES(
+    "42887e3c0621688570270964c5843534",
+    "ItemPropertySchema", 0, 0, 0, 0
+);
+INSERT INTO strings(item, name, value) VALUES(
+    (SELECT rowid FROM items WHERE id = "42887e3c0621688570270964c5843534"),
+    "itemType", "ItemPropertySchema"
+);
+INSERT INTO strings(item, name, value) VALUES(
+    (SELECT rowid FROM items WHERE id = "42887e3c0621688570270964c5843534"),
+    "propertyName", "formula"
+);
+INSERT INTO strings(item, name, value) VALUES(
+    (SELECT rowid FROM items WHERE id = "42887e3c0621688570270964c5843534"),
+    "valueType", "Text"
+);
@@ -0,0 +1,48 @@
+CREATE TABLE revisions (
+    rowid INTEGER PRIMARY KEY,
+    item INTEGER NOT NULL,
+    dateServerModified INTEGER /* datetime */ NOT NULL, /* of the item, before the change */
+    dateRecorded INTEGER /* datetime */ NOT NULL,
+    author TEXT NOT NULL, /* "client", or "plugin:" followed by the plugin name */
+    content TEXT NOT NULL, /* JSON of the item, before the change */
+    FOREIGN KEY (item) REFERENCES items(rowid)
+);
+CREATE INDEX idx_revisions_item on revisions(item);
+
+
+-- Items of type "ItemTypeSchema" have property "itemType" (text)
+INSERT INTO items(id, type, dateCreated, dateModified, dateServerModified, deleted) VALUES(
+    "5845607edf7cf53459403a9a2d1d2abf",
+    "ItemPropertySchema", 0, 0, 0, 0
+);
+INSERT INTO strings(item, name, value) VALUES(
+    (SELECT rowid FROM items WHERE id = "5845607edf7cf53459403a9a2d1d2abf"),
+    "itemType", "ItemTypeSchema"
+);
+INSERT INTO strings(item, name, value) VALUES(
+    (SELECT rowid FROM items WHERE id = "5845607edf7cf53459403a9a2d1d2abf"),
+    "propertyName", "itemType"
+);
+INSERT INTO strings(item, name, value) VALUES(
+    (SELECT rowid FROM items WHERE id = "5845607edf7cf53459403a9a2d1d2abf"),
+    "valueType", "Text"
+);
+
+
+-- Items of type "ItemTypeSchema" have property "keepRevisions" (bool)
+INSERT INTO items(id, type, dateCreated, dateModified, dateServerModified, deleted) VALUES(
+    "9a20dbd218bde5257594058e2a198262",
+    "ItemPropertySchema", 0, 0, 0, 0
+);
+INSERT INTO strings(item, name, value) VALUES(
+    (SELECT rowid FROM items WHERE id = "9a20dbd218bde5257594058e2a198262"),
+    "itemType", "ItemTypeSchema"
+);
+INSERT INTO strings(item, name, value) VALUES(
+    (SELECT rowid FROM items WHERE id = "9a20dbd218bde5257594058e2a198262"),
+    "propertyName", "keepRevisions"
+);
+INSERT INTO strings(item, name, value) VALUES(
+    (SELECT rowid FROM items WHERE id = "9a20dbd218bde5257594058e2a198262"),
+    "valueType", "Bool"
+);
@@ -0,0 +1,16 @@
+-- Every change of an item (including edges, which are items too) gets the next
+-- number of a sequence that only increases, in the order the changes are committed.
+-- Clients use it to sync changes, see the `changes_since` endpoint.
+ALTER TABLE items ADD COLUMN changeSeq INTEGER NOT NULL DEFAULT 0;
+-- Existing items get a sequence number in their order of creation
+UPDATE items SET changeSeq = rowid;
+CREATE INDEX idx_items_changeSeq on items(changeSeq);
+
+
+-- The last used sequence number. It is kept separately from the items,
+-- so that numbers are not reused after the latest item is purged.
+CREATE TABLE changeSequence (
+    rowid INTEGER PRIMARY KEY CHECK (rowid = 1),
+    value INTEGER NOT NULL
+);
+INSERT INTO changeSequence(rowid, value) VALUES(1, (SELECT IFNULL(MAX(changeSeq), 0) FROM items));
@@ -0,0 +1,11 @@
+-- When each property of an item was last modified, according to the `dateModified`
+-- of the update that wrote it. Used to resolve concurrent edits per property
+-- (last writer wins), see the `update_item` endpoint.
+-- The `deleted` flag of items is recorded here as well, under the name "deleted".
+CREATE TABLE propertyModified (
+    item INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    dateModified INTEGER /* datetime */ NOT NULL,
+    FOREIGN KEY (item) REFERENCES items(rowid)
+);
+CREATE UNIQUE INDEX idx_propertyModified_item_name on propertyModified(item, name);
@@ -59,14 +59,48 @@ pub struct CreateItem {
     pub fields: HashMap<String, Value>,
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GetItems {
+    pub ids: Vec<String>,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct UpdateItem {
     pub id: String,
+    /// Only update the item if its `dateServerModified` still has this value
+    #[serde(rename = "_ifDateServerModified")]
+    pub if_date_server_modified: Option<i64>,
+    /// Property values, or a `PropertyOperation` to apply to the current value
     #[serde(flatten)]
     pub fields: HashMap<String, Value>,
 }
 
+/// Operation on the current value of a property, performed on the server
+/// as part of an update, e.g. `"unreadCount": {"$increment": 1}`
+#[derive(Serialize, Deserialize, Debug)]
+pub enum PropertyOperation {
+    /// Add to an Integer or Real property, a missing value counts as 0
+    #[serde(rename = "$increment")]
+    Increment(serde_json::Number),
+    /// Subtract from an Integer or Real property, a missing value counts as 0
+    #[serde(rename = "$decrement")]
+    Decrement(serde_json::Number),
+    /// Append to a Text property, a missing value counts as an empty string
+    #[serde(rename = "$append")]
+    Append(String),
+    /// Set the property only if the item does not have it yet
+    #[serde(rename = "$setIfAbsent")]
+    SetIfAbsent(Value),
+    /// Keep the smaller of the current and the given Integer, Real or DateTime value
+    #[serde(rename = "$min")]
+    Min(serde_json::Number),
+    /// Keep the larger of the current and the given Integer, Real or DateTime value
+    #[serde(rename = "$max")]
+    Max(serde_json::Number),
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Bulk {
@@ -82,6 +116,71 @@ pub struct Bulk {
     pub search: Vec<Search>,
 }
 
+/// Payload of the `bulk` endpoint. Entries are parsed individually (into a `Bulk`),
+/// so that a malformed entry can be reported on its own in `BulkMode::Partial`.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct BulkEntries {
+    #[serde(default)]
+    pub mode: BulkMode,
+    #[serde(default)]
+    pub create_items: Vec<Value>,
+    #[serde(default)]
+    pub update_items: Vec<Value>,
+    #[serde(default)]
+    pub delete_items: Vec<Value>,
+    #[serde(default)]
+    pub create_edges: Vec<Value>,
+    #[serde(default)]
+    pub search: Vec<Value>,
+    /// Alternative to the fields above, operations performed in the given order.
+    /// Each one is a `BulkOperation`.
+    #[serde(default)]
+    pub operations: Vec<Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum BulkOperation {
+    CreateItem(CreateItem),
+    UpdateItem(UpdateItem),
+    DeleteItem(String),
+    CreateEdge(CreateEdge),
+    Search(Search),
+}
+
+/// Operations that a client queued, e.g. while offline, to be applied in order
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ApplyOperations {
+    pub client_id: String,
+    pub operations: Vec<LoggedOperation>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LoggedOperation {
+    /// Unique per client, an operation with the same id is only applied once
+    pub op_id: String,
+    #[serde(flatten)]
+    pub operation: BulkOperation,
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum BulkMode {
+    /// All entries succeed, or the whole request fails
+    Atomic,
+    /// Every entry succeeds or fails on its own
+    Partial,
+}
+
+impl Default for BulkMode {
+    fn default() -> Self {
+        BulkMode::Atomic
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct CreateEdge {
@@ -108,10 +207,10 @@ pub enum EdgeDirection {
     Incoming,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct EdgeSearch {}
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct Search {
     pub id: Option<String>,
@@ -133,6 +232,81 @@ pub struct Search {
     pub other_properties: HashMap<String, Value>,
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ChangesSince {
+    /// The `changeSeq` returned by the previous request, or 0 to get all items
+    #[serde(default)]
+    pub since: i64,
+    #[serde(default = "default_changes_limit")]
+    pub limit: u64,
+    /// Only return changes within the sync scope of this client, see `SetSyncScope`
+    pub client_id: Option<String>,
+}
+
+/// The part of the database that a client syncs with `changes_since`.
+/// Items are in scope if they match all of the given conditions.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncScope {
+    pub types: Option<Vec<String>>,
+    pub date_modified_after: Option<i64>,
+    pub date_modified_before: Option<i64>,
+    /// Like `date_modified_after`, relative to the time of each `changes_since` request
+    pub modified_within_days: Option<u32>,
+    /// Only items reachable from this item by following edges
+    pub root: Option<String>,
+    /// Maximum number of edges from `root`
+    #[serde(default = "default_sync_scope_max_depth")]
+    pub max_depth: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SetSyncScope {
+    pub client_id: String,
+    /// The new scope, or `None` to sync everything again
+    pub scope: Option<SyncScope>,
+}
+
+/// A client acknowledging that it has synced the changes up to `change_seq`
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AcknowledgeChanges {
+    /// Id of the client's `SyncClient` item, created on its first acknowledgement
+    pub client_id: String,
+    pub change_seq: i64,
+    /// Name of the client or device, to show to the user
+    pub name: Option<String>,
+}
+
+/// A remote Pod to replicate the changes of
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Replicate {
+    /// Base url of the remote Pod, e.g. `https://pod.example.com`
+    pub url: String,
+    pub owner_key: String,
+    pub database_key: String,
+}
+
+/// Which change events to receive, all of them by default
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Subscribe {
+    pub id: Option<String>,
+    pub _type: Option<String>,
+    pub operations: Option<Vec<ChangeOperation>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum ChangeOperation {
+    Create,
+    Update,
+    Delete,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct SendEmail {
@@ -141,7 +315,7 @@ pub struct SendEmail {
     pub body: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
 pub enum SortOrder {
     /// Ascending
     Asc,
@@ -159,6 +333,48 @@ fn default_api_sort_order() -> SortOrder {
 fn default_api_limit() -> u64 {
     u64::MAX
 }
+fn default_changes_limit() -> u64 {
+    1000
+}
+
+fn default_sync_scope_max_depth() -> u32 {
+    3
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GetRevisions {
+    pub id: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RestoreRevision {
+    pub id: String,
+    pub revision: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportSchema {
+    #[serde(default)]
+    pub format: SchemaExportFormat,
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum SchemaExportFormat {
+    /// JSON Schema document with item types in `$defs`
+    JsonSchema,
+    /// OpenAPI document with item types in `components.schemas`
+    OpenApi,
+}
+
+impl Default for SchemaExportFormat {
+    fn default() -> Self {
+        SchemaExportFormat::JsonSchema
+    }
+}
 
 //
 // Files API:
@@ -170,6 +386,30 @@ pub struct GetFile {
     pub sha256: String,
 }
 
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportArchive {
+    /// Also export deleted items, which are left out by default
+    #[serde(default)]
+    pub include_deleted: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FilesManifest {
+    /// The `changeSeq` returned by the previous request, or 0 to get all files
+    #[serde(default)]
+    pub since: i64,
+    #[serde(default = "default_changes_limit")]
+    pub limit: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FilesExist {
+    pub sha256s: Vec<String>,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,707 @@
+//
+// Portable archives of a Pod database: items, edges, Schema and files in a tar
+//
+
+use crate::api_model::CreateItem;
+use crate::api_model::ExportArchive;
+use crate::command_line_interface::CliOptions;
+use crate::constants;
+use crate::database_api;
+use crate::database_api::ItemBase;
+use crate::database_api::ItemKind;
+use crate::database_utils::add_item_edge_properties;
+use crate::database_utils::item_base_to_json;
+use crate::error::Error;
+use crate::error::Result;
+use crate::file_api;
+use crate::internal_api;
+use crate::plugin_auth_crypto::DatabaseKey;
+use crate::replication;
+use crate::replication::Outcome;
+use crate::schema::Schema;
+use crate::triggers;
+use crate::triggers::SchemaAdditionChange;
+use chrono::Utc;
+use log::info;
+use log::warn;
+use rusqlite::Connection;
+use rusqlite::Transaction as Tx;
+use serde::Serialize;
+use serde_json::json;
+use serde_json::Map;
+use serde_json::Value;
+use sha2::Digest;
+use sha2::Sha256;
+use std::collections::BTreeSet;
+use std::io::Read;
+use std::io::Write;
+use tar::Archive;
+use tar::Builder;
+use tar::Entry;
+use tar::Header;
+use warp::http::status::StatusCode;
+
+#[derive(Debug, Default, PartialEq)]
+pub struct ExportReport {
+    pub schema_items: usize,
+    pub items: usize,
+    pub edges: usize,
+    pub files: usize,
+}
+
+/// Write the database of `owner` into a tar archive:
+///
+/// * `manifest.json` with the archive format and version
+/// * `schema/*.jsonl`, `items/*.jsonl` and `edges/*.jsonl` with one item per line,
+///   split into parts of `ARCHIVE_LINES_PER_PART` lines
+///   (`schema/` has the `ItemPropertySchema` and `ItemTypeSchema` items,
+///   `edges/` the items that are the `self` of an edge, and `items/` all others)
+/// * `files/$sha256` with the decrypted content of each file
+///
+/// Items are read and written a part at a time, so the whole database is never held in memory.
+/// Each part and each file is read in its own short transaction, and written to `writer`
+/// after the transaction, so that a slow reader doesn't block changes to the database meanwhile.
+pub fn export<W: Write>(
+    conn: &Connection,
+    schema: &Schema,
+    owner: &str,
+    options: &ExportArchive,
+    writer: W,
+) -> Result<ExportReport> {
+    let mut report = ExportReport::default();
+    let mut builder = Builder::new(writer);
+    let manifest = json!({
+        "format": constants::ARCHIVE_FORMAT,
+        "version": constants::ARCHIVE_VERSION,
+        "podVersion": internal_api::get_project_version(),
+        "dateExported": Utc::now().timestamp_millis(),
+    });
+    append_file(
+        &mut builder,
+        "manifest.json",
+        &serde_json::to_vec(&manifest)?,
+    )?;
+    let mut sha256s = BTreeSet::new();
+    let parts = [
+        ("schema", ItemKind::Schema),
+        ("items", ItemKind::Other),
+        ("edges", ItemKind::Edge),
+    ];
+    for (dir, kind) in parts {
+        let mut lines = JsonLines::new(dir);
+        let mut after_rowid = 0;
+        loop {
+            let limit = constants::ARCHIVE_LINES_PER_PART;
+            let include_deleted = options.include_deleted;
+            let items = in_part(conn, None, |tx| {
+                let items = database_api::get_item_bases_of_kind(
+                    tx,
+                    kind,
+                    include_deleted,
+                    after_rowid,
+                    limit,
+                )?;
+                for item in &items {
+                    after_rowid = item.rowid;
+                }
+                items
+                    .into_iter()
+                    .map(|item| item_to_json(tx, schema, item))
+                    .collect::<Result<Vec<_>>>()
+            })?;
+            let count = items.len();
+            for item in items {
+                if let Some(sha256) = item.get("sha256").and_then(Value::as_str) {
+                    sha256s.insert(sha256.to_string());
+                }
+                lines.push(&mut builder, &item)?;
+            }
+            if count < limit {
+                break;
+            }
+        }
+        let total = lines.finish(&mut builder)?;
+        match kind {
+            ItemKind::Schema => report.schema_items = total,
+            ItemKind::Other => report.items = total,
+            ItemKind::Edge => report.edges = total,
+        }
+    }
+
+    for sha256 in sha256s {
+        if !file_api::file_exists_on_disk(owner, &sha256)? {
+            continue;
+        }
+        match in_part(conn, None, |tx| file_api::get_file(tx, owner, &sha256)) {
+            Ok(data) => {
+                append_file(&mut builder, &format!("files/{}", sha256), &data)?;
+                report.files += 1;
+            }
+            Err(err) => warn!("Skipping file {} in export of {}, {}", sha256, owner, err),
+        }
+    }
+    let mut writer = builder.into_inner().map_err(archive_error)?;
+    writer.flush().map_err(archive_error)?;
+    info!("Exported {:?} of owner {}", report, owner);
+    Ok(report)
+}
+
+#[derive(Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportReport {
+    pub dry_run: bool,
+    pub schema_items: usize,
+    pub items: usize,
+    pub edges: usize,
+    pub files: usize,
+    /// Items that are the same in the Pod, Schema that the Pod already has,
+    /// and item types that are not imported (see `replication::apply_item`)
+    pub skipped: usize,
+    /// Parts imported by an earlier, interrupted import, and files that the Pod already has
+    pub already_imported: usize,
+    pub conflicts: Vec<ImportConflict>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportConflict {
+    /// Path of the part or file in the archive
+    pub path: String,
+    pub id: Option<String>,
+    pub reason: String,
+}
+
+/// Import an archive written by `export`, in the order it was written:
+/// Schema first, then items and edges with their ids and dates, then the files,
+/// which `file_api::upload_file` encrypts again with new keys.
+///
+/// Each part of the archive is imported in its own transaction and remembered,
+/// so that importing the same archive again after an interruption resumes where it stopped.
+/// Items that exist with a different content are merged like replicated ones,
+/// and reported as conflicts.
+///
+/// With `dry_run`, everything happens in a single transaction that is rolled back,
+/// and failures are reported as conflicts instead of stopping the import.
+pub fn import<R: Read>(
+    conn: &Connection,
+    schema: Schema,
+    owner: &str,
+    cli: &CliOptions,
+    database_key: &DatabaseKey,
+    dry_run: bool,
+    reader: R,
+) -> Result<ImportReport> {
+    let dry_run_tx = if dry_run {
+        Some(conn.unchecked_transaction()?)
+    } else {
+        None
+    };
+    let mut archive = Archive::new(reader);
+    let mut entries = archive.entries().map_err(read_error)?;
+    let archive_id = match entries.next() {
+        Some(entry) => read_manifest(entry.map_err(read_error)?)?,
+        None => return Err(invalid_archive("Archive is empty".to_string())),
+    };
+    let mut importer = Importer {
+        archive_id,
+        schema,
+        owner,
+        cli,
+        database_key,
+        report: ImportReport {
+            dry_run,
+            ..Default::default()
+        },
+    };
+    for entry in entries {
+        let mut entry = entry.map_err(read_error)?;
+        let path = entry.path().map_err(read_error)?;
+        let path = path.to_string_lossy().to_string();
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data).map_err(read_error)?;
+        let is_part = ["schema/", "items/", "edges/"]
+            .iter()
+            .any(|dir| path.starts_with(dir));
+        if let Some(sha256) = path.strip_prefix("files/") {
+            importer.import_file(conn, dry_run_tx.as_ref(), &path, sha256, &data)?;
+        } else if is_part && path.ends_with(".jsonl") {
+            in_part(conn, dry_run_tx.as_ref(), |tx| {
+                importer.import_part(tx, &path, &data)
+            })?;
+        } else {
+            warn!("Skipping unknown entry {} of archive", path);
+        }
+    }
+    if let Some(tx) = dry_run_tx {
+        tx.rollback()?;
+    }
+    info!("Imported {:?} for owner {}", importer.report, owner);
+    Ok(importer.report)
+}
+
+/// Run `func` in its own committed transaction, or in the transaction of a dry run
+fn in_part<T, F: FnOnce(&Tx) -> Result<T>>(
+    conn: &Connection,
+    dry_run_tx: Option<&Tx>,
+    func: F,
+) -> Result<T> {
+    match dry_run_tx {
+        Some(tx) => func(tx),
+        None => {
+            let tx = conn.unchecked_transaction()?;
+            let result = func(&tx)?;
+            tx.commit()?;
+            Ok(result)
+        }
+    }
+}
+
+/// Check the manifest of an archive, returning its hash to identify the archive
+fn read_manifest<R: Read>(mut entry: Entry<R>) -> Result<String> {
+    let path = entry.path().map_err(read_error)?;
+    if path.to_str() != Some("manifest.json") {
+        return Err(invalid_archive(
+            "Archive does not start with manifest.json".to_string(),
+        ));
+    }
+    let mut data = Vec::new();
+    entry.read_to_end(&mut data).map_err(read_error)?;
+    let manifest: Value = serde_json::from_slice(&data)?;
+    if manifest["format"] != json!(constants::ARCHIVE_FORMAT) {
+        return Err(invalid_archive(format!(
+            "Archive format {} is not {}",
+            manifest["format"],
+            constants::ARCHIVE_FORMAT
+        )));
+    }
+    match manifest["version"].as_u64() {
+        Some(version) if version <= u64::from(constants::ARCHIVE_VERSION) => (),
+        _ => {
+            return Err(invalid_archive(format!(
+                "Archive version {} is not supported, the latest supported is {}",
+                manifest["version"],
+                constants::ARCHIVE_VERSION
+            )))
+        }
+    }
+    Ok(hex::encode(Sha256::digest(&data)))
+}
+
+struct Importer<'a> {
+    archive_id: String,
+    schema: Schema,
+    owner: &'a str,
+    cli: &'a CliOptions,
+    database_key: &'a DatabaseKey,
+    report: ImportReport,
+}
+
+impl Importer<'_> {
+    fn import_part(&mut self, tx: &Tx, path: &str, data: &[u8]) -> Result<()> {
+        if database_api::is_archive_part_imported(tx, &self.archive_id, path)? {
+            self.report.already_imported += 1;
+            return Ok(());
+        }
+        for line in data.split(|b| *b == b'\n').filter(|line| !line.is_empty()) {
+            let item: Map<String, Value> = serde_json::from_slice(line)
+                .map_err(|err| invalid_archive(format!("Invalid item in {}, {}", path, err)))?;
+            let id = item.get("id").and_then(Value::as_str).map(String::from);
+            let result = database_api::in_savepoint(tx, || self.import_item(tx, path, item));
+            match result {
+                Ok(()) => (),
+                Err(err) if self.report.dry_run => {
+                    // The in-memory Schema might have been changed by the rolled back item
+                    self.schema = database_api::get_schema(tx)?;
+                    self.conflict(path, id, err.msg)
+                }
+                Err(err) => {
+                    return Err(Error {
+                        code: err.code,
+                        msg: format!("Failed to import item {:?} of {}, {}", id, path, err.msg),
+                    })
+                }
+            }
+        }
+        let now = Utc::now().timestamp_millis();
+        database_api::insert_imported_archive_part(tx, &self.archive_id, path, now)
+    }
+
+    fn import_item(&mut self, tx: &Tx, path: &str, item: Map<String, Value>) -> Result<()> {
+        let id = match (item.get("id"), item.get("type")) {
+            (Some(Value::String(id)), Some(Value::String(_))) => id.to_string(),
+            _ => {
+                return Err(invalid_archive(format!(
+                    "Item without id or type in {}",
+                    path
+                )))
+            }
+        };
+        if path.starts_with("schema/") {
+            return self.import_schema_item(tx, &id, item);
+        }
+        let outcome = replication::apply_item(
+            tx,
+            &mut self.schema,
+            item,
+            constants::REVISION_AUTHOR_IMPORT,
+            self.owner,
+            self.cli,
+            self.database_key,
+        )?;
+        let count = if path.starts_with("edges/") {
+            &mut self.report.edges
+        } else {
+            &mut self.report.items
+        };
+        match outcome {
+            Outcome::Created => *count += 1,
+            Outcome::Updated => {
+                *count += 1;
+                let reason = "Exists with a different content, merged with the archive's version";
+                self.conflict(path, Some(id), reason.to_string());
+            }
+            Outcome::Skipped => self.report.skipped += 1,
+            Outcome::MissingEnds(_) => {
+                let reason = "Source or target of the edge does not exist, skipped";
+                self.conflict(path, Some(id), reason.to_string());
+            }
+            Outcome::Conflict(reason) => self.conflict(path, Some(id), reason),
+        }
+        Ok(())
+    }
+
+    /// Add the Schema of the archive, unless the Pod already has the same.
+    /// Schema that contradicts the Pod's fails in `triggers::add_item_as_schema_opt`.
+    fn import_schema_item(
+        &mut self,
+        tx: &Tx,
+        id: &str,
+        mut item: Map<String, Value>,
+    ) -> Result<()> {
+        if database_api::get_item_rowid(tx, id)?.is_some() {
+            self.report.skipped += 1;
+            return Ok(());
+        }
+        item.remove("dateServerModified");
+        let item: CreateItem = serde_json::from_value(Value::Object(item))?;
+        match triggers::add_item_as_schema_opt(&mut self.schema, &item)? {
+            SchemaAdditionChange::NewSchemaAdded => {
+                internal_api::create_item_tx(
+                    tx,
+                    &mut self.schema,
+                    item,
+                    self.owner,
+                    self.cli,
+                    self.database_key,
+                )?;
+                self.report.schema_items += 1;
+            }
+            SchemaAdditionChange::OldSchemaIgnored => self.report.skipped += 1,
+            SchemaAdditionChange::NotASchema => {
+                return Err(invalid_archive(format!("Item {} is not a Schema item", id)))
+            }
+        }
+        Ok(())
+    }
+
+    fn import_file(
+        &mut self,
+        conn: &Connection,
+        dry_run_tx: Option<&Tx>,
+        path: &str,
+        sha256: &str,
+        data: &[u8],
+    ) -> Result<()> {
+        if !file_api::is_sha256(sha256) {
+            return Err(invalid_archive(format!(
+                "Unexpected file {} in archive",
+                path
+            )));
+        }
+        if file_api::file_exists_on_disk(self.owner, sha256)? {
+            self.report.already_imported += 1;
+            return Ok(());
+        }
+        let dry_run = self.report.dry_run;
+        let result = in_part(conn, dry_run_tx, |tx| {
+            if !dry_run {
+                return file_api::upload_file(tx, self.owner, sha256, data);
+            }
+            file_api::validate_hash(sha256, data)?;
+            if database_api::search_strings(tx, "sha256", sha256)?.is_empty() {
+                return Err(Error {
+                    code: StatusCode::NOT_FOUND,
+                    msg: format!("Item with sha256 {} not found in database", sha256),
+                });
+            }
+            Ok(())
+        });
+        match result {
+            Ok(()) => self.report.files += 1,
+            Err(err) if dry_run => self.conflict(path, None, err.msg),
+            Err(err) => {
+                return Err(Error {
+                    code: err.code,
+                    msg: format!("Failed to import {}, {}", path, err.msg),
+                })
+            }
+        }
+        Ok(())
+    }
+
+    fn conflict(&mut self, path: &str, id: Option<String>, reason: String) {
+        self.report.conflicts.push(ImportConflict {
+            path: path.to_string(),
+            id,
+            reason,
+        });
+    }
+}
+
+fn item_to_json(tx: &Tx, schema: &Schema, item: ItemBase) -> Result<Value> {
+    let rowid = item.rowid;
+    let is_file = item._type == "File";
+    let mut object_map = item_base_to_json(tx, item, schema)?;
+    add_item_edge_properties(tx, &mut object_map, rowid)?;
+    if is_file {
+        for name in replication::LOCAL_FILE_PROPERTIES {
+            object_map.remove(*name);
+        }
+    }
+    Ok(Value::Object(object_map))
+}
+
+/// Lines of JSON, appended to the archive in parts as they fill up
+struct JsonLines {
+    dir: &'static str,
+    part: usize,
+    lines: Vec<u8>,
+    count: usize,
+    total: usize,
+}
+
+impl JsonLines {
+    fn new(dir: &'static str) -> JsonLines {
+        JsonLines {
+            dir,
+            part: 0,
+            lines: Vec::new(),
+            count: 0,
+            total: 0,
+        }
+    }
+
+    fn push<W: Write>(&mut self, builder: &mut Builder<W>, item: &Value) -> Result<()> {
+        serde_json::to_writer(&mut self.lines, item)?;
+        self.lines.push(b'\n');
+        self.count += 1;
+        self.total += 1;
+        if self.count >= constants::ARCHIVE_LINES_PER_PART {
+            self.flush(builder)?;
+        }
+        Ok(())
+    }
+
+    fn flush<W: Write>(&mut self, builder: &mut Builder<W>) -> Result<()> {
+        if self.count > 0 {
+            let path = format!("{}/{:06}.jsonl", self.dir, self.part);
+            append_file(builder, &path, &self.lines)?;
+            self.part += 1;
+            self.lines.clear();
+            self.count = 0;
+        }
+        Ok(())
+    }
+
+    /// Append the remaining lines, returning the number of lines in all parts
+    fn finish<W: Write>(mut self, builder: &mut Builder<W>) -> Result<usize> {
+        self.flush(builder)?;
+        Ok(self.total)
+    }
+}
+
+fn append_file<W: Write>(builder: &mut Builder<W>, path: &str, data: &[u8]) -> Result<()> {
+    let mut header = Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o600);
+    header.set_mtime(Utc::now().timestamp() as u64);
+    builder
+        .append_data(&mut header, path, data)
+        .map_err(archive_error)
+}
+
+fn archive_error(err: std::io::Error) -> Error {
+    Error {
+        code: StatusCode::INTERNAL_SERVER_ERROR,
+        msg: format!("Failed to write archive, {}", err),
+    }
+}
+
+fn read_error(err: std::io::Error) -> Error {
+    invalid_archive(format!("Failed to read archive, {}", err))
+}
+
+fn invalid_archive(msg: String) -> Error {
+    Error {
+        code: StatusCode::BAD_REQUEST,
+        msg,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api_model::CreateEdge;
+    use crate::command_line_interface;
+    use crate::database_api::tests::new_conn;
+    use crate::database_utils::get_item_from_rowid;
+    use crate::plugin_auth_crypto::DatabaseKey;
+    use std::collections::HashMap;
+    use std::io::Read;
+    use std::path::PathBuf;
+
+    const SHA256: &str = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+
+    fn create_items(tx: &Tx, schema: &mut Schema, owner: &str, items: Vec<Value>) -> Result<()> {
+        let cli = command_line_interface::tests::test_cli();
+        let database_key = DatabaseKey::from("".to_string()).unwrap();
+        for item in items {
+            let item = serde_json::from_value(item)?;
+            internal_api::create_item_tx(tx, schema, item, owner, &cli, &database_key)?;
+        }
+        Ok(())
+    }
+
+    /// Export a Pod with two items, an edge between them and a file
+    fn export_test_pod(owner: &str) -> Result<(ExportReport, Vec<u8>)> {
+        let mut conn = new_conn();
+        let tx = conn.transaction().unwrap();
+        let mut schema = database_api::get_schema(&tx)?;
+        let items = vec![
+            json!({"type": "ItemPropertySchema", "itemType": "Person", "propertyName": "nickname",
+                "valueType": "Text"}),
+            json!({"id": "note-revisions", "type": "ItemTypeSchema", "itemType": "Note",
+                "keepRevisions": true}),
+            json!({"id": "person", "type": "Person", "nickname": "Al"}),
+            json!({"id": "file-1", "type": "File", "sha256": SHA256}),
+            json!({"id": "person-gone", "type": "Person", "deleted": true}),
+        ];
+        create_items(&tx, &mut schema, owner, items)?;
+        let edge = CreateEdge {
+            source: "person".to_string(),
+            target: "file-1".to_string(),
+            name: "picture".to_string(),
+            self_id: None,
+        };
+        internal_api::create_edge(&tx, edge)?;
+        file_api::upload_file(&tx, owner, SHA256, b"hello")?;
+        tx.commit()?;
+        let mut archive = Vec::new();
+        let options = ExportArchive::default();
+        let report = export(&conn, &schema, owner, &options, &mut archive)?;
+        Ok((report, archive))
+    }
+
+    #[test]
+    fn test_export() -> Result<()> {
+        let owner = "testExportOwner";
+        let owner_dir = PathBuf::from(constants::FILES_DIR).join(owner);
+        std::fs::remove_dir_all(&owner_dir).ok();
+        let (report, archive) = export_test_pod(owner)?;
+        assert_eq!(report.items, 2);
+        assert_eq!(report.edges, 1);
+        assert_eq!(report.files, 1);
+        assert!(report.schema_items > 0);
+
+        let mut entries = HashMap::new();
+        let mut paths = Vec::new();
+        for entry in tar::Archive::new(archive.as_slice()).entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let path = entry.path().unwrap().to_string_lossy().to_string();
+            let mut data = Vec::new();
+            entry.read_to_end(&mut data).unwrap();
+            paths.push(path.clone());
+            entries.insert(path, data);
+        }
+        assert_eq!(paths[0], "manifest.json");
+        let manifest: Value = serde_json::from_slice(&entries["manifest.json"])?;
+        assert_eq!(manifest["format"], json!(constants::ARCHIVE_FORMAT));
+        assert_eq!(manifest["version"], json!(constants::ARCHIVE_VERSION));
+        let items: Vec<Value> = entries["items/000000.jsonl"]
+            .split(|b| *b == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| serde_json::from_slice(line).unwrap())
+            .collect();
+        let ids: Vec<_> = items.iter().map(|item| item["id"].clone()).collect();
+        assert_eq!(ids, vec![json!("person"), json!("file-1")]);
+        assert_eq!(items[1].get("key"), None);
+        assert_eq!(items[1].get("nonce"), None);
+        assert!(entries.contains_key("edges/000000.jsonl"));
+        let schema = String::from_utf8(entries["schema/000000.jsonl"].clone()).unwrap();
+        assert!(schema.contains("\"note-revisions\""));
+        assert_eq!(entries[&format!("files/{}", SHA256)], b"hello");
+        std::fs::remove_dir_all(owner_dir).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn test_import() -> Result<()> {
+        let cli = command_line_interface::tests::test_cli();
+        let database_key = DatabaseKey::from("".to_string()).unwrap();
+        let (exporter, owner) = ("testImportFromOwner", "testImportOwner");
+        for owner in [exporter, owner] {
+            std::fs::remove_dir_all(PathBuf::from(constants::FILES_DIR).join(owner)).ok();
+        }
+        let (_, archive) = export_test_pod(exporter)?;
+        let import = |conn: &Connection, dry_run: bool| {
+            let schema = database_api::get_schema(&conn.unchecked_transaction()?)?;
+            let archive = archive.as_slice();
+            import(conn, schema, owner, &cli, &database_key, dry_run, archive)
+        };
+
+        // A Pod where the archive conflicts with the Schema and an item
+        let mut conn = new_conn();
+        let tx = conn.transaction().unwrap();
+        let mut schema = database_api::get_schema(&tx)?;
+        let items = vec![
+            json!({"type": "ItemPropertySchema", "itemType": "Person", "propertyName": "nickname",
+                "valueType": "Integer"}),
+            json!({"id": "person", "type": "Person"}),
+        ];
+        create_items(&tx, &mut schema, owner, items)?;
+        tx.commit().unwrap();
+        let report = import(&conn, true)?;
+        let conflicts: Vec<_> = report.conflicts.iter().map(|c| c.path.as_str()).collect();
+        assert_eq!(conflicts, vec!["schema/000000.jsonl", "items/000000.jsonl"]);
+        assert_eq!(report.conflicts[1].id.as_deref(), Some("person"));
+        assert_eq!((report.items, report.edges, report.files), (1, 1, 1));
+        // Nothing was changed
+        let tx = conn.transaction().unwrap();
+        assert_eq!(database_api::get_item_rowid(&tx, "file-1")?, None);
+        drop(tx);
+        assert!(import(&conn, false).is_err());
+
+        let conn = new_conn();
+        let report = import(&conn, true)?;
+        assert_eq!(report.conflicts, vec![]);
+        let report = import(&conn, false)?;
+        assert_eq!(report.conflicts, vec![]);
+        assert_eq!(report.schema_items, 2);
+        assert_eq!((report.items, report.edges, report.files), (2, 1, 1));
+        let tx = conn.unchecked_transaction()?;
+        assert_eq!(file_api::get_file(&tx, owner, SHA256)?, b"hello");
+        let person = database_api::get_item_rowid(&tx, "person")?.unwrap();
+        let person = get_item_from_rowid(&tx, &database_api::get_schema(&tx)?, person)?;
+        assert_eq!(person["nickname"], json!("Al"));
+        drop(tx);
+
+        // Importing again, e.g. after an interruption, skips what was imported
+        let report = import(&conn, false)?;
+        assert_eq!((report.items, report.edges, report.files), (0, 0, 0));
+        assert_eq!(report.already_imported, 4);
+        for owner in [exporter, owner] {
+            std::fs::remove_dir_all(PathBuf::from(constants::FILES_DIR).join(owner)).ok();
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,168 @@
+//
+// Notifications of item changes to subscribed clients and plugins
+//
+
+use crate::api_model::ChangeOperation;
+use crate::api_model::Subscribe;
+use crate::constants;
+use crate::database_api;
+use crate::database_api::Rowid;
+use crate::error::Result;
+use crate::global_static::CHANGE_EVENTS;
+use log::error;
+use rusqlite::Transaction as Tx;
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ChangeEvent {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub _type: String,
+    pub operation: ChangeOperation,
+    pub change_seq: i64,
+}
+
+/// State of the database at the start of a transaction, to find out what the transaction changed
+pub struct ChangeTracker {
+    last_change_seq: i64,
+    max_rowid: Rowid,
+}
+
+/// Start tracking the changes of a transaction, if anyone is subscribed to the owner's changes
+pub fn start_tracking(owner: &str, tx: &Tx) -> Result<Option<ChangeTracker>> {
+    let has_subscribers = match CHANGE_EVENTS.lock() {
+        Ok(channels) => channels
+            .get(owner)
+            .map(|sender| sender.receiver_count() > 0)
+            .unwrap_or(false),
+        Err(err) => {
+            error!("Failed to lock change events, {}", err);
+            false
+        }
+    };
+    if !has_subscribers {
+        return Ok(None);
+    }
+    Ok(Some(ChangeTracker {
+        last_change_seq: database_api::get_last_change_seq(tx)?,
+        max_rowid: database_api::get_max_item_rowid(tx)?,
+    }))
+}
+
+/// Get the changes made since tracking started, to be published once the transaction commits.
+/// An item changed several times is only reported once, with its last operation.
+pub fn collect(tx: &Tx, tracker: &ChangeTracker) -> Result<Vec<ChangeEvent>> {
+    let changes = database_api::get_changes_since(tx, tracker.last_change_seq, u64::MAX)?;
+    let events = changes
+        .into_iter()
+        .map(|(change_seq, item)| {
+            let operation = if item.rowid > tracker.max_rowid {
+                ChangeOperation::Create
+            } else if item.deleted {
+                ChangeOperation::Delete
+            } else {
+                ChangeOperation::Update
+            };
+            ChangeEvent {
+                id: item.id,
+                _type: item._type,
+                operation,
+                change_seq,
+            }
+        })
+        .collect();
+    Ok(events)
+}
+
+pub fn publish(owner: &str, events: Vec<ChangeEvent>) {
+    if events.is_empty() {
+        return;
+    }
+    let mut channels = match CHANGE_EVENTS.lock() {
+        Ok(channels) => channels,
+        Err(err) => {
+            error!("Failed to lock change events, {}", err);
+            return;
+        }
+    };
+    if let Some(sender) = channels.get(owner) {
+        for event in events {
+            if sender.send(event).is_err() {
+                // All subscribers are gone
+                channels.remove(owner);
+                return;
+            }
+        }
+    }
+}
+
+pub fn subscribe(owner: &str) -> Result<broadcast::Receiver<ChangeEvent>> {
+    let mut channels = CHANGE_EVENTS.lock()?;
+    let sender = channels
+        .entry(owner.to_string())
+        .or_insert_with(|| broadcast::channel(constants::CHANGE_EVENTS_CAPACITY).0);
+    Ok(sender.subscribe())
+}
+
+pub fn matches(subscription: &Subscribe, event: &ChangeEvent) -> bool {
+    let id = &subscription.id;
+    let _type = &subscription._type;
+    let operations = &subscription.operations;
+    id.as_ref().map(|id| id == &event.id).unwrap_or(true)
+        && _type.as_ref().map(|t| t == &event._type).unwrap_or(true)
+        && operations
+            .as_ref()
+            .map(|ops| ops.contains(&event.operation))
+            .unwrap_or(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api_model::CreateItem;
+    use crate::command_line_interface;
+    use crate::constants::REVISION_AUTHOR_CLIENT;
+    use crate::database_api::tests::new_conn;
+    use crate::internal_api;
+    use crate::plugin_auth_crypto::DatabaseKey;
+    use serde_json::json;
+
+    #[test]
+    fn test_change_events() {
+        let owner = "change-events-test-owner";
+        let mut conn = new_conn();
+        let cli = command_line_interface::tests::test_cli();
+        let db_key = DatabaseKey::from("".to_string()).unwrap();
+        let tx = conn.transaction().unwrap();
+        let mut schema = database_api::get_schema(&tx).unwrap();
+        let item: CreateItem =
+            serde_json::from_value(json!({"id": "existing", "type": "Person"})).unwrap();
+        internal_api::create_item_tx(&tx, &mut schema, item, "", &cli, &db_key).unwrap();
+        assert!(start_tracking(owner, &tx).unwrap().is_none());
+
+        let mut receiver = subscribe(owner).unwrap();
+        let tracker = start_tracking(owner, &tx).unwrap().unwrap();
+        let item: CreateItem =
+            serde_json::from_value(json!({"id": "new-item", "type": "Person"})).unwrap();
+        internal_api::create_item_tx(&tx, &mut schema, item, "", &cli, &db_key).unwrap();
+        internal_api::delete_item_tx(&tx, &mut schema, "existing", REVISION_AUTHOR_CLIENT).unwrap();
+        publish(owner, collect(&tx, &tracker).unwrap());
+
+        let created = receiver.try_recv().unwrap();
+        assert_eq!(created.id, "new-item");
+        assert_eq!(created.operation, ChangeOperation::Create);
+        let deleted = receiver.try_recv().unwrap();
+        assert_eq!(deleted.id, "existing");
+        assert_eq!(deleted.operation, ChangeOperation::Delete);
+        assert!(deleted.change_seq > created.change_seq);
+
+        let subscription = Subscribe {
+            operations: Some(vec![ChangeOperation::Delete]),
+            ..Default::default()
+        };
+        assert!(!matches(&subscription, &created));
+        assert!(matches(&subscription, &deleted));
+    }
+}
@@ -128,6 +128,44 @@ pub struct CliOptions {
     #[structopt(long, name = "NETWORK_INTERFACE", env = "POD_INSECURE_NON_TLS")]
     pub insecure_non_tls: Option<IpAddr>,
 
+    /// Allow replicating from Pods on plain http urls (see `replicate` endpoint).
+    /// WARNING: the database key of the other Pod is then sent unencrypted,
+    /// only use this on a trusted network, e.g. for development.
+    #[structopt(long, env = "POD_INSECURE_REPLICATION_HTTP")]
+    pub insecure_replication_http: bool,
+
+    /// JSON or YAML file with a bundle of Schema items to apply to every database
+    /// when it is first opened, in addition to the built-in Schema.
+    /// The file has the same format as the `bulk` endpoint payload
+    /// with `createItems` of type "ItemPropertySchema" only,
+    /// see `docs/HTTP_API.md#schema-bundle-file`.
+    ///
+    /// Already existing Schema is left untouched, and conflicting Schema items are reported
+    /// in the logs, so it is safe to keep this option on between restarts.
+    #[structopt(long, name = "SCHEMA_FILE", env = "POD_SCHEMA_FILE")]
+    pub schema_file: Option<String>,
+
+    /// Permanently remove items that were deleted more than this number of days ago,
+    /// together with their properties, edges and files (see `purge_item` endpoint).
+    ///
+    /// Databases are encrypted, so garbage collection of a database runs in the background
+    /// after its owner makes a request, at most once an hour.
+    /// If not specified, deleted items are kept forever.
+    #[structopt(long, name = "DAYS", env = "POD_PURGE_DELETED_AFTER_DAYS")]
+    pub purge_deleted_after_days: Option<u32>,
+
+    /// Forget the ids of client operations that were applied more than this number of days ago
+    /// (see `apply_operations` endpoint), after which retried uploads would apply them again.
+    ///
+    /// This happens in the same background garbage collection as purging deleted items.
+    /// If not specified, applied operations are remembered forever.
+    #[structopt(
+        long,
+        name = "OPERATION_DAYS",
+        env = "POD_FORGET_APPLIED_OPERATIONS_AFTER_DAYS"
+    )]
+    pub forget_applied_operations_after_days: Option<u32>,
+
     /// Run server as a "SharedServer". See `/docs/SharedServer.md` documentation
     /// for details on what it is, and how it works.
     #[structopt(long, env)]
@@ -148,6 +186,84 @@ pub struct CliOptions {
     /// SMTP relay server password (advanced option).
     #[structopt(long, env)]
     pub email_smtp_password: Option<String>,
+
+    /// Run a one-off command instead of the server.
+    #[structopt(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(StructOpt, Clone)]
+pub enum Command {
+    /// Export the items, Schema and files of an owner into a portable tar archive,
+    /// see `docs/HTTP_API.md#post-v4owner_keyexport`.
+    Export {
+        /// Owner to export (hex-encoded hash of the owner's public key).
+        #[structopt(long)]
+        owner: String,
+
+        /// Database key of the owner.
+        #[structopt(long, env = "POD_DATABASE_KEY", hide_env_values = true)]
+        database_key: String,
+
+        /// File to write the archive to.
+        #[structopt(long, name = "FILE")]
+        output: String,
+
+        /// Also export deleted items.
+        #[structopt(long)]
+        include_deleted: bool,
+    },
+
+    /// Import an archive written by `export` into the database of an owner,
+    /// resuming a previous import of the same archive that was interrupted.
+    /// The Pod should not be running for that owner meanwhile.
+    Import {
+        /// Owner to import into (hex-encoded hash of the owner's public key).
+        #[structopt(long)]
+        owner: String,
+
+        /// Database key of the owner.
+        #[structopt(long, env = "POD_DATABASE_KEY", hide_env_values = true)]
+        database_key: String,
+
+        /// Archive to import.
+        #[structopt(long, name = "FILE")]
+        input: String,
+
+        /// Only report what would be imported and the conflicts, without changing anything.
+        #[structopt(long)]
+        dry_run: bool,
+    },
+}
+
+/// Debug output of the configuration is logged, and must not contain the database key
+impl std::fmt::Debug for Command {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Command::Export {
+                owner,
+                output,
+                include_deleted,
+                ..
+            } => f
+                .debug_struct("Export")
+                .field("owner", owner)
+                .field("output", output)
+                .field("include_deleted", include_deleted)
+                .finish(),
+            Command::Import {
+                owner,
+                input,
+                dry_run,
+                ..
+            } => f
+                .debug_struct("Import")
+                .field("owner", owner)
+                .field("input", input)
+                .field("dry_run", dry_run)
+                .finish(),
+        }
+    }
 }
 
 fn parse_key_val<T, U>(s: &str) -> Result<(T, U), Box<dyn Error>>
@@ -192,11 +308,16 @@ pub mod tests {
             tls_priv_key: "".to_string(),
             non_tls: true,
             insecure_non_tls: Some(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0))),
+            insecure_replication_http: false,
+            schema_file: None,
+            purge_deleted_after_days: None,
+            forget_applied_operations_after_days: None,
             shared_server: false,
             email_smtp_relay: None,
             email_smtp_port: 465,
             email_smtp_user: None,
             email_smtp_password: None,
+            command: None,
         }
     }
 }
@@ -8,8 +8,47 @@ pub const FILES_DIR: &str = "./data/files";
 /// (in future, the files should also be s3-uploaded).
 pub const FILES_FINAL_SUBDIR: &str = "final";
 
+/// Minimum time between two garbage collections of the same database
+pub const GARBAGE_COLLECTION_INTERVAL_SECONDS: u64 = 60 * 60;
+/// Number of deleted items purged in each transaction of a garbage collection
+pub const GARBAGE_COLLECTION_BATCH_SIZE: u64 = 1000;
+
+/// Number of items of a streamed search that are read in each transaction,
+/// see `internal_api::SearchBatches`
+pub const SEARCH_STREAM_BATCH_SIZE: u64 = 1000;
+
+/// Number of change events buffered for each owner's subscribers.
+/// Subscribers that fall behind more than that are told to re-sync.
+pub const CHANGE_EVENTS_CAPACITY: usize = 1024;
+
 pub const PLUGIN_EMAIL_SUBJECT_PREFIX: &str = "Memri plugin message: ";
 pub const PLUGIN_EMAIL_FOOTER: &str =
     "This is an automated message from a Memri plugin, do not reply.
 
 ";
+
+/// Number of changes requested at once from a Pod that is replicated from
+pub const REPLICATION_PAGE_SIZE: u64 = 1000;
+/// Maximum time to connect to a Pod that is replicated from, including the TLS handshake
+pub const REPLICATION_CONNECT_TIMEOUT_SECONDS: u64 = 30;
+/// Maximum time for a Pod that is replicated from to respond with a page of changes
+pub const REPLICATION_RESPONSE_TIMEOUT_SECONDS: u64 = 300;
+/// Item types that are specific to a Pod, and are not replicated to other Pods
+pub const REPLICATION_EXCLUDED_ITEM_TYPES: &[&str] = &["PluginRun", SYNC_CLIENT_ITEM_TYPE];
+
+/// Author of changes made with ClientAuth, as recorded in item revisions
+pub const REVISION_AUTHOR_CLIENT: &str = "client";
+/// Prefix of the author of changes made with PluginAuth, followed by the plugin name
+pub const REVISION_AUTHOR_PLUGIN_PREFIX: &str = "plugin:";
+/// Prefix of the author of changes replicated from another Pod, followed by the Pod's url
+pub const REVISION_AUTHOR_REPLICATION_PREFIX: &str = "replication:";
+/// Author of changes made by importing an archive
+pub const REVISION_AUTHOR_IMPORT: &str = "import";
+
+/// Format and version of archives created by `export`
+pub const ARCHIVE_FORMAT: &str = "memri-pod-archive";
+pub const ARCHIVE_VERSION: u32 = 1;
+/// Maximum number of items in each `.jsonl` file of an archive
+pub const ARCHIVE_LINES_PER_PART: usize = 1000;
+
+pub const SYNC_CLIENT_ITEM_TYPE: &str = "SyncClient";
@@ -2,6 +2,9 @@ use crate::api_model::SortOrder;
 use crate::error::Error;
 use crate::error::ErrorContext;
 use crate::error::Result;
+use crate::schema;
+use crate::schema::Formula;
+use crate::schema::PropertyRules;
 use crate::schema::Schema;
 use crate::schema::SchemaPropertyType;
 use field_count::FieldCount;
@@ -12,12 +15,15 @@ use rusqlite::Row;
 use rusqlite::Rows;
 use rusqlite::Transaction as Tx;
 use std::collections::HashMap;
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::sync::Arc;
 use warp::http::StatusCode;
 
 pub type Rowid = i64;
 pub type DbTime = i64;
 
-#[derive(FieldCount)]
+#[derive(FieldCount, Clone)]
 pub struct ItemBase {
     pub rowid: Rowid,
     pub id: String,
@@ -46,8 +52,9 @@ pub fn insert_item_base(
             dateCreated, \
             dateModified, \
             dateServerModified, \
-            deleted \
-        ) VALUES (?, ?, ?, ?, ?, ?);",
+            deleted, \
+            changeSeq \
+        ) VALUES (?, ?, ?, ?, ?, ?, ?);",
         )
         .context_str("Failed to prepare/compile INSERT statement")?;
     stmt.insert(params![
@@ -57,6 +64,7 @@ pub fn insert_item_base(
         date_modified_millis,
         date_server_modified_millis,
         deleted,
+        next_change_seq(tx)?,
     ])
     .context_str("Failed to execute insert_item with parameters")
 }
@@ -110,8 +118,22 @@ fn parse_item_base(row: &Row) -> Result<ItemBase> {
     })
 }
 
-#[allow(clippy::comparison_chain)]
 pub fn search_items(tx: &Tx, query: &DatabaseSearch) -> Result<Vec<ItemBase>> {
+    let mut result = Vec::new();
+    search_items_with(tx, query, |item| {
+        result.push(item);
+        Ok(())
+    })?;
+    Ok(result)
+}
+
+/// Search items, passing each of them to `on_item` as soon as it is read from the database
+#[allow(clippy::comparison_chain)]
+pub fn search_items_with<F: FnMut(ItemBase) -> Result<()>>(
+    tx: &Tx,
+    query: &DatabaseSearch,
+    mut on_item: F,
+) -> Result<()> {
     let mut sql_query = "\
         SELECT \
             rowid, \
@@ -169,7 +191,6 @@ pub fn search_items(tx: &Tx, query: &DatabaseSearch) -> Result<Vec<ItemBase>> {
     }
     let mut rows: Rows = stmt.raw_query();
 
-    let mut result = Vec::new();
     let mut num_left = query._limit;
     let mut last_date: Option<DbTime> = None;
     while let Some(row) = rows.next()? {
@@ -186,11 +207,367 @@ pub fn search_items(tx: &Tx, query: &DatabaseSearch) -> Result<Vec<ItemBase>> {
         } else {
             break;
         }
-        result.push(item);
+        on_item(item)?;
+    }
+    Ok(())
+}
+
+/// Older SQLite versions limit the number of parameters of a statement to 999
+const MAX_SQL_PARAMS_PER_QUERY: usize = 500;
+
+/// Get the items with the given ids, with one query per (large) chunk of ids.
+/// Ids that do not exist are absent from the result.
+pub fn get_item_bases_by_ids(tx: &Tx, ids: &[String]) -> Result<HashMap<String, ItemBase>> {
+    let mut result = HashMap::new();
+    for chunk in ids.chunks(MAX_SQL_PARAMS_PER_QUERY) {
+        let placeholders = vec!["?"; chunk.len()].join(", ");
+        let sql_query = format!(
+            "SELECT rowid, id, type, dateCreated, dateModified, dateServerModified, deleted \
+            FROM items WHERE id IN ({});",
+            placeholders
+        );
+        // Not cached, every chunk size is a different statement
+        let mut stmt = tx.prepare(&sql_query)?;
+        let mut rows = stmt.query(rusqlite::params_from_iter(chunk))?;
+        while let Some(row) = rows.next()? {
+            let item = parse_item_base(row)?;
+            result.insert(item.id.clone(), item);
+        }
+    }
+    Ok(result)
+}
+
+/// Items changed after the change sequence number `since`, in the order of their changes.
+/// Returns at most `limit` items with their change sequence number.
+pub fn get_changes_since(tx: &Tx, since: i64, limit: u64) -> Result<Vec<(i64, ItemBase)>> {
+    let mut stmt = tx.prepare_cached(
+        "SELECT rowid, id, type, dateCreated, dateModified, dateServerModified, deleted, \
+        changeSeq FROM items WHERE changeSeq > ? ORDER BY changeSeq LIMIT ?;",
+    )?;
+    let limit = i64::try_from(limit).unwrap_or(i64::MAX);
+    let mut rows = stmt.query(params![since, limit])?;
+    let mut result = Vec::new();
+    while let Some(row) = rows.next()? {
+        result.push((row.get(7)?, parse_item_base(row)?));
+    }
+    Ok(result)
+}
+
+/// Ids of the items purged after the change sequence number `since`, in the order of purging.
+/// Returns at most `limit` ids with the change sequence number of their purging.
+pub fn get_purged_items_since(tx: &Tx, since: i64, limit: u64) -> Result<Vec<(i64, String)>> {
+    let mut stmt = tx.prepare_cached(
+        "SELECT changeSeq, id FROM purgedItems WHERE changeSeq > ? ORDER BY changeSeq LIMIT ?;",
+    )?;
+    let limit = i64::try_from(limit).unwrap_or(i64::MAX);
+    let mut rows = stmt.query(params![since, limit])?;
+    let mut result = Vec::new();
+    while let Some(row) = rows.next()? {
+        result.push((row.get(0)?, row.get(1)?));
+    }
+    Ok(result)
+}
+
+/// A `File` item that was changed, see `get_file_changes_since`
+pub struct FileChange {
+    pub change_seq: i64,
+    pub id: String,
+    pub deleted: bool,
+    pub sha256: Option<String>,
+}
+
+/// Like `get_changes_since`, for items of type `File` only
+pub fn get_file_changes_since(tx: &Tx, since: i64, limit: u64) -> Result<Vec<FileChange>> {
+    let mut stmt = tx.prepare_cached(
+        "SELECT items.changeSeq, items.id, items.deleted, strings.value FROM items \
+        LEFT JOIN strings ON strings.item = items.rowid AND strings.name = 'sha256' \
+        WHERE items.type = 'File' AND items.changeSeq > ? ORDER BY items.changeSeq LIMIT ?;",
+    )?;
+    let limit = i64::try_from(limit).unwrap_or(i64::MAX);
+    let mut rows = stmt.query(params![since, limit])?;
+    let mut result = Vec::new();
+    while let Some(row) = rows.next()? {
+        result.push(FileChange {
+            change_seq: row.get(0)?,
+            id: row.get(1)?,
+            deleted: row.get(2)?,
+            sha256: row.get(3)?,
+        });
+    }
+    Ok(result)
+}
+
+/// The largest rowid of the items, any item created afterwards has a larger one
+pub fn get_max_item_rowid(tx: &Tx) -> Result<Rowid> {
+    let mut stmt = tx.prepare_cached("SELECT IFNULL(MAX(rowid), 0) FROM items;")?;
+    Ok(stmt.query_row([], |row| row.get(0))?)
+}
+
+/// The last number of the change sequence that was used
+pub fn get_last_change_seq(tx: &Tx) -> Result<i64> {
+    let mut stmt = tx.prepare_cached("SELECT value FROM changeSequence WHERE rowid = 1;")?;
+    Ok(stmt.query_row([], |row| row.get(0))?)
+}
+
+/// The oldest change acknowledged by all registered (not deleted) `SyncClient` items,
+/// `None` if there are no such clients
+pub fn get_min_acknowledged_change_seq(tx: &Tx) -> Result<Option<i64>> {
+    let mut stmt = tx.prepare_cached(
+        "SELECT MIN(integers.value) FROM items \
+        INNER JOIN integers ON integers.item = items.rowid AND integers.name = 'ackedChangeSeq' \
+        WHERE items.type = 'SyncClient' AND items.deleted = 0;",
+    )?;
+    Ok(stmt.query_row([], |row| row.get(0))?)
+}
+
+/// The last change of a replicated Pod that was applied, 0 if none
+pub fn get_replication_checkpoint(tx: &Tx, url: &str, owner_key: &str) -> Result<i64> {
+    let mut stmt = tx
+        .prepare_cached("SELECT changeSeq FROM replicationPeers WHERE url = ? AND ownerKey = ?;")?;
+    let mut rows = stmt.query(params![url, owner_key])?;
+    match rows.next()? {
+        Some(row) => Ok(row.get(0)?),
+        None => Ok(0),
+    }
+}
+
+pub fn set_replication_checkpoint(
+    tx: &Tx,
+    url: &str,
+    owner_key: &str,
+    change_seq: i64,
+    date: DbTime,
+) -> Result<()> {
+    let mut stmt = tx.prepare_cached(
+        "INSERT OR REPLACE INTO replicationPeers(url, ownerKey, changeSeq, dateReplicated) \
+        VALUES(?, ?, ?, ?);",
+    )?;
+    stmt.execute(params![url, owner_key, change_seq, date])?;
+    Ok(())
+}
+
+/// Items of the given types, or all items if `types` is `None`,
+/// with a `dateModified` from `date_modified_gte` (inclusive) to `date_modified_lt` (exclusive)
+pub fn get_item_bases_of_types(
+    tx: &Tx,
+    types: Option<&[String]>,
+    date_modified_gte: Option<DbTime>,
+    date_modified_lt: Option<DbTime>,
+) -> Result<Vec<ItemBase>> {
+    let mut sql = "SELECT rowid, id, type, dateCreated, dateModified, dateServerModified, deleted \
+        FROM items WHERE "
+        .to_string();
+    let mut params_vec: Vec<ToSqlOutput> = Vec::new();
+    if let Some(types) = types {
+        if types.is_empty() {
+            return Ok(Vec::new());
+        }
+        let placeholders = vec!["?"; types.len()].join(", ");
+        sql.push_str(&format!("type IN ({}) AND ", placeholders));
+        params_vec.extend(types.iter().map(|t| t.as_str().into()));
+    }
+    if let Some(dt) = date_modified_gte {
+        add_sql_param(&mut sql, "dateModified", &Comparison::GreaterOrEquals);
+        params_vec.push(dt.into());
+    }
+    if let Some(dt) = date_modified_lt {
+        add_sql_param(&mut sql, "dateModified", &Comparison::LessThan);
+        params_vec.push(dt.into());
+    }
+    sql.push_str("1;"); // older sqlite versions do not support `true`
+    let mut stmt = tx.prepare(&sql)?;
+    let mut rows = stmt.query(rusqlite::params_from_iter(params_vec))?;
+    let mut result = Vec::new();
+    while let Some(row) = rows.next()? {
+        result.push(parse_item_base(row)?);
     }
     Ok(result)
 }
 
+/// Edges whose source and target are both among `items`, as `(self, id of self)`.
+/// The items are put in a temporary table, to find the edges with a single join.
+pub fn get_edges_between(tx: &Tx, items: &[Rowid]) -> Result<Vec<(Rowid, String)>> {
+    tx.execute_batch(
+        "CREATE TEMP TABLE IF NOT EXISTS edgeEnds (item INTEGER PRIMARY KEY); \
+        DELETE FROM edgeEnds;",
+    )?;
+    let mut stmt = tx.prepare_cached("INSERT OR IGNORE INTO edgeEnds(item) VALUES(?);")?;
+    for item in items {
+        stmt.execute(params![item])?;
+    }
+    let mut stmt = tx.prepare_cached(
+        "SELECT edges.self, items.id FROM edges \
+        INNER JOIN edgeEnds AS sources ON sources.item = edges.source \
+        INNER JOIN edgeEnds AS targets ON targets.item = edges.target \
+        INNER JOIN items ON items.rowid = edges.self;",
+    )?;
+    let mut rows = stmt.query([])?;
+    let mut result = Vec::new();
+    while let Some(row) = rows.next()? {
+        result.push((row.get(0)?, row.get(1)?));
+    }
+    tx.execute_batch("DELETE FROM edgeEnds;")?;
+    Ok(result)
+}
+
+/// Kinds of items, as written into the parts of an archive (see `archive::export`)
+#[derive(Debug, Clone, Copy)]
+pub enum ItemKind {
+    /// `ItemPropertySchema` and `ItemTypeSchema` items
+    Schema,
+    /// Items that are the `self` of an edge
+    Edge,
+    /// All other items
+    Other,
+}
+
+/// At most `limit` items of the given kind with a rowid greater than `after_rowid`,
+/// in the order of their rowids, to read all of them page by page
+pub fn get_item_bases_of_kind(
+    tx: &Tx,
+    kind: ItemKind,
+    include_deleted: bool,
+    after_rowid: Rowid,
+    limit: usize,
+) -> Result<Vec<ItemBase>> {
+    let kind_condition = match kind {
+        ItemKind::Schema => "type IN ('ItemPropertySchema', 'ItemTypeSchema')",
+        ItemKind::Edge => "rowid IN (SELECT self FROM edges)",
+        ItemKind::Other => {
+            "type NOT IN ('ItemPropertySchema', 'ItemTypeSchema') \
+            AND rowid NOT IN (SELECT self FROM edges)"
+        }
+    };
+    let deleted_condition = if include_deleted {
+        ""
+    } else {
+        "AND deleted = 0 "
+    };
+    let sql = format!(
+        "SELECT rowid, id, type, dateCreated, dateModified, dateServerModified, deleted \
+        FROM items WHERE rowid > ? AND {} {}ORDER BY rowid LIMIT ?;",
+        kind_condition, deleted_condition
+    );
+    let mut stmt = tx.prepare_cached(&sql)?;
+    let mut rows = stmt.query(params![after_rowid, limit as i64])?;
+    let mut result = Vec::new();
+    while let Some(row) = rows.next()? {
+        result.push(parse_item_base(row)?);
+    }
+    Ok(result)
+}
+
+/// The sync scope (JSON) of a client, if it has one
+pub fn get_sync_scope(tx: &Tx, client_id: &str) -> Result<Option<String>> {
+    let mut stmt = tx.prepare_cached("SELECT scope FROM syncScopes WHERE clientId = ?;")?;
+    let mut rows = stmt.query(params![client_id])?;
+    match rows.next()? {
+        Some(row) => Ok(Some(row.get(0)?)),
+        None => Ok(None),
+    }
+}
+
+/// Set the sync scope (JSON) of a client, or remove it along with the client's scope items
+pub fn set_sync_scope(tx: &Tx, client_id: &str, scope: Option<&str>, date: DbTime) -> Result<()> {
+    match scope {
+        Some(scope) => {
+            let mut stmt = tx.prepare_cached(
+                "INSERT OR REPLACE INTO syncScopes(clientId, scope, dateSet) VALUES(?, ?, ?);",
+            )?;
+            stmt.execute(params![client_id, scope, date])?;
+        }
+        None => {
+            let mut stmt = tx.prepare_cached("DELETE FROM syncScopes WHERE clientId = ?;")?;
+            stmt.execute(params![client_id])?;
+            let mut stmt = tx.prepare_cached("DELETE FROM syncScopeItems WHERE clientId = ?;")?;
+            stmt.execute(params![client_id])?;
+        }
+    }
+    Ok(())
+}
+
+/// What a client was last told about an item of its sync scope
+pub struct SyncScopeItem {
+    pub id: String,
+    pub in_scope: bool,
+    pub change_seq: i64,
+}
+
+pub fn get_sync_scope_items(tx: &Tx, client_id: &str) -> Result<Vec<SyncScopeItem>> {
+    let mut stmt =
+        tx.prepare_cached("SELECT id, inScope, changeSeq FROM syncScopeItems WHERE clientId = ?;")?;
+    let mut rows = stmt.query(params![client_id])?;
+    let mut result = Vec::new();
+    while let Some(row) = rows.next()? {
+        result.push(SyncScopeItem {
+            id: row.get(0)?,
+            in_scope: row.get(1)?,
+            change_seq: row.get(2)?,
+        });
+    }
+    Ok(result)
+}
+
+pub fn set_sync_scope_item(tx: &Tx, client_id: &str, item: &SyncScopeItem) -> Result<()> {
+    let mut stmt = tx.prepare_cached(
+        "INSERT OR REPLACE INTO syncScopeItems(clientId, id, inScope, changeSeq) \
+        VALUES(?, ?, ?, ?);",
+    )?;
+    stmt.execute(params![client_id, item.id, item.in_scope, item.change_seq])?;
+    Ok(())
+}
+
+/// The result of an operation of a client's operation log, if it was applied before
+pub fn get_applied_operation(tx: &Tx, client_id: &str, op_id: &str) -> Result<Option<String>> {
+    let mut stmt =
+        tx.prepare_cached("SELECT result FROM appliedOperations WHERE clientId = ? AND opId = ?;")?;
+    let mut rows = stmt.query(params![client_id, op_id])?;
+    match rows.next()? {
+        Some(row) => Ok(Some(row.get(0)?)),
+        None => Ok(None),
+    }
+}
+
+pub fn insert_applied_operation(
+    tx: &Tx,
+    client_id: &str,
+    op_id: &str,
+    result: &str,
+    date: DbTime,
+) -> Result<()> {
+    let mut stmt = tx.prepare_cached(
+        "INSERT INTO appliedOperations(clientId, opId, result, dateApplied) VALUES(?, ?, ?, ?);",
+    )?;
+    stmt.execute(params![client_id, op_id, result, date])?;
+    Ok(())
+}
+
+pub fn is_archive_part_imported(tx: &Tx, archive: &str, part: &str) -> Result<bool> {
+    let mut stmt =
+        tx.prepare_cached("SELECT 1 FROM importedArchiveParts WHERE archive = ? AND part = ?;")?;
+    let mut rows = stmt.query(params![archive, part])?;
+    Ok(rows.next()?.is_some())
+}
+
+pub fn insert_imported_archive_part(
+    tx: &Tx,
+    archive: &str,
+    part: &str,
+    date: DbTime,
+) -> Result<()> {
+    let mut stmt = tx.prepare_cached(
+        "INSERT INTO importedArchiveParts(archive, part, dateImported) VALUES(?, ?, ?);",
+    )?;
+    stmt.execute(params![archive, part, date])?;
+    Ok(())
+}
+
+/// Forget operations applied before `date_applied_lt`, returning how many
+pub fn delete_applied_operations(tx: &Tx, date_applied_lt: DbTime) -> Result<usize> {
+    let mut stmt = tx.prepare_cached("DELETE FROM appliedOperations WHERE dateApplied < ?;")?;
+    Ok(stmt.execute(params![date_applied_lt])?)
+}
+
 /// Search for items that have a certain property equal to certain value
 pub fn search_strings(tx: &Tx, property_name: &str, value: &str) -> Result<Vec<Rowid>> {
     let mut stmt = tx.prepare_cached("SELECT item FROM strings WHERE name = ? AND value = ?;")?;
@@ -299,10 +676,67 @@ pub fn check_real_exists(tx: &Tx, item_rowid: Rowid, name: &str, value: f64) ->
     Ok(stmt.exists(params![item_rowid, name, value])?)
 }
 
+/// Check whether a non-deleted item of type `item_type`, other than `except_rowid`,
+/// already has the string property `name` equal to `value`.
+pub fn check_string_taken(
+    tx: &Tx,
+    item_type: &str,
+    except_rowid: Rowid,
+    name: &str,
+    value: &str,
+) -> Result<bool> {
+    let mut stmt = tx.prepare_cached(
+        "SELECT 1 FROM strings, items \
+        WHERE strings.name = ? AND strings.value = ? AND strings.item != ? \
+        AND items.rowid = strings.item AND items.type = ? AND items.deleted = 0;",
+    )?;
+    Ok(stmt.exists(params![name, value, except_rowid, item_type])?)
+}
+
+/// Same as `check_string_taken`, for integer (and DateTime, Bool) properties
+pub fn check_integer_taken(
+    tx: &Tx,
+    item_type: &str,
+    except_rowid: Rowid,
+    name: &str,
+    value: i64,
+) -> Result<bool> {
+    let mut stmt = tx.prepare_cached(
+        "SELECT 1 FROM integers, items \
+        WHERE integers.name = ? AND integers.value = ? AND integers.item != ? \
+        AND items.rowid = integers.item AND items.type = ? AND items.deleted = 0;",
+    )?;
+    Ok(stmt.exists(params![name, value, except_rowid, item_type])?)
+}
+
+/// Same as `check_string_taken`, for real properties
+pub fn check_real_taken(
+    tx: &Tx,
+    item_type: &str,
+    except_rowid: Rowid,
+    name: &str,
+    value: f64,
+) -> Result<bool> {
+    let mut stmt = tx.prepare_cached(
+        "SELECT 1 FROM reals, items \
+        WHERE reals.name = ? AND reals.value = ? AND reals.item != ? \
+        AND items.rowid = reals.item AND items.type = ? AND items.deleted = 0;",
+    )?;
+    Ok(stmt.exists(params![name, value, except_rowid, item_type])?)
+}
+
+/// Take the next number of the change sequence, see `get_changes_since`
+pub fn next_change_seq(tx: &Tx) -> Result<i64> {
+    let mut stmt =
+        tx.prepare_cached("UPDATE changeSequence SET value = value + 1 WHERE rowid = 1;")?;
+    stmt.execute([])?;
+    get_last_change_seq(tx)
+}
+
 pub fn update_item_date_server_modified(tx: &Tx, rowid: Rowid, date: DbTime) -> Result<()> {
-    let sql = "UPDATE items SET dateServerModified = ? WHERE rowid = ?;";
+    let sql = "UPDATE items SET dateServerModified = ?, changeSeq = ? WHERE rowid = ?;";
     let mut stmt = tx.prepare_cached(sql)?;
-    stmt.execute(params![date, rowid])?;
+    stmt.execute(params![date, next_change_seq(tx)?, rowid])?;
     Ok(())
 }
 
@@ -313,17 +747,30 @@ pub fn update_item_base(
     date_server_modified: DbTime,
     deleted: Option<bool>,
 ) -> Result<()> {
-    let mut sql = "UPDATE items SET dateModified = ?, dateServerModified = ?".to_string();
+    let mut sql =
+        "UPDATE items SET dateModified = ?, dateServerModified = ?, changeSeq = ?".to_string();
     if deleted.is_some() {
         sql.push_str(", deleted = ?");
     }
     sql.push_str(" WHERE rowid = ?;");
+    let change_seq = next_change_seq(tx)?;
     let mut stmt = tx.prepare_cached(&sql)?;
     if let Some(deleted) = deleted {
-        stmt.execute(params![date_modified, date_server_modified, deleted, rowid])?;
+        stmt.execute(params![
+            date_modified,
+            date_server_modified,
+            change_seq,
+            deleted,
+            rowid
+        ])?;
         Ok(())
     } else {
-        stmt.execute(params![date_modified, date_server_modified, rowid])?;
+        stmt.execute(params![
+            date_modified,
+            date_server_modified,
+            change_seq,
+            rowid
+        ])?;
         Ok(())
     }
 }
@@ -335,11 +782,79 @@ pub fn dangerous_permament_remove_item(tx: &Tx, rowid: Rowid) -> Result<()> {
     stmt.execute(params![rowid])?;
     let mut stmt = tx.prepare_cached("DELETE FROM strings WHERE item = ?;")?;
     stmt.execute(params![rowid])?;
+    let mut stmt = tx.prepare_cached("DELETE FROM propertyModified WHERE item = ?;")?;
+    stmt.execute(params![rowid])?;
+    let mut stmt = tx.prepare_cached("DELETE FROM revisions WHERE item = ?;")?;
+    stmt.execute(params![rowid])?;
     let mut stmt = tx.prepare_cached("DELETE FROM items WHERE rowid = ?;")?;
     stmt.execute(params![rowid])?;
     Ok(())
 }
 
+/// Permanently remove an item together with everything that refers to it:
+/// its properties, revisions, and edges from or to it.
+/// Edges are represented by items themselves (see `insert_edge`), those are removed as well
+/// unless they are regular items set as `_self` of the edge.
+/// The ids of removed items are recorded with a new change, see `get_purged_items_since`.
+pub fn dangerous_purge_item(tx: &Tx, rowid: Rowid) -> Result<()> {
+    let mut stmt = tx
+        .prepare_cached("SELECT self FROM edges WHERE (source = ? OR target = ?) AND self != ?;")?;
+    let mut rows = stmt.query(params![rowid, rowid, rowid])?;
+    let mut edge_items: Vec<Rowid> = Vec::new();
+    while let Some(row) = rows.next()? {
+        edge_items.push(row.get(0)?);
+    }
+    let mut stmt =
+        tx.prepare_cached("DELETE FROM edges WHERE source = ? OR target = ? OR self = ?;")?;
+    stmt.execute(params![rowid, rowid, rowid])?;
+    for edge_item in edge_items {
+        let is_edge_item = get_item_base(tx, edge_item)?
+            .map(|base| base._type == "Edge")
+            .unwrap_or(false);
+        if is_edge_item {
+            let mut stmt = tx.prepare_cached("DELETE FROM edges WHERE self = ?;")?;
+            stmt.execute(params![edge_item])?;
+            insert_purged_item(tx, edge_item)?;
+            dangerous_permament_remove_item(tx, edge_item)?;
+        }
+    }
+    insert_purged_item(tx, rowid)?;
+    dangerous_permament_remove_item(tx, rowid)
+}
+
+fn insert_purged_item(tx: &Tx, rowid: Rowid) -> Result<()> {
+    let mut stmt = tx.prepare_cached(
+        "INSERT OR REPLACE INTO purgedItems(id, changeSeq) \
+        SELECT id, ? FROM items WHERE rowid = ?;",
+    )?;
+    stmt.execute(params![next_change_seq(tx)?, rowid])?;
+    Ok(())
+}
+
+/// Ids of items that were deleted before `date_server_modified_lt`,
+/// and whose deletion is at most change `change_seq_le`.
+/// Schema items are never returned, as removing them would change the Schema.
+/// At most `limit` ids are returned.
+pub fn get_deleted_item_ids(
+    tx: &Tx,
+    date_server_modified_lt: DbTime,
+    change_seq_le: i64,
+    limit: u64,
+) -> Result<Vec<String>> {
+    let mut stmt = tx.prepare_cached(
+        "SELECT id FROM items \
+        WHERE deleted = 1 AND dateServerModified < ? AND changeSeq <= ? \
+        AND type NOT IN ('ItemPropertySchema', 'ItemTypeSchema') \
+        ORDER BY rowid LIMIT ?;",
+    )?;
+    let mut rows = stmt.query(params![date_server_modified_lt, change_seq_le, limit])?;
+    let mut result = Vec::new();
+    while let Some(row) = rows.next()? {
+        result.push(row.get(0)?);
+    }
+    Ok(result)
+}
+
 pub fn insert_integer(tx: &Tx, item: Rowid, name: &str, value: i64) -> Result<()> {
     let mut stmt = tx.prepare_cached("INSERT INTO integers VALUES(?, ?, ?);")?;
     stmt.execute(params![item, name, value])?;
@@ -368,6 +883,29 @@ pub fn delete_property(tx: &Tx, item: Rowid, name: &str) -> Result<()> {
     Ok(())
 }
 
+/// When the properties of an item were last modified, see `set_property_date_modified`.
+/// Properties that were not modified since this is tracked are absent.
+pub fn get_property_dates_modified(tx: &Tx, item: Rowid) -> Result<HashMap<String, DbTime>> {
+    let mut stmt =
+        tx.prepare_cached("SELECT name, dateModified FROM propertyModified WHERE item = ?;")?;
+    let mut rows = stmt.query(params![item])?;
+    let mut result = HashMap::new();
+    while let Some(row) = rows.next()? {
+        result.insert(row.get(0)?, row.get(1)?);
+    }
+    Ok(result)
+}
+
+/// Record when a property was modified. The date never moves backwards.
+pub fn set_property_date_modified(tx: &Tx, item: Rowid, name: &str, date: DbTime) -> Result<()> {
+    let mut stmt = tx.prepare_cached(
+        "INSERT OR REPLACE INTO propertyModified(item, name, dateModified) VALUES(?, ?, MAX(?, \
+        IFNULL((SELECT dateModified FROM propertyModified WHERE item = ? AND name = ?), ?)));",
+    )?;
+    stmt.execute(params![item, name, date, item, name, date])?;
+    Ok(())
+}
+
 pub fn insert_edge(
     tx: &Tx,
     self_rowid: Rowid,
@@ -396,6 +934,93 @@ pub struct EdgePointer {
     pub item: Rowid,
 }
 
+/// Past state of an item, recorded before the item was changed
+#[derive(Debug, PartialEq)]
+pub struct Revision {
+    pub rowid: Rowid,
+    /// `dateServerModified` of the item in this revision
+    pub date_server_modified: DbTime,
+    /// When the item was changed (and the revision recorded)
+    pub date_recorded: DbTime,
+    pub author: String,
+    /// JSON of the item in this revision
+    pub content: String,
+}
+
+pub fn insert_revision(
+    tx: &Tx,
+    item: Rowid,
+    date_server_modified: DbTime,
+    date_recorded: DbTime,
+    author: &str,
+    content: &str,
+) -> Result<()> {
+    let mut stmt = tx.prepare_cached(
+        "INSERT INTO revisions(item, dateServerModified, dateRecorded, author, content) \
+        VALUES(?, ?, ?, ?, ?);",
+    )?;
+    stmt.execute(params![
+        item,
+        date_server_modified,
+        date_recorded,
+        author,
+        content
+    ])?;
+    Ok(())
+}
+
+/// Get revisions of an item, newest first
+pub fn get_revisions(tx: &Tx, item: Rowid) -> Result<Vec<Revision>> {
+    let mut stmt = tx.prepare_cached(
+        "SELECT rowid, dateServerModified, dateRecorded, author, content \
+        FROM revisions WHERE item = ? ORDER BY rowid DESC;",
+    )?;
+    let mut rows = stmt.query(params![item])?;
+    let mut result = Vec::new();
+    while let Some(row) = rows.next()? {
+        result.push(Revision {
+            rowid: row.get(0)?,
+            date_server_modified: row.get(1)?,
+            date_recorded: row.get(2)?,
+            author: row.get(3)?,
+            content: row.get(4)?,
+        });
+    }
+    Ok(result)
+}
+
+/// Kind of edges present in the database: type of the source item,
+/// name of the edge and type of the target item.
+#[derive(Debug, PartialEq)]
+pub struct EdgeType {
+    pub source_type: String,
+    pub name: String,
+    pub target_type: String,
+}
+
+/// Get all distinct `EdgeType`-s of non-deleted edges
+pub fn get_edge_types(tx: &Tx) -> Result<Vec<EdgeType>> {
+    let mut stmt = tx.prepare_cached(
+        "SELECT DISTINCT source.type, edges.name, target.type \
+        FROM edges, items as source, items as target \
+        WHERE edges.source = source.rowid \
+        AND edges.target = target.rowid \
+        AND source.deleted = 0 \
+        AND target.deleted = 0 \
+        ORDER BY source.type, edges.name, target.type;",
+    )?;
+    let mut rows = stmt.query([])?;
+    let mut result = Vec::new();
+    while let Some(row) = rows.next()? {
+        result.push(EdgeType {
+            source_type: row.get(0)?,
+            name: row.get(1)?,
+            target_type: row.get(2)?,
+        });
+    }
+    Ok(result)
+}
+
 pub fn get_self_edge(tx: &Tx, self_rowid: Rowid) -> Result<Option<EdgeBase>> {
     let mut stmt = tx.prepare_cached("SELECT source, name, target FROM edges WHERE self = ?;")?;
     let mut rows = stmt.query(params![self_rowid])?;
@@ -440,20 +1065,44 @@ pub fn get_incoming_edges(tx: &Tx, target: Rowid) -> Result<Vec<EdgePointer>> {
 pub fn get_schema(tx: &Tx) -> Result<Schema> {
     let mut stmt = tx
         .prepare_cached(
-            "SELECT thisProperty.value, thisType.value \
+            "SELECT \
+            thisProperty.value, \
+            thisType.value, \
+            thisItemType.value, \
+            isRequired.value, \
+            isUnique.value, \
+            pattern.value, \
+            minimum.value, \
+            maximum.value, \
+            defaultValue.value, \
+            formula.value \
         FROM \
-            items as item, \
-            strings as thisProperty, \
-            strings as thisType \
-        WHERE item.type = 'ItemPropertySchema' \
-        AND thisProperty.item = item.rowid \
-        AND thisType.item = item.rowid \
-        AND thisProperty.name = 'propertyName' \
-        AND thisType.name = 'valueType';",
+            items as item \
+            JOIN strings as thisProperty \
+                ON thisProperty.item = item.rowid AND thisProperty.name = 'propertyName' \
+            JOIN strings as thisType \
+                ON thisType.item = item.rowid AND thisType.name = 'valueType' \
+            LEFT JOIN strings as thisItemType \
+                ON thisItemType.item = item.rowid AND thisItemType.name = 'itemType' \
+            LEFT JOIN integers as isRequired \
+                ON isRequired.item = item.rowid AND isRequired.name = 'isRequired' \
+            LEFT JOIN integers as isUnique \
+                ON isUnique.item = item.rowid AND isUnique.name = 'isUnique' \
+            LEFT JOIN strings as pattern \
+                ON pattern.item = item.rowid AND pattern.name = 'pattern' \
+            LEFT JOIN reals as minimum \
+                ON minimum.item = item.rowid AND minimum.name = 'minimum' \
+            LEFT JOIN reals as maximum \
+                ON maximum.item = item.rowid AND maximum.name = 'maximum' \
+            LEFT JOIN strings as defaultValue \
+                ON defaultValue.item = item.rowid AND defaultValue.name = 'defaultValue' \
+            LEFT JOIN strings as formula \
+                ON formula.item = item.rowid AND formula.name = 'formula' \
+        WHERE item.type = 'ItemPropertySchema';",
         )
         .context_str("Failed to prepare SQL get_schema query")?;
     let mut rows = stmt.query([])?;
-    let mut property_types: HashMap<String, SchemaPropertyType> = HashMap::new();
+    let mut schema = Schema::default();
     while let Some(row) = rows.next()? {
         let this_property: String = row.get(0)?;
         let this_type: String = row.get(1)?;
@@ -461,9 +1110,60 @@ pub fn get_schema(tx: &Tx) -> Result<Schema> {
             code: StatusCode::INTERNAL_SERVER_ERROR,
             msg: e,
         })?;
-        property_types.insert(this_property, value_type);
+        let item_type: Option<String> = row.get(2)?;
+        if let Some(item_type) = item_type {
+            let is_required: Option<i64> = row.get(3)?;
+            let is_unique: Option<i64> = row.get(4)?;
+            let pattern: Option<String> = row.get(5)?;
+            let pattern = pattern
+                .map(|p| schema::parse_property_pattern(&p))
+                .transpose()
+                .context(|| format!("Schema of property {}", this_property))?;
+            let default: Option<String> = row.get(8)?;
+            let default = default
+                .map(|d| schema::parse_default_value(&value_type, &d))
+                .transpose()
+                .context(|| format!("Schema of property {}", this_property))?;
+            let formula: Option<String> = row.get(9)?;
+            let formula = formula
+                .map(|f| Formula::parse(&f))
+                .transpose()
+                .context(|| format!("Schema of property {}", this_property))?;
+            let rules = PropertyRules {
+                required: is_required == Some(1),
+                unique: is_unique == Some(1),
+                pattern,
+                minimum: row.get(6)?,
+                maximum: row.get(7)?,
+                default,
+                formula,
+            };
+            schema.add_property_rules(&item_type, &this_property, rules);
+        }
+        schema.add_property_type(this_property, value_type);
     }
-    Ok(Schema { property_types })
+    schema.revision_item_types = Arc::new(get_revision_item_types(tx)?);
+    Ok(schema)
+}
+
+/// Item types that have revisions enabled by a (non-deleted) `ItemTypeSchema` item
+fn get_revision_item_types(tx: &Tx) -> Result<HashSet<String>> {
+    let mut stmt = tx.prepare_cached(
+        "SELECT DISTINCT itemType.value \
+        FROM \
+            items as item \
+            JOIN strings as itemType \
+                ON itemType.item = item.rowid AND itemType.name = 'itemType' \
+            JOIN integers as keepRevisions \
+                ON keepRevisions.item = item.rowid AND keepRevisions.name = 'keepRevisions' \
+        WHERE item.type = 'ItemTypeSchema' AND item.deleted = 0 AND keepRevisions.value = 1;",
+    )?;
+    let mut rows = stmt.query([])?;
+    let mut result = HashSet::new();
+    while let Some(row) = rows.next()? {
+        result.insert(row.get(0)?);
+    }
+    Ok(result)
 }
 
 pub fn delete_schema_items_by_item_type_and_prop(
@@ -471,6 +1171,18 @@ pub fn delete_schema_items_by_item_type_and_prop(
     item_type: &str,
     property_name: &str,
 ) -> Result<()> {
+    for rowid in get_schema_items_by_item_type_and_prop(tx, item_type, property_name)? {
+        dangerous_permament_remove_item(tx, rowid)?;
+    }
+    Ok(())
+}
+
+/// Find `ItemPropertySchema` items declaring property `property_name` for `item_type`
+pub fn get_schema_items_by_item_type_and_prop(
+    tx: &Tx,
+    item_type: &str,
+    property_name: &str,
+) -> Result<Vec<Rowid>> {
     let sql = "SELECT rowid FROM items as item, strings as itemTypeStr, strings as propNameStr \
         WHERE item.type = 'ItemPropertySchema' \
         AND item.rowid = itemTypeStr.item \
@@ -482,11 +1194,27 @@ pub fn delete_schema_items_by_item_type_and_prop(
         ;";
     let mut stmt = tx.prepare_cached(sql)?;
     let mut rows = stmt.query(params![item_type, property_name])?;
+    let mut result = Vec::new();
     while let Some(row) = rows.next()? {
-        let rowid: Rowid = row.get(0)?;
-        dangerous_permament_remove_item(tx, rowid)?;
+        result.push(row.get(0)?);
+    }
+    Ok(result)
+}
+
+/// Run `func` inside an SQLite savepoint, so that a failure only reverts
+/// the changes made by `func` and not the whole transaction.
+pub fn in_savepoint<T, F: FnOnce() -> Result<T>>(tx: &Tx, func: F) -> Result<T> {
+    tx.execute_batch("SAVEPOINT pod_savepoint;")?;
+    match func() {
+        Ok(result) => {
+            tx.execute_batch("RELEASE pod_savepoint;")?;
+            Ok(result)
+        }
+        Err(err) => {
+            tx.execute_batch("ROLLBACK TO pod_savepoint; RELEASE pod_savepoint;")?;
+            Err(err)
+        }
     }
-    Ok(())
 }
 
 #[derive(Debug, Copy, Clone, PartialEq)]
@@ -1,3 +1,4 @@
+use crate::api_model::PropertyOperation;
 use crate::database_api;
 use crate::database_api::IntegersNameValue;
 use crate::database_api::ItemBase;
@@ -6,6 +7,7 @@ use crate::database_api::Rowid;
 use crate::database_api::StringsNameValue;
 use crate::error::Error;
 use crate::error::Result;
+use crate::schema::Formula;
 use crate::schema::Schema;
 use crate::schema::SchemaPropertyType;
 use log::warn;
@@ -259,6 +261,353 @@ pub fn insert_property(
     Ok(())
 }
 
+/// Check the properties being written to an item against the rules
+/// that the Schema declares for its type (see `PropertyRules`).
+///
+/// Only the `fields` that are being written are checked.
+/// When creating an item (`is_create`), all required properties need to be present;
+/// when updating, required properties cannot be erased.
+pub fn check_property_rules(
+    tx: &Tx,
+    schema: &Schema,
+    item_type: &str,
+    rowid: Rowid,
+    fields: &HashMap<String, Value>,
+    is_create: bool,
+) -> Result<()> {
+    let rules = if let Some(rules) = schema.item_rules.get(item_type) {
+        rules
+    } else {
+        return Ok(());
+    };
+    for (name, rule) in rules {
+        let value = match fields.get(name) {
+            Some(Value::Null) | None => {
+                if rule.required && (is_create || fields.contains_key(name)) {
+                    return Err(rule_violation(
+                        "required",
+                        item_type,
+                        name,
+                        "a value is required".to_string(),
+                    ));
+                }
+                continue;
+            }
+            Some(value) => value,
+        };
+        if let (Some(pattern), Value::String(string)) = (&rule.pattern, value) {
+            if !pattern.is_match(string) {
+                return Err(rule_violation(
+                    "pattern",
+                    item_type,
+                    name,
+                    format!("value {} does not match {}", value, pattern.as_str()),
+                ));
+            }
+        }
+        if let Some(number) = value.as_f64() {
+            if rule.minimum.map(|min| number < min).unwrap_or(false)
+                || rule.maximum.map(|max| number > max).unwrap_or(false)
+            {
+                let min = rule.minimum.map(|m| m.to_string()).unwrap_or_default();
+                let max = rule.maximum.map(|m| m.to_string()).unwrap_or_default();
+                return Err(rule_violation(
+                    "range",
+                    item_type,
+                    name,
+                    format!("value {} is outside of range [{}, {}]", value, min, max),
+                ));
+            }
+        }
+        if rule.unique && check_property_value_taken(tx, schema, item_type, rowid, name, value)? {
+            return Err(rule_violation(
+                "unique",
+                item_type,
+                name,
+                format!("value {} is already used by another item", value),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Check that the `unique` properties of an item that is being restored
+/// have not been taken by another item since it was deleted.
+pub fn check_unique_properties_free(
+    tx: &Tx,
+    schema: &Schema,
+    item_type: &str,
+    rowid: Rowid,
+) -> Result<()> {
+    let rules = if let Some(rules) = schema.item_rules.get(item_type) {
+        rules
+    } else {
+        return Ok(());
+    };
+    let properties = get_item_properties(tx, rowid, schema)?;
+    for (name, rule) in rules {
+        let value = match properties.get(name) {
+            Some(value) if rule.unique => value,
+            _ => continue,
+        };
+        if check_property_value_taken(tx, schema, item_type, rowid, name, value)? {
+            return Err(Error {
+                code: StatusCode::CONFLICT,
+                msg: format!(
+                    "Schema rule 'unique' violated by property {} of item type {}: \
+                    value {} has been used by another item since the item was deleted",
+                    name, item_type, value
+                ),
+            });
+        }
+    }
+    Ok(())
+}
+
+fn check_property_value_taken(
+    tx: &Tx,
+    schema: &Schema,
+    item_type: &str,
+    rowid: Rowid,
+    name: &str,
+    value: &Value,
+) -> Result<bool> {
+    match (schema.property_types.get(name), value) {
+        (Some(SchemaPropertyType::Text), Value::String(s)) => {
+            database_api::check_string_taken(tx, item_type, rowid, name, s)
+        }
+        (Some(SchemaPropertyType::Real), Value::Number(n)) => match n.as_f64() {
+            Some(n) => database_api::check_real_taken(tx, item_type, rowid, name, n),
+            None => Ok(false),
+        },
+        (Some(SchemaPropertyType::Integer), Value::Number(n))
+        | (Some(SchemaPropertyType::DateTime), Value::Number(n)) => match n.as_i64() {
+            Some(n) => database_api::check_integer_taken(tx, item_type, rowid, name, n),
+            None => Ok(false),
+        },
+        (Some(SchemaPropertyType::Bool), Value::Bool(b)) => {
+            database_api::check_integer_taken(tx, item_type, rowid, name, if *b { 1 } else { 0 })
+        }
+        // Type mismatches are reported when the property is inserted
+        _ => Ok(false),
+    }
+}
+
+/// Replace every `PropertyOperation` in the `fields` of an update
+/// with the value it results in, given the current properties of the item
+pub fn apply_property_operations(
+    tx: &Tx,
+    schema: &Schema,
+    rowid: Rowid,
+    fields: &mut HashMap<String, Value>,
+) -> Result<()> {
+    if !fields.values().any(|v| v.is_object()) {
+        return Ok(());
+    }
+    let current = get_item_properties(tx, rowid, schema)?;
+    for (name, value) in fields.iter_mut() {
+        if !value.is_object() {
+            continue;
+        }
+        let operation: PropertyOperation =
+            serde_json::from_value(value.take()).map_err(|err| Error {
+                code: StatusCode::BAD_REQUEST,
+                msg: format!("Failed to parse operation on property {}: {}", name, err),
+            })?;
+        let value_type = schema.property_types.get(name).ok_or_else(|| Error {
+            code: StatusCode::BAD_REQUEST,
+            msg: format!("Property {} not defined in Schema", name),
+        })?;
+        *value = apply_property_operation(name, value_type, current.get(name), operation)?;
+    }
+    Ok(())
+}
+
+fn apply_property_operation(
+    name: &str,
+    value_type: &SchemaPropertyType,
+    current: Option<&Value>,
+    operation: PropertyOperation,
+) -> Result<Value> {
+    use PropertyOperation::*;
+    use SchemaPropertyType::*;
+    let unsupported = |operation: &PropertyOperation| Error {
+        code: StatusCode::BAD_REQUEST,
+        msg: format!(
+            "Operation {} is not supported on {:?} property {}",
+            serde_json::json!(operation),
+            value_type,
+            name
+        ),
+    };
+    let current = current.filter(|v| !v.is_null());
+    let result = match (&operation, value_type) {
+        (SetIfAbsent(value), _) => current.unwrap_or(value).clone(),
+        (Append(suffix), Text) => {
+            let current = current.and_then(|v| v.as_str()).unwrap_or("");
+            Value::String(format!("{}{}", current, suffix))
+        }
+        (Increment(n) | Decrement(n), Integer) => {
+            let current = current.and_then(|v| v.as_i64()).unwrap_or(0);
+            let n = n.as_i64().ok_or_else(|| unsupported(&operation))?;
+            let result = match operation {
+                Increment(_) => current.checked_add(n),
+                _ => current.checked_sub(n),
+            };
+            result.map(Value::from).ok_or_else(|| Error {
+                code: StatusCode::BAD_REQUEST,
+                msg: format!(
+                    "Integer overflow in operation {} on property {}",
+                    serde_json::json!(operation),
+                    name
+                ),
+            })?
+        }
+        (Increment(n) | Decrement(n), Real) => {
+            let current = current.and_then(|v| v.as_f64()).unwrap_or(0.0);
+            let n = n.as_f64().ok_or_else(|| unsupported(&operation))?;
+            let result = match operation {
+                Increment(_) => current + n,
+                _ => current - n,
+            };
+            if !result.is_finite() {
+                return Err(Error {
+                    code: StatusCode::BAD_REQUEST,
+                    msg: format!(
+                        "Real overflow in operation {} on property {}",
+                        serde_json::json!(operation),
+                        name
+                    ),
+                });
+            }
+            Value::from(result)
+        }
+        (Min(n) | Max(n), Integer | DateTime) => {
+            let n = n.as_i64().ok_or_else(|| unsupported(&operation))?;
+            let result = match (current.and_then(|v| v.as_i64()), &operation) {
+                (None, _) => n,
+                (Some(current), Min(_)) => current.min(n),
+                (Some(current), _) => current.max(n),
+            };
+            Value::from(result)
+        }
+        (Min(n) | Max(n), Real) => {
+            let n = n.as_f64().ok_or_else(|| unsupported(&operation))?;
+            let result = match (current.and_then(|v| v.as_f64()), &operation) {
+                (None, _) => n,
+                (Some(current), Min(_)) => current.min(n),
+                (Some(current), _) => current.max(n),
+            };
+            Value::from(result)
+        }
+        _ => return Err(unsupported(&operation)),
+    };
+    Ok(result)
+}
+
+/// Add the Schema's `defaultValue` of every property that is absent from a new item
+pub fn apply_property_defaults(
+    schema: &Schema,
+    item_type: &str,
+    fields: &mut HashMap<String, Value>,
+) {
+    if let Some(rules) = schema.item_rules.get(item_type) {
+        for (name, rule) in rules {
+            if let Some(default) = &rule.default {
+                fields
+                    .entry(name.to_string())
+                    .or_insert_with(|| default.clone());
+            }
+        }
+    }
+}
+
+/// Computed properties (those with a `formula` in the Schema) cannot be written directly
+pub fn check_no_computed_properties(
+    schema: &Schema,
+    item_type: &str,
+    fields: &HashMap<String, Value>,
+) -> Result<()> {
+    if let Some(rules) = schema.item_rules.get(item_type) {
+        for name in fields.keys() {
+            if rules
+                .get(name)
+                .map(|r| r.formula.is_some())
+                .unwrap_or(false)
+            {
+                return Err(rule_violation(
+                    "formula",
+                    item_type,
+                    name,
+                    "the property is computed and cannot be written directly".to_string(),
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Recompute the computed properties of an item from its current properties.
+/// Formulas that use other computed properties are computed after them,
+/// and the computed values are checked against the Schema rules like written ones.
+pub fn update_computed_properties(
+    tx: &Tx,
+    schema: &Schema,
+    item_type: &str,
+    rowid: Rowid,
+) -> Result<()> {
+    let rules = match schema.item_rules.get(item_type) {
+        Some(rules) if rules.values().any(|r| r.formula.is_some()) => rules,
+        _ => return Ok(()),
+    };
+    let mut properties = get_item_properties(tx, rowid, schema)?;
+    let mut pending: Vec<(&String, &Formula)> = rules
+        .iter()
+        .filter_map(|(name, rule)| rule.formula.as_ref().map(|formula| (name, formula)))
+        .collect();
+    let mut computed = HashMap::new();
+    while let Some((first, _)) = pending.first() {
+        let (ready, waiting): (Vec<_>, Vec<_>) =
+            pending.iter().copied().partition(|(_, formula)| {
+                !formula
+                    .properties()
+                    .any(|p| pending.iter().any(|(name, _)| *name == p))
+            });
+        if ready.is_empty() {
+            return Err(rule_violation(
+                "formula",
+                item_type,
+                first,
+                "the formulas of the item type refer to each other in a cycle".to_string(),
+            ));
+        }
+        for (name, formula) in ready {
+            let value = formula
+                .compute(&properties)
+                .map(Value::String)
+                .unwrap_or(Value::Null);
+            properties.insert(name.to_string(), value.clone());
+            computed.insert(name.to_string(), value);
+        }
+        pending = waiting;
+    }
+    check_property_rules(tx, schema, item_type, rowid, &computed, false)?;
+    for (name, value) in &computed {
+        insert_property(tx, schema, rowid, name, value)?;
+    }
+    Ok(())
+}
+
+fn rule_violation(rule: &str, item_type: &str, property: &str, details: String) -> Error {
+    Error {
+        code: StatusCode::BAD_REQUEST,
+        msg: format!(
+            "Schema rule '{}' violated by property {} of item type {}: {}",
+            rule, property, item_type, details
+        ),
+    }
+}
+
 pub fn item_base_to_json(tx: &Tx, item: ItemBase, schema: &Schema) -> Result<Map<String, Value>> {
     let mut props = get_item_properties(tx, item.rowid, schema)?;
     add_item_base_properties(&mut props, item);
@@ -320,15 +669,9 @@ mod tests {
         let mut conn = new_conn();
         let tx = conn.transaction()?;
         let mut schema = database_api::get_schema(&tx).unwrap();
-        schema
-            .property_types
-            .insert("age".to_string(), SchemaPropertyType::Integer);
-        schema
-            .property_types
-            .insert("strength".to_string(), SchemaPropertyType::Real);
-        schema
-            .property_types
-            .insert("myDescription".to_string(), SchemaPropertyType::Text);
+        schema.add_property_type("age".to_string(), SchemaPropertyType::Integer);
+        schema.add_property_type("strength".to_string(), SchemaPropertyType::Real);
+        schema.add_property_type("myDescription".to_string(), SchemaPropertyType::Text);
 
         let date = Utc::now().timestamp_millis();
         let item: Rowid =
@@ -381,15 +724,9 @@ mod tests {
         let mut conn = new_conn();
         let tx = conn.transaction()?;
         let mut schema = database_api::get_schema(&tx).unwrap();
-        schema
-            .property_types
-            .insert("age".to_string(), SchemaPropertyType::Integer);
-        schema
-            .property_types
-            .insert("strength".to_string(), SchemaPropertyType::Real);
-        schema
-            .property_types
-            .insert("myDescription".to_string(), SchemaPropertyType::Text);
+        schema.add_property_type("age".to_string(), SchemaPropertyType::Integer);
+        schema.add_property_type("strength".to_string(), SchemaPropertyType::Real);
+        schema.add_property_type("myDescription".to_string(), SchemaPropertyType::Text);
 
         let date = Utc::now().timestamp_millis();
         let item: Rowid =
@@ -75,6 +75,16 @@ impl From<serde_path_to_error::Error<serde_json::Error>> for Error {
     }
 }
 
+impl From<serde_yaml::Error> for Error {
+    fn from(err: serde_yaml::Error) -> Error {
+        let msg = format!("YAML deserialization error {}", err);
+        Error {
+            code: StatusCode::BAD_REQUEST,
+            msg,
+        }
+    }
+}
+
 impl From<lettre::address::AddressError> for Error {
     fn from(err: lettre::address::AddressError) -> Error {
         let msg = format!("Email address error: {}", err);
@@ -1,5 +1,8 @@
+use crate::api_model::FilesExist;
+use crate::api_model::FilesManifest;
 use crate::constants;
 use crate::database_api;
+use crate::database_api::DbTime;
 use crate::error::Error;
 use crate::error::Result;
 use chacha20poly1305::aead::Aead;
@@ -7,9 +10,12 @@ use chacha20poly1305::aead::NewAead;
 use chacha20poly1305::Key;
 use chacha20poly1305::XChaCha20Poly1305;
 use chacha20poly1305::XNonce;
+use chrono::Utc;
 use log::warn;
 use rand::random;
 use rusqlite::Transaction;
+use serde_json::json;
+use serde_json::Value;
 use sha2::Digest;
 use sha2::Sha256;
 use std::fs::create_dir_all;
@@ -18,8 +24,12 @@ use std::io::Write;
 use std::ops::Deref;
 use std::path::PathBuf;
 use std::str::FromStr;
+use std::time::UNIX_EPOCH;
 use warp::http::status::StatusCode;
 
+/// Size of the authentication tag that encryption adds to each file
+const ENCRYPTION_TAG_SIZE: u64 = 16;
+
 pub fn upload_file(
     tx: &Transaction,
     owner: &str,
@@ -83,11 +93,108 @@ pub fn get_file(tx: &Transaction, owner: &str, sha256: &str) -> Result<Vec<u8>>
     Ok(plaintext)
 }
 
-fn file_exists_on_disk(owner: &str, sha256: &str) -> Result<bool> {
+/// Remove a file from disk, e.g. when the items referring to it are purged.
+/// Removing a file that does not exist is not an error.
+pub fn delete_file(owner: &str, sha256: &str) -> Result<()> {
+    let file = final_path(owner, sha256)?;
+    match std::fs::remove_file(file) {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(Error {
+            code: StatusCode::INTERNAL_SERVER_ERROR,
+            msg: format!("Failed to remove file {}, {}", sha256, err),
+        }),
+    }
+}
+
+pub fn file_exists_on_disk(owner: &str, sha256: &str) -> Result<bool> {
     let file = final_path(owner, sha256)?;
     Ok(file.exists())
 }
 
+/// Size (unencrypted) and upload date of a file, `None` if it's not uploaded
+pub fn file_info(owner: &str, sha256: &str) -> Result<Option<FileInfo>> {
+    if !is_sha256(sha256) || !file_exists_on_disk(owner, sha256)? {
+        return Ok(None);
+    }
+    let metadata = std::fs::metadata(final_path(owner, sha256)?).map_err(|err| Error {
+        code: StatusCode::INTERNAL_SERVER_ERROR,
+        msg: format!("Failed to read metadata of file {}, {}", sha256, err),
+    })?;
+    let date_uploaded = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|duration| duration.as_millis() as DbTime);
+    Ok(Some(FileInfo {
+        size: metadata.len().saturating_sub(ENCRYPTION_TAG_SIZE),
+        date_uploaded,
+    }))
+}
+
+pub struct FileInfo {
+    pub size: u64,
+    pub date_uploaded: Option<DbTime>,
+}
+
+/// Files of the `File` items changed since `query.since`, for clients to sync them.
+/// Like `internal_api::changes_since`, the returned `changeSeq` is the `since` of the next request.
+pub fn files_manifest(tx: &Transaction, owner: &str, query: FilesManifest) -> Result<Value> {
+    let mut changes =
+        database_api::get_file_changes_since(tx, query.since, query.limit.saturating_add(1))?;
+    let has_more = changes.len() as u64 > query.limit;
+    changes.truncate(query.limit as usize);
+    let change_seq = match changes.last() {
+        Some(change) => change.change_seq,
+        None => query.since.max(database_api::get_last_change_seq(tx)?),
+    };
+    let mut files = Vec::with_capacity(changes.len());
+    for change in changes {
+        let info = match &change.sha256 {
+            Some(sha256) => file_info(owner, sha256)?,
+            None => None,
+        };
+        files.push(json!({
+            "id": change.id,
+            "sha256": change.sha256,
+            "deleted": change.deleted,
+            "uploaded": info.is_some(),
+            "size": info.as_ref().map(|info| info.size),
+            "dateUploaded": info.and_then(|info| info.date_uploaded),
+        }));
+    }
+    Ok(json!({
+        "files": files,
+        "changeSeq": change_seq,
+        "hasMore": has_more,
+    }))
+}
+
+/// Split hashes into the files that are uploaded and the ones that are missing
+pub fn files_exist(owner: &str, query: FilesExist) -> Result<Value> {
+    let mut existing = Vec::new();
+    let mut missing = Vec::new();
+    for sha256 in query.sha256s {
+        if !is_sha256(&sha256) {
+            return Err(Error {
+                code: StatusCode::BAD_REQUEST,
+                msg: format!("Invalid sha256 {}, expected 64 hex characters", sha256),
+            });
+        }
+        if file_exists_on_disk(owner, &sha256)? {
+            existing.push(sha256);
+        } else {
+            missing.push(sha256);
+        }
+    }
+    Ok(json!({ "existing": existing, "missing": missing }))
+}
+
+/// Whether `sha256` is a hex-encoded hash, and therefore safe to use as a file name
+pub fn is_sha256(sha256: &str) -> bool {
+    sha256.len() == 64 && sha256.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
 fn final_path(owner: &str, sha256: &str) -> Result<PathBuf> {
     let result = files_dir()?;
     let final_dir = result.join(owner).join(constants::FILES_FINAL_SUBDIR);
@@ -101,8 +208,8 @@ fn final_path(owner: &str, sha256: &str) -> Result<PathBuf> {
     Ok(final_dir.join(sha256))
 }
 
-fn validate_hash(expected_sha256: &str, data: &[u8]) -> Result<()> {
-    let expected_sha256_vec = hex::decode(&expected_sha256)?;
+pub fn validate_hash(expected_sha256: &str, data: &[u8]) -> Result<()> {
+    let expected_sha256_vec = hex::decode(expected_sha256)?;
     let mut real_sha256 = Sha256::new();
     real_sha256.update(data);
     let real_sha256 = real_sha256.finalize();
@@ -135,9 +242,16 @@ fn update_key_and_nonce(
     } else {
         let key = hex::encode(key);
         let nonce = hex::encode(nonce);
+        let date = Utc::now().timestamp_millis();
         for item in item_rowids {
             database_api::insert_string(tx, item, "key", &key)?;
             database_api::insert_string(tx, item, "nonce", &nonce)?;
+            // For clients syncing files to learn that the file is now uploaded.
+            // Like any other change, it moves dateServerModified forward.
+            if let Some(base) = database_api::get_item_base(tx, item)? {
+                let date = date.max(base.date_server_modified + 1);
+                database_api::update_item_date_server_modified(tx, item, date)?;
+            }
         }
         Ok(())
     }
@@ -187,8 +301,12 @@ fn files_dir() -> Result<PathBuf> {
 #[cfg(test)]
 mod tests {
     use super::files_dir;
+    use super::files_exist;
+    use super::files_manifest;
     use super::get_file;
     use super::upload_file;
+    use crate::api_model::FilesExist;
+    use crate::api_model::FilesManifest;
     use crate::command_line_interface;
     use crate::database_api;
     use crate::database_api::tests::new_conn;
@@ -214,13 +332,64 @@ mod tests {
             "sha256": &sha,
         });
         let sha_item = serde_json::from_value(json)?;
-        internal_api::create_item_tx(&tx, &mut schema, sha_item, &owner, &cli, &database_key)?;
+        let id =
+            internal_api::create_item_tx(&tx, &mut schema, sha_item, &owner, &cli, &database_key)?;
+        let rowid = database_api::get_item_rowid(&tx, &id)?.unwrap();
+        let created = database_api::get_item_base(&tx, rowid)?.unwrap();
 
         upload_file(&tx, &owner, &sha, &[])?;
+        let uploaded = database_api::get_item_base(&tx, rowid)?.unwrap();
+        assert!(uploaded.date_server_modified > created.date_server_modified);
 
         let result = get_file(&tx, &owner, &sha)?;
         assert_eq!(result.len(), 0, "{}:{}", file!(), line!());
         std::fs::remove_dir_all(owner_dir).ok();
         Ok(())
     }
+
+    #[test]
+    fn test_files_manifest() -> Result<()> {
+        let mut conn = new_conn();
+        let tx = conn.transaction().unwrap();
+        let mut schema = database_api::get_schema(&tx)?;
+        let cli = command_line_interface::tests::test_cli();
+        let database_key = DatabaseKey::from("".to_string()).unwrap();
+        let owner = "testManifestOwner".to_string();
+        let owner_dir = files_dir()?.join(&owner);
+        std::fs::remove_dir_all(&owner_dir).ok();
+        let uploaded = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+        let missing = "2c26b46b68ffc68ff99b453c1d30413413422d706483bfa0f98a5e886266e7ae";
+        let since = database_api::get_last_change_seq(&tx)?;
+        for sha in [uploaded, missing] {
+            let item = serde_json::from_value(json!({"type": "File", "sha256": sha}))?;
+            internal_api::create_item_tx(&tx, &mut schema, item, &owner, &cli, &database_key)?;
+        }
+        upload_file(&tx, &owner, uploaded, &[])?;
+
+        let query = FilesManifest { since, limit: 10 };
+        let manifest = files_manifest(&tx, &owner, query)?;
+        let files = manifest["files"].as_array().unwrap();
+        // Uploading changes the item, so it comes last
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0]["sha256"], json!(missing));
+        assert_eq!(files[0]["uploaded"], json!(false));
+        assert_eq!(files[0]["size"], json!(null));
+        assert_eq!(files[1]["sha256"], json!(uploaded));
+        assert_eq!(files[1]["uploaded"], json!(true));
+        assert_eq!(files[1]["size"], json!(0));
+        assert_eq!(manifest["hasMore"], json!(false));
+
+        let query = FilesExist {
+            sha256s: vec![uploaded.to_string(), missing.to_string()],
+        };
+        let result = files_exist(&owner, query)?;
+        assert_eq!(result["existing"], json!([uploaded]));
+        assert_eq!(result["missing"], json!([missing]));
+        let query = FilesExist {
+            sha256s: vec!["../escape".to_string()],
+        };
+        assert!(files_exist(&owner, query).is_err());
+        std::fs::remove_dir_all(owner_dir).ok();
+        Ok(())
+    }
 }
@@ -0,0 +1,135 @@
+//
+// Garbage collection of soft-deleted items
+//
+
+use crate::database_api;
+use crate::database_api::DbTime;
+use crate::error::Result;
+use crate::internal_api;
+use crate::schema::Schema;
+use log::info;
+use rusqlite::Transaction as Tx;
+
+#[derive(Debug, Default, PartialEq)]
+pub struct GarbageCollectionReport {
+    pub purged_items: usize,
+    /// Files that are no longer referenced, to be removed once the transaction is committed
+    pub orphaned_files: Vec<String>,
+    /// Whether fewer than `limit` items were left to purge, so that no other batch is needed
+    pub finished: bool,
+}
+
+/// Permanently remove at most `limit` items that were deleted before `deleted_before`,
+/// to keep each transaction of a garbage collection short.
+/// If clients are registered as `SyncClient` items, deletions that some client
+/// has not acknowledged yet are kept, for it to still learn about them.
+pub fn purge_deleted_items_tx(
+    tx: &Tx,
+    schema: &mut Schema,
+    deleted_before: DbTime,
+    limit: u64,
+) -> Result<GarbageCollectionReport> {
+    let mut report = GarbageCollectionReport::default();
+    let safe_change_seq = database_api::get_min_acknowledged_change_seq(tx)?.unwrap_or(i64::MAX);
+    let ids = database_api::get_deleted_item_ids(tx, deleted_before, safe_change_seq, limit)?;
+    report.finished = (ids.len() as u64) < limit;
+    for id in ids {
+        // An item could have already been removed as an edge of an item purged before
+        if database_api::get_item_rowid(tx, &id)?.is_none() {
+            continue;
+        }
+        let orphaned_files = internal_api::purge_item_tx(tx, schema, &id)?;
+        report.orphaned_files.extend(orphaned_files);
+        report.purged_items += 1;
+    }
+    info!(
+        "Garbage collection purged {} deleted items and {} files",
+        report.purged_items,
+        report.orphaned_files.len(),
+    );
+    Ok(report)
+}
+
+/// Forget operations of client operation logs that were applied before `applied_before`
+pub fn forget_applied_operations_tx(tx: &Tx, applied_before: DbTime) -> Result<usize> {
+    let forgotten = database_api::delete_applied_operations(tx, applied_before)?;
+    info!("Garbage collection forgot {} applied operations", forgotten);
+    Ok(forgotten)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api_model::AcknowledgeChanges;
+    use crate::api_model::CreateItem;
+    use crate::command_line_interface;
+    use crate::constants::REVISION_AUTHOR_CLIENT;
+    use crate::database_api::tests::new_conn;
+    use crate::plugin_auth_crypto::DatabaseKey;
+    use serde_json::json;
+
+    #[test]
+    fn test_purge_deleted_items() -> Result<()> {
+        let mut conn = new_conn();
+        let tx = conn.transaction()?;
+        let cli = command_line_interface::tests::test_cli();
+        let database_key = DatabaseKey::from("".to_string())?;
+        let mut schema = database_api::get_schema(&tx)?;
+        let mut create = |json: serde_json::Value| -> Result<String> {
+            let item: CreateItem = serde_json::from_value(json)?;
+            internal_api::create_item_tx(&tx, &mut schema, item, "", &cli, &database_key)
+        };
+        let kept = create(json!({"type": "Person"}))?;
+        let deleted = create(json!({"type": "Person"}))?;
+        let mut schema = database_api::get_schema(&tx)?;
+        internal_api::delete_item_tx(&tx, &mut schema, &deleted, REVISION_AUTHOR_CLIENT)?;
+
+        let report = purge_deleted_items_tx(&tx, &mut schema, 0, 10)?;
+        assert_eq!(report.purged_items, 0);
+
+        // A client that hasn't synced the deletion yet keeps it
+        let deletion = database_api::get_last_change_seq(&tx)?;
+        let mut acknowledge = |change_seq: i64| -> Result<()> {
+            let query = AcknowledgeChanges {
+                client_id: "phone-client".to_string(),
+                change_seq,
+                name: None,
+            };
+            internal_api::acknowledge_changes_tx(&tx, &mut schema, query, "", &cli, &database_key)?;
+            Ok(())
+        };
+        acknowledge(deletion - 1)?;
+        let mut schema = database_api::get_schema(&tx)?;
+        let report = purge_deleted_items_tx(&tx, &mut schema, DbTime::MAX, 10)?;
+        assert_eq!(report.purged_items, 0);
+        acknowledge(deletion)?;
+        let mut schema = database_api::get_schema(&tx)?;
+        let report = purge_deleted_items_tx(&tx, &mut schema, DbTime::MAX, 10)?;
+        assert_eq!(report.purged_items, 1);
+        assert!(database_api::get_item_rowid(&tx, &deleted)?.is_none());
+        assert!(database_api::get_item_rowid(&tx, &kept)?.is_some());
+
+        // Schema items are never purged, other items are purged in batches
+        let mut schema = database_api::get_schema(&tx)?;
+        let mut delete = |json: serde_json::Value| -> Result<String> {
+            let item: CreateItem = serde_json::from_value(json)?;
+            let id = internal_api::create_item_tx(&tx, &mut schema, item, "", &cli, &database_key)?;
+            internal_api::delete_item_tx(&tx, &mut schema, &id, REVISION_AUTHOR_CLIENT)?;
+            Ok(id)
+        };
+        let type_schema = delete(json!({"type": "ItemTypeSchema", "itemType": "Note"}))?;
+        let property_schema = delete(json!({"type": "ItemPropertySchema", "itemType": "Note",
+            "propertyName": "title", "valueType": "Text"}))?;
+        delete(json!({"type": "Person"}))?;
+        delete(json!({"type": "Person"}))?;
+        acknowledge(database_api::get_last_change_seq(&tx)?)?;
+        let mut schema = database_api::get_schema(&tx)?;
+        let report = purge_deleted_items_tx(&tx, &mut schema, DbTime::MAX, 1)?;
+        assert_eq!((report.purged_items, report.finished), (1, false));
+        let report = purge_deleted_items_tx(&tx, &mut schema, DbTime::MAX, 10)?;
+        assert_eq!((report.purged_items, report.finished), (1, true));
+        assert!(database_api::get_item_rowid(&tx, &type_schema)?.is_some());
+        assert!(database_api::get_item_rowid(&tx, &property_schema)?.is_some());
+        Ok(())
+    }
+}
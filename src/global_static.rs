@@ -5,8 +5,13 @@
 // * situations where impact on mock-ability is really zero,
 //     and there are clear maintenance/performance benefits of doing so
 
+use crate::change_events::ChangeEvent;
 use chacha20poly1305::XChaCha20Poly1305;
 use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Instant;
+use tokio::sync::broadcast;
 
 lazy_static! {
     /// Global/static Cipher used for PluginAuth.
@@ -17,3 +22,19 @@ lazy_static! {
     pub static ref CIPHER: XChaCha20Poly1305 =
         crate::plugin_auth_crypto::generate_xchacha20poly1305_cipher();
 }
+
+lazy_static! {
+    /// Last time garbage collection was started for each owner's database.
+    /// Databases can only be opened during requests (when the key is known),
+    /// so this is tracked across requests to not run garbage collection on every one.
+    pub static ref GARBAGE_COLLECTION_LAST_RUN: Mutex<HashMap<String, Instant>> =
+        Mutex::new(HashMap::new());
+}
+
+lazy_static! {
+    /// Channels of change events for each owner's database, see `change_events`.
+    /// Changes are committed by request handlers, and need to reach the subscribers
+    /// that are connected in other requests.
+    pub static ref CHANGE_EVENTS: Mutex<HashMap<String, broadcast::Sender<ChangeEvent>>> =
+        Mutex::new(HashMap::new());
+}
@@ -1,32 +1,58 @@
+use crate::api_model::AcknowledgeChanges;
+use crate::api_model::ApplyOperations;
 use crate::api_model::Bulk;
+use crate::api_model::BulkEntries;
+use crate::api_model::BulkMode;
+use crate::api_model::BulkOperation;
+use crate::api_model::ChangesSince;
 use crate::api_model::CreateEdge;
 use crate::api_model::CreateItem;
 use crate::api_model::EdgeDirection;
+use crate::api_model::ExportSchema;
 use crate::api_model::GetEdges;
+use crate::api_model::GetItems;
+use crate::api_model::GetRevisions;
+use crate::api_model::LoggedOperation;
+use crate::api_model::RestoreRevision;
 use crate::api_model::Search;
 use crate::api_model::SortOrder;
+use crate::api_model::UpdateItem;
 use crate::command_line_interface::CliOptions;
+use crate::constants;
+use crate::constants::REVISION_AUTHOR_CLIENT;
 use crate::database_api;
 use crate::database_api::get_incoming_edges;
 use crate::database_api::get_outgoing_edges;
 use crate::database_api::DatabaseSearch;
+use crate::database_api::DbTime;
 use crate::database_api::EdgePointer;
+use crate::database_api::ItemBase;
 use crate::database_api::Rowid;
 use crate::database_utils::add_item_edge_properties;
+use crate::database_utils::apply_property_defaults;
+use crate::database_utils::apply_property_operations;
 use crate::database_utils::check_item_has_all_properties;
+use crate::database_utils::check_no_computed_properties;
+use crate::database_utils::check_property_rules;
+use crate::database_utils::check_unique_properties_free;
+use crate::database_utils::get_item_properties;
 use crate::database_utils::insert_property;
 use crate::database_utils::item_base_to_json;
+use crate::database_utils::update_computed_properties;
 use crate::error::Error;
 use crate::error::Result;
 use crate::plugin_auth_crypto::DatabaseKey;
 use crate::schema;
 use crate::schema::validate_property_name;
 use crate::schema::Schema;
+use crate::schema_export;
+use crate::sync_scope;
 use crate::triggers;
 use chrono::Utc;
 use log::info;
 use rand::Rng;
 use rusqlite::Transaction as Tx;
+use serde::de::DeserializeOwned;
 use serde_json::Value;
 use std::collections::HashMap;
 use std::str;
@@ -58,6 +84,186 @@ pub fn get_item_tx(tx: &Tx, schema: &Schema, id: &str) -> Result<Vec<Value>> {
     Ok(result)
 }
 
+/// Get items by their ids, in the requested order.
+/// Ids that do not exist are reported in `missing`.
+pub fn get_items_tx(tx: &Tx, schema: &Schema, query: GetItems) -> Result<Value> {
+    info!("Getting {} items by id", query.ids.len());
+    let bases = database_api::get_item_bases_by_ids(tx, &query.ids)?;
+    let mut items = Vec::with_capacity(query.ids.len());
+    let mut missing = Vec::new();
+    for id in query.ids {
+        // The same id can be requested more than once
+        let base = match bases.get(&id) {
+            Some(base) => base.clone(),
+            None => {
+                missing.push(id);
+                continue;
+            }
+        };
+        let rowid = base.rowid;
+        let mut object_map = item_base_to_json(tx, base, schema)?;
+        add_item_edge_properties(tx, &mut object_map, rowid)?;
+        items.push(Value::Object(object_map));
+    }
+    Ok(serde_json::json!({ "items": items, "missing": missing }))
+}
+
+enum Change {
+    Item(ItemBase),
+    Purged(String),
+}
+
+/// Items (including edges) created, updated or deleted after the change sequence number
+/// `since`, in the order the changes were committed.
+/// Items that were purged are returned as `{"id": ..., "purged": true}`.
+///
+/// The returned `changeSeq` is to be used as `since` of the next request.
+pub fn changes_since(tx: &Tx, schema: &Schema, query: ChangesSince) -> Result<Value> {
+    if let Some(client_id) = query.client_id.clone() {
+        if let Some(scope) = sync_scope::get_sync_scope(tx, &client_id)? {
+            return sync_scope::changes_in_scope(tx, schema, &client_id, &scope, query);
+        }
+    }
+    info!("Getting changes since {}", query.since);
+    let limit = query.limit.saturating_add(1);
+    let changed = database_api::get_changes_since(tx, query.since, limit)?;
+    let purged = database_api::get_purged_items_since(tx, query.since, limit)?;
+    let mut changes: Vec<(i64, Change)> = changed
+        .into_iter()
+        .map(|(change_seq, base)| (change_seq, Change::Item(base)))
+        .chain(
+            purged
+                .into_iter()
+                .map(|(change_seq, id)| (change_seq, Change::Purged(id))),
+        )
+        .collect();
+    changes.sort_by_key(|(change_seq, _)| *change_seq);
+    let has_more = changes.len() as u64 > query.limit;
+    changes.truncate(query.limit as usize);
+    let change_seq = match changes.last() {
+        Some((change_seq, _)) => *change_seq,
+        None => query.since.max(database_api::get_last_change_seq(tx)?),
+    };
+    let mut items = Vec::with_capacity(changes.len());
+    for (_, change) in changes {
+        match change {
+            Change::Item(base) => {
+                let rowid = base.rowid;
+                let mut object_map = item_base_to_json(tx, base, schema)?;
+                add_item_edge_properties(tx, &mut object_map, rowid)?;
+                items.push(Value::Object(object_map));
+            }
+            Change::Purged(id) => items.push(serde_json::json!({"id": id, "purged": true})),
+        }
+    }
+    Ok(serde_json::json!({
+        "items": items,
+        "changeSeq": change_seq,
+        "hasMore": has_more,
+    }))
+}
+
+/// Record that a client has synced the changes up to `changeSeq`, see `garbage_collection`.
+/// The client's `SyncClient` item is created on its first acknowledgement,
+/// and registered again if it was deleted.
+/// Acknowledgements never move backwards.
+pub fn acknowledge_changes_tx(
+    tx: &Tx,
+    schema: &mut Schema,
+    query: AcknowledgeChanges,
+    pod_owner: &str,
+    cli: &CliOptions,
+    database_key: &DatabaseKey,
+) -> Result<Value> {
+    let AcknowledgeChanges {
+        client_id,
+        change_seq,
+        name,
+    } = query;
+    info!(
+        "Acknowledging change {} for client {}",
+        change_seq, client_id
+    );
+    let last_change_seq = database_api::get_last_change_seq(tx)?;
+    if change_seq > last_change_seq {
+        return Err(Error {
+            code: StatusCode::BAD_REQUEST,
+            msg: format!(
+                "Cannot acknowledge change {}, the last change is {}",
+                change_seq, last_change_seq
+            ),
+        });
+    }
+    let date = Utc::now().timestamp_millis();
+    let mut fields = HashMap::new();
+    fields.insert("dateAcked".to_string(), Value::from(date));
+    if let Some(name) = name {
+        fields.insert("name".to_string(), Value::from(name));
+    }
+    let rowid = database_api::get_item_rowid(tx, &client_id)?;
+    let base = match rowid {
+        Some(rowid) => database_api::get_item_base(tx, rowid)?,
+        None => None,
+    };
+    let acked_change_seq = match base {
+        None => {
+            fields.insert("ackedChangeSeq".to_string(), Value::from(change_seq));
+            let item = CreateItem {
+                rowid: None,
+                id: Some(client_id),
+                _type: constants::SYNC_CLIENT_ITEM_TYPE.to_string(),
+                date_created: None,
+                date_modified: None,
+                deleted: false,
+                fields,
+            };
+            create_item_tx(tx, schema, item, pod_owner, cli, database_key)?;
+            change_seq
+        }
+        Some(base) if base._type != constants::SYNC_CLIENT_ITEM_TYPE => {
+            return Err(Error {
+                code: StatusCode::BAD_REQUEST,
+                msg: format!(
+                    "Item {} is not a {}",
+                    base.id,
+                    constants::SYNC_CLIENT_ITEM_TYPE
+                ),
+            })
+        }
+        Some(base) => {
+            let props = get_item_properties(tx, base.rowid, schema)?;
+            let acked_change_seq = props
+                .get("ackedChangeSeq")
+                .and_then(Value::as_i64)
+                .map_or(change_seq, |acked| acked.max(change_seq));
+            // Written without a new change: the client would otherwise receive
+            // its own acknowledgement as a change, and acknowledge that forever
+            for (name, value) in [("ackedChangeSeq", acked_change_seq), ("dateAcked", date)] {
+                database_api::delete_property(tx, base.rowid, name)?;
+                database_api::insert_integer(tx, base.rowid, name, value)?;
+            }
+            fields.remove("dateAcked");
+            fields.retain(|name, value| props.get(name) != Some(value));
+            if base.deleted {
+                fields.insert("deleted".to_string(), Value::from(false));
+            }
+            if !fields.is_empty() {
+                let update = UpdateItem {
+                    id: client_id,
+                    if_date_server_modified: None,
+                    fields,
+                };
+                update_item_tx(tx, schema, update, REVISION_AUTHOR_CLIENT)?;
+            }
+            acked_change_seq
+        }
+    };
+    Ok(serde_json::json!({
+        "ackedChangeSeq": acked_change_seq,
+        "dateAcked": date,
+    }))
+}
+
 /// Generate a new random item id.
 /// This implementation chooses to generate 32 random hex characters.
 pub fn new_random_item_id() -> String {
@@ -77,7 +283,7 @@ const HEX_CHARACTERS: &[u8] = b"0123456789abcdef";
 pub fn create_item_tx(
     tx: &Tx,
     schema: &mut Schema,
-    item: CreateItem,
+    mut item: CreateItem,
     pod_owner: &str,
     cli: &CliOptions,
     database_key: &DatabaseKey,
@@ -87,9 +293,7 @@ pub fn create_item_tx(
     } else {
         new_random_item_id()
     };
-    if let Err(err) = schema::validate_create_item_id(&id) {
-        return Err(err);
-    }
+    schema::validate_create_item_id(&id)?;
     let time_now = Utc::now().timestamp_millis();
     let _is_new_schema = triggers::add_item_as_schema_opt(schema, &item)?;
     let rowid = database_api::insert_item_base(
@@ -101,9 +305,20 @@ pub fn create_item_tx(
         time_now,
         item.deleted,
     )?;
+    check_no_computed_properties(schema, &item._type, &item.fields)?;
+    apply_property_defaults(schema, &item._type, &mut item.fields);
+    check_property_rules(tx, schema, &item._type, rowid, &item.fields, true)?;
+    // Properties set at creation are modified as well, for `update_item_tx` to resolve
+    // offline edits that are older than the item
+    let date_modified = item.date_modified.unwrap_or(time_now);
     for (prop_name, prop_value) in &item.fields {
         insert_property(tx, schema, rowid, prop_name, prop_value)?;
+        database_api::set_property_date_modified(tx, rowid, prop_name, date_modified)?;
+    }
+    if item.deleted {
+        database_api::set_property_date_modified(tx, rowid, "deleted", date_modified)?;
     }
+    update_computed_properties(tx, schema, &item._type, rowid)?;
     triggers::trigger_after_item_create(
         tx,
         schema,
@@ -117,20 +332,29 @@ pub fn create_item_tx(
     Ok(id)
 }
 
+/// Update an item. `author` is the one making the change ("client" or the plugin),
+/// it is recorded in the item's revision history if the item type keeps revisions.
+///
+/// Concurrent edits are resolved per property, the last writer wins:
+/// properties (and `deleted`) that were modified after the `dateModified` sent by the client
+/// are not written, and are returned in `overriddenFields`.
+/// Updates without `dateModified` are considered to happen now, and always win.
 pub fn update_item_tx(
     tx: &Tx,
-    schema: &Schema,
-    id: &str,
-    mut fields: HashMap<String, Value>,
-) -> Result<()> {
+    schema: &mut Schema,
+    item: UpdateItem,
+    author: &str,
+) -> Result<Value> {
+    let id = item.id.as_str();
+    let mut fields = item.fields;
     log::debug!("Updating item {}", id);
     fields.remove("type");
     fields.remove("dateCreated");
 
-    let time_now = Utc::now().timestamp_millis();
-    let date_modified = if let Some(dm) = fields.remove("dateModified") {
+    let mut time_now = Utc::now().timestamp_millis();
+    let client_date_modified = if let Some(dm) = fields.remove("dateModified") {
         if let Some(dm) = dm.as_i64() {
-            dm
+            Some(dm)
         } else {
             return Err(Error {
                 code: StatusCode::BAD_REQUEST,
@@ -138,10 +362,11 @@ pub fn update_item_tx(
             });
         }
     } else {
-        time_now
+        None
     };
+    let date_modified = client_date_modified.unwrap_or(time_now);
     fields.remove("dateServerModified");
-    let deleted = if let Some(d) = fields.remove("deleted") {
+    let mut deleted = if let Some(d) = fields.remove("deleted") {
         match d {
             Value::Null => None,
             Value::Bool(d) => Some(d),
@@ -162,18 +387,570 @@ pub fn update_item_tx(
         code: StatusCode::NOT_FOUND,
         msg: format!("Item with id {} not found", id),
     })?;
-    database_api::update_item_base(tx, rowid, date_modified, time_now, deleted)?;
+    let base = database_api::get_item_base(tx, rowid)?.ok_or_else(|| Error {
+        code: StatusCode::NOT_FOUND,
+        msg: format!("Item with id {} not found", id),
+    })?;
+    if let Some(expected) = item.if_date_server_modified {
+        if base.date_server_modified != expected {
+            return Err(Error {
+                code: StatusCode::CONFLICT,
+                msg: format!(
+                    "Item {} was modified on the server at {}, expected dateServerModified {}",
+                    id, base.date_server_modified, expected
+                ),
+            });
+        }
+    }
+    let mut overridden = Vec::new();
+    if let Some(date_modified) = client_date_modified {
+        let dates = database_api::get_property_dates_modified(tx, rowid)?;
+        let is_stale = |name: &str| {
+            dates
+                .get(name)
+                .map(|date| *date > date_modified)
+                .unwrap_or(false)
+        };
+        fields.retain(|name, value| {
+            // Operations like `$increment` build on the current value, they are never stale
+            let keep = value.is_object() || !is_stale(name);
+            if !keep {
+                overridden.push(name.to_string());
+            }
+            keep
+        });
+        if deleted.is_some() && is_stale("deleted") {
+            deleted = None;
+            overridden.push("deleted".to_string());
+        }
+        overridden.sort();
+        if !overridden.is_empty() && fields.is_empty() && deleted.is_none() {
+            return Ok(serde_json::json!({ "overriddenFields": overridden }));
+        }
+    }
+    // Every change moves dateServerModified forward,
+    // even if two changes happen within the same millisecond
+    time_now = time_now.max(base.date_server_modified + 1);
+    let item_date_modified = base.date_modified.max(date_modified);
+    let item_type = base._type.clone();
+    if schema.revision_item_types.contains(&item_type) {
+        let date_server_modified = base.date_server_modified;
+        let content = Value::Object(item_base_to_json(tx, base, schema)?).to_string();
+        database_api::insert_revision(tx, rowid, date_server_modified, time_now, author, &content)?;
+    }
+    check_no_computed_properties(schema, &item_type, &fields)?;
+    apply_property_operations(tx, schema, rowid, &mut fields)?;
+    check_property_rules(tx, schema, &item_type, rowid, &fields, false)?;
+    database_api::update_item_base(tx, rowid, item_date_modified, time_now, deleted)?;
     for (k, v) in &fields {
         insert_property(tx, schema, rowid, k, v)?;
+        database_api::set_property_date_modified(tx, rowid, k, date_modified)?;
+    }
+    if deleted.is_some() {
+        database_api::set_property_date_modified(tx, rowid, "deleted", date_modified)?;
+    }
+    if !fields.is_empty() {
+        update_computed_properties(tx, schema, &item_type, rowid)?;
+    }
+    let is_schema = item_type == "ItemPropertySchema" || item_type == "ItemTypeSchema";
+    if is_schema && (!fields.is_empty() || deleted.is_some()) {
+        // Keep the in-memory Schema consistent with the changed Schema item
+        *schema = database_api::get_schema(tx)?;
+    }
+    if overridden.is_empty() {
+        Ok(serde_json::json!({}))
+    } else {
+        Ok(serde_json::json!({ "overriddenFields": overridden }))
     }
-    Ok(())
 }
 
-pub fn delete_item_tx(tx: &Tx, schema: &Schema, id: &str) -> Result<()> {
+pub fn delete_item_tx(tx: &Tx, schema: &mut Schema, id: &str, author: &str) -> Result<()> {
     log::debug!("Deleting item {}", id);
     let mut fields = HashMap::new();
     fields.insert("deleted".to_string(), true.into());
-    update_item_tx(tx, schema, id, fields)
+    let item = UpdateItem {
+        id: id.to_string(),
+        if_date_server_modified: None,
+        fields,
+    };
+    update_item_tx(tx, schema, item, author)?;
+    Ok(())
+}
+
+pub fn restore_item_tx(tx: &Tx, schema: &mut Schema, id: &str, author: &str) -> Result<()> {
+    log::debug!("Restoring item {}", id);
+    let rowid = database_api::get_item_rowid(tx, id)?.ok_or_else(|| Error {
+        code: StatusCode::NOT_FOUND,
+        msg: format!("Item with id {} not found", id),
+    })?;
+    if let Some(base) = database_api::get_item_base(tx, rowid)? {
+        check_unique_properties_free(tx, schema, &base._type, rowid)?;
+    }
+    let mut fields = HashMap::new();
+    fields.insert("deleted".to_string(), false.into());
+    let item = UpdateItem {
+        id: id.to_string(),
+        if_date_server_modified: None,
+        fields,
+    };
+    update_item_tx(tx, schema, item, author)?;
+    Ok(())
+}
+
+/// Permanently remove an item, see `database_api::dangerous_purge_item`.
+///
+/// Returns the sha256 hashes of files that are no longer referenced by any item.
+/// The caller is responsible for removing them from disk once the transaction is committed.
+pub fn purge_item_tx(tx: &Tx, schema: &mut Schema, id: &str) -> Result<Vec<String>> {
+    info!("Purging item {}", id);
+    let rowid = database_api::get_item_rowid(tx, id)?.ok_or_else(|| Error {
+        code: StatusCode::NOT_FOUND,
+        msg: format!("Item with id {} not found", id),
+    })?;
+    let item_type = database_api::get_item_base(tx, rowid)?
+        .map(|base| base._type)
+        .unwrap_or_default();
+    let sha256 = database_api::get_strings_for_item(tx, rowid)?.remove("sha256");
+    database_api::dangerous_purge_item(tx, rowid)?;
+    if item_type == "ItemPropertySchema" || item_type == "ItemTypeSchema" {
+        *schema = database_api::get_schema(tx)?;
+    }
+    let mut orphaned_files = Vec::new();
+    if let Some(sha256) = sha256 {
+        if database_api::search_strings(tx, "sha256", &sha256)?.is_empty() {
+            orphaned_files.push(sha256);
+        }
+    }
+    Ok(orphaned_files)
+}
+
+/// Perform a bulk request, see `BulkMode` for how failures are handled
+#[allow(clippy::too_many_arguments)]
+pub fn bulk_entries_tx(
+    tx: &Tx,
+    schema: &mut Schema,
+    entries: BulkEntries,
+    pod_owner: &str,
+    cli: &CliOptions,
+    database_key: &DatabaseKey,
+    author: &str,
+) -> Result<Value> {
+    let mut entries = entries;
+    let references = resolve_bulk_references(&mut entries)?;
+    let mut result = if !entries.operations.is_empty() {
+        let is_grouped = !entries.create_items.is_empty()
+            || !entries.update_items.is_empty()
+            || !entries.delete_items.is_empty()
+            || !entries.create_edges.is_empty()
+            || !entries.search.is_empty();
+        if is_grouped {
+            return Err(Error {
+                code: StatusCode::BAD_REQUEST,
+                msg: "Bulk operations cannot be combined with createItems, updateItems, \
+                    deleteItems, createEdges or search"
+                    .to_string(),
+            });
+        }
+        bulk_operations_tx(tx, schema, entries, pod_owner, cli, database_key, author)
+    } else {
+        match entries.mode {
+            BulkMode::Atomic => {
+                let bulk = Bulk {
+                    create_items: parse_bulk_entries("createItems", entries.create_items)?,
+                    update_items: parse_bulk_entries("updateItems", entries.update_items)?,
+                    delete_items: parse_bulk_entries("deleteItems", entries.delete_items)?,
+                    create_edges: parse_bulk_entries("createEdges", entries.create_edges)?,
+                    search: parse_bulk_entries("search", entries.search)?,
+                };
+                bulk_tx(tx, schema, bulk, pod_owner, cli, database_key, author)
+            }
+            BulkMode::Partial => {
+                bulk_partial_tx(tx, schema, entries, pod_owner, cli, database_key, author)
+            }
+        }
+    }?;
+    if !references.is_empty() {
+        result["references"] = serde_json::to_value(references)?;
+    }
+    Ok(result)
+}
+
+/// Resolve temporary references between entries of a bulk request.
+///
+/// An entry of `createItems` can declare a reference with `"$ref": "name"`
+/// (an id is generated for the item if it does not have one),
+/// and any value `{"$ref": "name"}` elsewhere in the request is replaced by the item's id.
+/// Returns the ids of the declared references.
+fn resolve_bulk_references(entries: &mut BulkEntries) -> Result<HashMap<String, String>> {
+    let mut references = HashMap::new();
+    let create_items = entries
+        .create_items
+        .iter_mut()
+        .enumerate()
+        .map(|(index, item)| (format!("createItems[{}]", index), item));
+    let create_operations =
+        entries
+            .operations
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(index, operation)| {
+                let item = operation.get_mut("createItem")?;
+                Some((format!("operations[{}].createItem", index), item))
+            });
+    for (path, item) in create_items.chain(create_operations) {
+        let item = match item.as_object_mut() {
+            Some(item) => item,
+            None => continue,
+        };
+        let reference = match item.remove("$ref") {
+            Some(Value::String(reference)) => reference,
+            Some(other) => {
+                return Err(Error {
+                    code: StatusCode::BAD_REQUEST,
+                    msg: format!("Reference {} is not a string at {}", other, path),
+                })
+            }
+            None => continue,
+        };
+        let id = match item.get("id") {
+            Some(Value::String(id)) => id.to_string(),
+            _ => {
+                let id = new_random_item_id();
+                item.insert("id".to_string(), Value::String(id.clone()));
+                id
+            }
+        };
+        if references.insert(reference.clone(), id).is_some() {
+            return Err(Error {
+                code: StatusCode::BAD_REQUEST,
+                msg: format!("Reference {} declared twice, at {}", reference, path),
+            });
+        }
+    }
+    let fields = [
+        ("createItems", &mut entries.create_items),
+        ("updateItems", &mut entries.update_items),
+        ("deleteItems", &mut entries.delete_items),
+        ("createEdges", &mut entries.create_edges),
+        ("search", &mut entries.search),
+        ("operations", &mut entries.operations),
+    ];
+    for (field, values) in fields {
+        for (index, value) in values.iter_mut().enumerate() {
+            replace_references(value, &references).map_err(|msg| Error {
+                code: StatusCode::BAD_REQUEST,
+                msg: format!("{} at {}[{}]", msg, field, index),
+            })?;
+        }
+    }
+    Ok(references)
+}
+
+fn replace_references(
+    value: &mut Value,
+    references: &HashMap<String, String>,
+) -> std::result::Result<(), String> {
+    match value {
+        Value::Object(object) if object.len() == 1 && object.contains_key("$ref") => {
+            let reference = object.get("$ref").and_then(|r| r.as_str()).unwrap_or("");
+            let id = references
+                .get(reference)
+                .ok_or_else(|| format!("Unknown reference {}", object["$ref"]))?;
+            *value = Value::String(id.to_string());
+        }
+        Value::Object(object) => {
+            for value in object.values_mut() {
+                replace_references(value, references)?;
+            }
+        }
+        Value::Array(array) => {
+            for value in array {
+                replace_references(value, references)?;
+            }
+        }
+        _ => (),
+    }
+    Ok(())
+}
+
+/// Failure of a single bulk entry, with the JSON path to what failed
+struct BulkEntryError {
+    error: Error,
+    path: String,
+}
+
+impl BulkEntryError {
+    fn to_json(&self) -> Value {
+        serde_json::json!({
+            "error": {
+                "code": self.error.code.as_u16(),
+                "message": self.error.msg,
+                "path": self.path,
+            }
+        })
+    }
+}
+
+fn parse_bulk_entry<T: DeserializeOwned>(
+    field: &str,
+    index: usize,
+    entry: Value,
+) -> std::result::Result<T, BulkEntryError> {
+    serde_path_to_error::deserialize(entry).map_err(|err| {
+        let path = err.path().to_string();
+        let path = if path == "." {
+            format!("{}[{}]", field, index)
+        } else {
+            format!("{}[{}].{}", field, index, path)
+        };
+        BulkEntryError {
+            error: Error {
+                code: StatusCode::BAD_REQUEST,
+                msg: format!("JSON deserialization error {}", err.inner()),
+            },
+            path,
+        }
+    })
+}
+
+fn parse_bulk_entries<T: DeserializeOwned>(field: &str, entries: Vec<Value>) -> Result<Vec<T>> {
+    let mut result = Vec::with_capacity(entries.len());
+    for (index, entry) in entries.into_iter().enumerate() {
+        let entry = parse_bulk_entry(field, index, entry).map_err(|err| Error {
+            code: err.error.code,
+            msg: format!("{} at {}", err.error.msg, err.path),
+        })?;
+        result.push(entry);
+    }
+    Ok(result)
+}
+
+/// Name the failed entry of an atomic bulk request in its error, like parse errors do
+fn at_bulk_entry<T>(field: &str, index: usize, result: Result<T>) -> Result<T> {
+    result.map_err(|err| Error {
+        code: err.code,
+        msg: format!("{} at {}[{}]", err.msg, field, index),
+    })
+}
+
+/// Perform each entry of a bulk request in its own savepoint.
+/// The results are in the same order as the entries,
+/// failed entries are reported as `{"error": {"code": ..., "message": ..., "path": ...}}`.
+#[allow(clippy::too_many_arguments)]
+fn bulk_partial_tx(
+    tx: &Tx,
+    schema: &mut Schema,
+    entries: BulkEntries,
+    pod_owner: &str,
+    cli: &CliOptions,
+    database_key: &DatabaseKey,
+    author: &str,
+) -> Result<Value> {
+    info!(
+        "Performing partial bulk action with {} new items, {} updated items, {} deleted items, {} created edges",
+        entries.create_items.len(),
+        entries.update_items.len(),
+        entries.delete_items.len(),
+        entries.create_edges.len(),
+    );
+    let mut created_items = Vec::new();
+    for (index, entry) in entries.create_items.into_iter().enumerate() {
+        let result = run_bulk_entry(tx, schema, "createItems", index, entry, |schema, item| {
+            let id = create_item_tx(tx, schema, item, pod_owner, cli, database_key)?;
+            Ok(Value::String(id))
+        })?;
+        created_items.push(result);
+    }
+    let mut updated_items = Vec::new();
+    for (index, entry) in entries.update_items.into_iter().enumerate() {
+        let result = run_bulk_entry(tx, schema, "updateItems", index, entry, |schema, item| {
+            update_item_tx(tx, schema, item, author)
+        })?;
+        updated_items.push(result);
+    }
+    let mut deleted_items = Vec::new();
+    for (index, entry) in entries.delete_items.into_iter().enumerate() {
+        let result = run_bulk_entry(tx, schema, "deleteItems", index, entry, |schema, id| {
+            let id: String = id;
+            delete_item_tx(tx, schema, &id, author)?;
+            Ok(serde_json::json!({}))
+        })?;
+        deleted_items.push(result);
+    }
+    let mut created_edges = Vec::new();
+    for (index, entry) in entries.create_edges.into_iter().enumerate() {
+        let result = run_bulk_entry(tx, schema, "createEdges", index, entry, |_, edge| {
+            Ok(Value::String(create_edge(tx, edge)?))
+        })?;
+        created_edges.push(result);
+    }
+    let mut search_results = Vec::new();
+    for (index, entry) in entries.search.into_iter().enumerate() {
+        let result = run_bulk_entry(tx, schema, "search", index, entry, |schema, query| {
+            Ok(Value::Array(search(tx, schema, query)?))
+        })?;
+        search_results.push(result);
+    }
+    let result = serde_json::json!({
+        "createItems": created_items,
+        "updateItems": updated_items,
+        "deleteItems": deleted_items,
+        "createEdges": created_edges,
+        "search": search_results,
+    });
+    Ok(result)
+}
+
+/// Perform bulk `operations` in the given order, see `BulkMode` for how failures are handled.
+/// The results are in the same order as the operations.
+#[allow(clippy::too_many_arguments)]
+fn bulk_operations_tx(
+    tx: &Tx,
+    schema: &mut Schema,
+    entries: BulkEntries,
+    pod_owner: &str,
+    cli: &CliOptions,
+    database_key: &DatabaseKey,
+    author: &str,
+) -> Result<Value> {
+    info!(
+        "Performing bulk action with {} operations",
+        entries.operations.len()
+    );
+    let mut results = Vec::with_capacity(entries.operations.len());
+    let perform = |schema: &mut Schema, operation| {
+        perform_bulk_operation(tx, schema, operation, pod_owner, cli, database_key, author)
+    };
+    match entries.mode {
+        BulkMode::Atomic => {
+            let operations: Vec<BulkOperation> =
+                parse_bulk_entries("operations", entries.operations)?;
+            for (index, operation) in operations.into_iter().enumerate() {
+                let result = perform(schema, operation);
+                results.push(at_bulk_entry("operations", index, result)?);
+            }
+        }
+        BulkMode::Partial => {
+            for (index, entry) in entries.operations.into_iter().enumerate() {
+                let result = run_bulk_entry(tx, schema, "operations", index, entry, perform)?;
+                results.push(result);
+            }
+        }
+    }
+    Ok(serde_json::json!({ "operations": results }))
+}
+
+fn perform_bulk_operation(
+    tx: &Tx,
+    schema: &mut Schema,
+    operation: BulkOperation,
+    pod_owner: &str,
+    cli: &CliOptions,
+    database_key: &DatabaseKey,
+    author: &str,
+) -> Result<Value> {
+    match operation {
+        BulkOperation::CreateItem(item) => {
+            let id = create_item_tx(tx, schema, item, pod_owner, cli, database_key)?;
+            Ok(Value::String(id))
+        }
+        BulkOperation::UpdateItem(item) => update_item_tx(tx, schema, item, author),
+        BulkOperation::DeleteItem(id) => {
+            delete_item_tx(tx, schema, &id, author)?;
+            Ok(serde_json::json!({}))
+        }
+        BulkOperation::CreateEdge(edge) => Ok(Value::String(create_edge(tx, edge)?)),
+        BulkOperation::Search(query) => Ok(Value::Array(search(tx, schema, query)?)),
+    }
+}
+
+/// Apply a client's operation log in order, each operation in its own savepoint.
+/// Operations that were applied before (by `op_id`) are not applied again,
+/// their stored result is returned instead, so clients can safely retry uploads.
+/// Failed operations are not remembered, and can be retried.
+/// Searches are not remembered either, as they change nothing and their results can be large.
+pub fn apply_operations_tx(
+    tx: &Tx,
+    schema: &mut Schema,
+    log: ApplyOperations,
+    pod_owner: &str,
+    cli: &CliOptions,
+    database_key: &DatabaseKey,
+    author: &str,
+) -> Result<Value> {
+    info!(
+        "Applying {} operations of client {}",
+        log.operations.len(),
+        log.client_id
+    );
+    let client_id = log.client_id;
+    let mut results = Vec::with_capacity(log.operations.len());
+    for (index, LoggedOperation { op_id, operation }) in log.operations.into_iter().enumerate() {
+        let remembered = !matches!(operation, BulkOperation::Search(_));
+        if remembered {
+            if let Some(result) = database_api::get_applied_operation(tx, &client_id, &op_id)? {
+                let result: Value = serde_json::from_str(&result)?;
+                let duplicate =
+                    serde_json::json!({"opId": op_id, "result": result, "duplicate": true});
+                results.push(duplicate);
+                continue;
+            }
+        }
+        let applied = database_api::in_savepoint(tx, || {
+            let result = perform_bulk_operation(
+                tx,
+                schema,
+                operation,
+                pod_owner,
+                cli,
+                database_key,
+                author,
+            )?;
+            if remembered {
+                let date = Utc::now().timestamp_millis();
+                let stored = result.to_string();
+                database_api::insert_applied_operation(tx, &client_id, &op_id, &stored, date)?;
+            }
+            Ok(result)
+        });
+        match applied {
+            Ok(result) => results.push(serde_json::json!({"opId": op_id, "result": result})),
+            Err(error) => {
+                // The in-memory Schema might have been changed by the rolled back operation
+                *schema = database_api::get_schema(tx)?;
+                let path = format!("operations[{}]", index);
+                let mut failure = BulkEntryError { error, path }.to_json();
+                failure["opId"] = Value::String(op_id);
+                results.push(failure);
+            }
+        }
+    }
+    Ok(serde_json::json!({ "operations": results }))
+}
+
+/// Parse and perform a single entry of a partial bulk request in a savepoint,
+/// returning either its result or the failure
+fn run_bulk_entry<T: DeserializeOwned>(
+    tx: &Tx,
+    schema: &mut Schema,
+    field: &str,
+    index: usize,
+    entry: Value,
+    func: impl FnOnce(&mut Schema, T) -> Result<Value>,
+) -> Result<Value> {
+    let entry: T = match parse_bulk_entry(field, index, entry) {
+        Ok(entry) => entry,
+        Err(err) => return Ok(err.to_json()),
+    };
+    match database_api::in_savepoint(tx, || func(schema, entry)) {
+        Ok(value) => Ok(value),
+        Err(error) => {
+            // The in-memory Schema might have been changed by the rolled back entry
+            *schema = database_api::get_schema(tx)?;
+            let path = format!("{}[{}]", field, index);
+            Ok(BulkEntryError { error, path }.to_json())
+        }
+    }
 }
 
 pub fn bulk_tx(
@@ -183,6 +960,7 @@ pub fn bulk_tx(
     pod_owner: &str,
     cli: &CliOptions,
     database_key: &DatabaseKey,
+    author: &str,
 ) -> Result<Value> {
     info!(
         "Performing bulk action with {} new items, {} updated items, {} deleted items, {} created edges",
@@ -192,28 +970,32 @@ pub fn bulk_tx(
         bulk.create_edges.len(),
     );
     let mut created_items = Vec::new();
-    for item in bulk.create_items {
-        let id = create_item_tx(tx, schema, item, pod_owner, cli, database_key)?;
-        created_items.push(id);
+    for (index, item) in bulk.create_items.into_iter().enumerate() {
+        let id = create_item_tx(tx, schema, item, pod_owner, cli, database_key);
+        created_items.push(at_bulk_entry("createItems", index, id)?);
     }
-    for item in bulk.update_items {
-        update_item_tx(tx, schema, &item.id, item.fields)?;
+    let mut updated_items = Vec::new();
+    for (index, item) in bulk.update_items.into_iter().enumerate() {
+        let result = update_item_tx(tx, schema, item, author);
+        updated_items.push(at_bulk_entry("updateItems", index, result)?);
     }
-    for item_id in bulk.delete_items {
-        delete_item_tx(tx, schema, &item_id)?;
+    for (index, item_id) in bulk.delete_items.into_iter().enumerate() {
+        let result = delete_item_tx(tx, schema, &item_id, author);
+        at_bulk_entry("deleteItems", index, result)?;
     }
     let mut created_edges = Vec::new();
-    for item_id in bulk.create_edges {
-        let id = create_edge(tx, item_id)?;
-        created_edges.push(id);
+    for (index, item_id) in bulk.create_edges.into_iter().enumerate() {
+        let id = create_edge(tx, item_id);
+        created_edges.push(at_bulk_entry("createEdges", index, id)?);
     }
     let mut search_results = Vec::new();
-    for query in bulk.search {
-        let result = search(tx, schema, query)?;
-        search_results.push(result);
+    for (index, query) in bulk.search.into_iter().enumerate() {
+        let result = search(tx, schema, query);
+        search_results.push(at_bulk_entry("search", index, result)?);
     }
     let result = serde_json::json!({
         "createItems": created_items,
+        "updateItems": updated_items,
         "createEdges": created_edges,
         "search": search_results,
     });
@@ -251,7 +1033,11 @@ pub fn create_edge(tx: &Tx, query: CreateEdge) -> Result<String> {
         msg: format!("Edge target not found: {}", target),
     })?;
     database_api::insert_edge(tx, self_rowid, source, &name, target)?;
-    database_api::update_item_date_server_modified(tx, source, date)?;
+    // Like any other change of the source item, it moves dateServerModified forward
+    if let Some(base) = database_api::get_item_base(tx, source)? {
+        let date = date.max(base.date_server_modified + 1);
+        database_api::update_item_date_server_modified(tx, source, date)?;
+    }
     Ok(self_id)
 }
 
@@ -317,6 +1103,30 @@ pub fn get_edges(tx: &Tx, query: GetEdges, schema: &Schema) -> Result<Vec<Value>
 }
 
 pub fn search(tx: &Tx, schema: &Schema, query: Search) -> Result<Vec<Value>> {
+    let mut result = Vec::new();
+    search_with(tx, schema, query, |item| {
+        result.push(item);
+        Ok(())
+    })?;
+    Ok(result)
+}
+
+/// How far `search_with` read the database, to continue a search from there
+#[derive(Debug, Default)]
+pub struct SearchProgress {
+    /// Items read from the database, including the ones without the searched properties
+    pub items_read: u64,
+    pub last_date_server_modified: Option<DbTime>,
+}
+
+/// Search items, passing each of the resulting items to `on_item` as soon as it is ready,
+/// so that large results can be streamed without keeping them in memory
+pub fn search_with<F: FnMut(Value) -> Result<()>>(
+    tx: &Tx,
+    schema: &Schema,
+    query: Search,
+    mut on_item: F,
+) -> Result<SearchProgress> {
     info!("Searching by fields {:?}", query);
     if !query.other_properties.is_empty() {
         log::trace!(
@@ -334,29 +1144,162 @@ pub fn search(tx: &Tx, schema: &Schema, query: Search) -> Result<Vec<Value>> {
         sort_order: query.sort_order,
         _limit: query.limit,
     };
-    let items = database_api::search_items(tx, &database_search)?;
-    let mut result = Vec::new();
-    for item in items {
+    let other_properties = &query.other_properties;
+    let forward_edges = query.forward_edges.is_some();
+    let backward_edges = query.backward_edges.is_some();
+    let mut progress = SearchProgress::default();
+    database_api::search_items_with(tx, &database_search, |item| {
+        progress.items_read += 1;
+        progress.last_date_server_modified = Some(item.date_server_modified);
         let rowid = item.rowid;
-        if check_item_has_all_properties(tx, schema, rowid, &query.other_properties)? {
+        if check_item_has_all_properties(tx, schema, rowid, other_properties)? {
             let mut object_map = item_base_to_json(tx, item, schema)?;
             add_item_edge_properties(tx, &mut object_map, rowid)?;
-            if query.forward_edges.is_some() {
+            if forward_edges {
                 let edges = get_outgoing_edges(tx, rowid)?;
                 let edges = edge_pointers_to_json(tx, schema, &edges)?;
                 object_map.insert("[[edges]]".to_string(), Value::Array(edges));
             }
-            if query.backward_edges.is_some() {
+            if backward_edges {
                 let edges = get_incoming_edges(tx, rowid)?;
                 let edges = edge_pointers_to_json(tx, schema, &edges)?;
                 object_map.insert("~[[edges]]".to_string(), Value::Array(edges));
             }
-            result.push(Value::Object(object_map));
+            on_item(Value::Object(object_map))?;
+        }
+        Ok(())
+    })?;
+    Ok(progress)
+}
+
+/// A search that is read in batches, each of them in its own transaction,
+/// so that streaming a large result to a slow client doesn't block writers meanwhile.
+/// Each batch continues after the `dateServerModified` of the previous one.
+pub struct SearchBatches {
+    query: Search,
+    /// Items left to read before reaching the `_limit` of the search
+    remaining: u64,
+    done: bool,
+}
+
+impl SearchBatches {
+    pub fn new(query: Search) -> SearchBatches {
+        SearchBatches {
+            remaining: query.limit,
+            query,
+            done: false,
         }
     }
+
+    /// The items of the next batch, `None` once the search is complete
+    pub fn next_batch(&mut self, tx: &Tx, schema: &Schema) -> Result<Option<Vec<Value>>> {
+        if self.done {
+            return Ok(None);
+        }
+        let limit = self.remaining.min(constants::SEARCH_STREAM_BATCH_SIZE);
+        let mut query = self.query.clone();
+        query.limit = limit;
+        let mut items = Vec::new();
+        let progress = search_with(tx, schema, query, |item| {
+            items.push(item);
+            Ok(())
+        })?;
+        // Items with the same `dateServerModified` as the last one are all in this batch,
+        // see `database_api::search_items_with`
+        match progress.last_date_server_modified {
+            Some(last) if progress.items_read >= limit && limit < self.remaining => {
+                self.remaining -= progress.items_read.min(self.remaining);
+                match self.query.sort_order {
+                    SortOrder::Asc => self.query.date_server_modified_gte = Some(last + 1),
+                    SortOrder::Desc => self.query.date_server_modified_lt = Some(last),
+                }
+            }
+            _ => self.done = true,
+        }
+        Ok(Some(items))
+    }
+}
+
+pub fn get_revisions(tx: &Tx, query: GetRevisions) -> Result<Vec<Value>> {
+    info!("Getting revisions of item {}", query.id);
+    let rowid = database_api::get_item_rowid(tx, &query.id)?.ok_or_else(|| Error {
+        code: StatusCode::NOT_FOUND,
+        msg: format!("Item with id {} not found", query.id),
+    })?;
+    let mut result = Vec::new();
+    for revision in database_api::get_revisions(tx, rowid)? {
+        let item: Value = serde_json::from_str(&revision.content)?;
+        result.push(serde_json::json!({
+            "revision": revision.rowid,
+            "dateServerModified": revision.date_server_modified,
+            "dateRecorded": revision.date_recorded,
+            "author": revision.author,
+            "item": item,
+        }));
+    }
     Ok(result)
 }
 
+/// Restore an item to the state it had in a past revision.
+/// The restoration is itself a change of the item, so it's recorded as a new revision
+/// and can be reverted in the same way.
+pub fn restore_revision(
+    tx: &Tx,
+    schema: &mut Schema,
+    query: RestoreRevision,
+    author: &str,
+) -> Result<()> {
+    info!("Restoring item {} to revision {}", query.id, query.revision);
+    let rowid = database_api::get_item_rowid(tx, &query.id)?.ok_or_else(|| Error {
+        code: StatusCode::NOT_FOUND,
+        msg: format!("Item with id {} not found", query.id),
+    })?;
+    let revision = database_api::get_revisions(tx, rowid)?
+        .into_iter()
+        .find(|r| r.rowid == query.revision)
+        .ok_or_else(|| Error {
+            code: StatusCode::NOT_FOUND,
+            msg: format!("Revision {} of item {} not found", query.revision, query.id),
+        })?;
+    let mut fields: HashMap<String, Value> = serde_json::from_str(&revision.content)?;
+    // Properties that the item didn't have in the revision are erased
+    for name in get_item_properties(tx, rowid, schema)?.keys() {
+        fields.entry(name.to_string()).or_insert(Value::Null);
+    }
+    for name in [
+        "id",
+        "type",
+        "dateCreated",
+        "dateModified",
+        "dateServerModified",
+    ] {
+        fields.remove(name);
+    }
+    let item_type = database_api::get_item_base(tx, rowid)?
+        .map(|base| base._type)
+        .unwrap_or_default();
+    if let Some(rules) = schema.item_rules.get(&item_type) {
+        fields.retain(|name, _| rules.get(name).map(|r| r.formula.is_none()).unwrap_or(true));
+    }
+    let item = UpdateItem {
+        id: query.id,
+        if_date_server_modified: None,
+        fields,
+    };
+    update_item_tx(tx, schema, item, author)?;
+    Ok(())
+}
+
+/// Export the Schema, see `schema_export::export_schema`.
+///
+/// Edges are not declared in the Schema, they are taken from a scan of all existing edges
+/// (see `database_api::get_edge_types`), which is proportional to the number of edges.
+pub fn export_schema(tx: &Tx, schema: &Schema, query: ExportSchema) -> Result<Value> {
+    info!("Exporting Schema as {:?}", query.format);
+    let edges = database_api::get_edge_types(tx)?;
+    Ok(schema_export::export_schema(schema, &edges, query.format))
+}
+
 #[cfg(test)]
 mod tests {
     use crate::api_model::CreateItem;
@@ -372,6 +1315,465 @@ mod tests {
     use std::collections::HashMap;
     use warp::hyper::StatusCode;
 
+    fn update_item(id: &str, fields: HashMap<String, Value>) -> UpdateItem {
+        UpdateItem {
+            id: id.to_string(),
+            if_date_server_modified: None,
+            fields,
+        }
+    }
+
+    /// Declare properties of `item_type`, given as `(propertyName, valueType)`
+    fn add_property_schemas(
+        tx: &Tx,
+        schema: &mut Schema,
+        item_type: &str,
+        properties: &[(&str, &str)],
+    ) {
+        let cli = command_line_interface::tests::test_cli();
+        let db_key = DatabaseKey::from("".to_string()).unwrap();
+        for (name, value_type) in properties {
+            let item = json!({"type": "ItemPropertySchema", "itemType": item_type,
+                "propertyName": name, "valueType": value_type});
+            let item: CreateItem = serde_json::from_value(item).unwrap();
+            create_item_tx(tx, schema, item, "", &cli, &db_key).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_update_precondition() {
+        let mut conn = new_conn();
+        let cli = command_line_interface::tests::test_cli();
+        let db_key = DatabaseKey::from("".to_string()).unwrap();
+        let tx = conn.transaction().unwrap();
+        let mut schema = database_api::get_schema(&tx).unwrap();
+        let item: CreateItem = serde_json::from_value(json!({"type": "Person"})).unwrap();
+        let id = create_item_tx(&tx, &mut schema, item, "", &cli, &db_key).unwrap();
+        let date_server_modified = |tx: &Tx| {
+            let item = get_item_tx(tx, &schema, &id).unwrap();
+            item[0]["dateServerModified"].as_i64().unwrap()
+        };
+        let original = date_server_modified(&tx);
+
+        let mut update: UpdateItem = serde_json::from_value(json!({
+            "id": id, "_ifDateServerModified": original, "deleted": false,
+        }))
+        .unwrap();
+        assert!(!update.fields.contains_key("_ifDateServerModified"));
+        update_item_tx(&tx, &mut schema.clone(), update, REVISION_AUTHOR_CLIENT).unwrap();
+        let updated = date_server_modified(&tx);
+        assert!(updated > original);
+
+        // A second update based on the same original version is rejected
+        update = serde_json::from_value(json!({
+            "id": id, "_ifDateServerModified": original, "deleted": true,
+        }))
+        .unwrap();
+        let err =
+            update_item_tx(&tx, &mut schema.clone(), update, REVISION_AUTHOR_CLIENT).unwrap_err();
+        assert_eq!(err.code, StatusCode::CONFLICT);
+        assert_eq!(date_server_modified(&tx), updated);
+    }
+
+    #[test]
+    fn test_search_with() {
+        let mut conn = new_conn();
+        let cli = command_line_interface::tests::test_cli();
+        let db_key = DatabaseKey::from("".to_string()).unwrap();
+        let tx = conn.transaction().unwrap();
+        let mut schema = database_api::get_schema(&tx).unwrap();
+        for _ in 0..3 {
+            let item: CreateItem = serde_json::from_value(json!({"type": "Person"})).unwrap();
+            create_item_tx(&tx, &mut schema, item, "", &cli, &db_key).unwrap();
+        }
+        let query = || serde_json::from_value::<Search>(json!({"type": "Person"})).unwrap();
+        let mut streamed = Vec::new();
+        search_with(&tx, &schema, query(), |item| {
+            streamed.push(item);
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(streamed, search(&tx, &schema, query()).unwrap());
+
+        // An error while handling an item stops the search
+        let mut count = 0;
+        let err = search_with(&tx, &schema, query(), |_| {
+            count += 1;
+            Err(Error {
+                code: StatusCode::INTERNAL_SERVER_ERROR,
+                msg: "client disconnected".to_string(),
+            })
+        })
+        .unwrap_err();
+        assert_eq!(err.msg, "client disconnected");
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn test_search_batches() {
+        let mut conn = new_conn();
+        let tx = conn.transaction().unwrap();
+        let schema = database_api::get_schema(&tx).unwrap();
+        let count = constants::SEARCH_STREAM_BATCH_SIZE as i64 * 2 + 10;
+        for date in 0..count {
+            let id = format!("note-{}", date);
+            database_api::insert_item_base(&tx, &id, "Note", date, date, date, false).unwrap();
+        }
+        let all_batches = |query: Value| {
+            let mut batches = SearchBatches::new(serde_json::from_value(query).unwrap());
+            let mut result = Vec::new();
+            while let Some(items) = batches.next_batch(&tx, &schema).unwrap() {
+                result.push(items.len());
+            }
+            result
+        };
+        let batch = constants::SEARCH_STREAM_BATCH_SIZE as usize;
+        assert_eq!(all_batches(json!({"type": "Note"})), vec![batch, batch, 10]);
+        let query = json!({"type": "Note", "_sortOrder": "Desc", "_limit": batch + 5});
+        assert_eq!(all_batches(query), vec![batch, 5]);
+
+        let mut batches =
+            SearchBatches::new(serde_json::from_value(json!({"type": "Note"})).unwrap());
+        let first = batches.next_batch(&tx, &schema).unwrap().unwrap();
+        let second = batches.next_batch(&tx, &schema).unwrap().unwrap();
+        assert_eq!(
+            first.last().unwrap()["id"],
+            json!(format!("note-{}", batch - 1))
+        );
+        assert_eq!(second[0]["id"], json!(format!("note-{}", batch)));
+    }
+
+    #[test]
+    fn test_changes_since() {
+        let mut conn = new_conn();
+        let cli = command_line_interface::tests::test_cli();
+        let db_key = DatabaseKey::from("".to_string()).unwrap();
+        let tx = conn.transaction().unwrap();
+        let mut schema = database_api::get_schema(&tx).unwrap();
+        let start = database_api::get_last_change_seq(&tx).unwrap();
+
+        for id in ["item-a", "item-b"] {
+            let item: CreateItem =
+                serde_json::from_value(json!({"id": id, "type": "Person"})).unwrap();
+            create_item_tx(&tx, &mut schema, item, "", &cli, &db_key).unwrap();
+        }
+        let edge: CreateEdge = serde_json::from_value(json!({
+            "_source": "item-a", "_target": "item-b", "_name": "friend",
+        }))
+        .unwrap();
+        let source = database_api::get_item_rowid(&tx, "item-a")
+            .unwrap()
+            .unwrap();
+        let created = database_api::get_item_base(&tx, source).unwrap().unwrap();
+        let edge_id = create_edge(&tx, edge).unwrap();
+        let linked = database_api::get_item_base(&tx, source).unwrap().unwrap();
+        assert!(linked.date_server_modified > created.date_server_modified);
+        delete_item_tx(&tx, &mut schema, "item-a", REVISION_AUTHOR_CLIENT).unwrap();
+        let changes = |since: i64, limit: u64| {
+            let query = ChangesSince {
+                since,
+                limit,
+                client_id: None,
+            };
+            changes_since(&tx, &schema, query).unwrap()
+        };
+
+        // Creating the edge also changes its source item, deleted afterwards
+        let first = changes(start, 2);
+        let items = first["items"].as_array().unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0]["id"], json!("item-b"));
+        assert_eq!(items[1]["_edge"], json!("friend"));
+        assert_eq!(first["hasMore"], json!(true));
+
+        let since = first["changeSeq"].as_i64().unwrap();
+        let rest = changes(since, 10);
+        let items = rest["items"].as_array().unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0]["id"], json!("item-a"));
+        assert_eq!(items[0]["deleted"], json!(true));
+        assert_eq!(rest["hasMore"], json!(false));
+        let since = rest["changeSeq"].as_i64().unwrap();
+        assert_eq!(changes(since, 10)["items"], json!([]));
+        assert_eq!(changes(since, 10)["changeSeq"], json!(since));
+
+        // Purged items are returned as well, after the edges removed with them
+        let mut schema = database_api::get_schema(&tx).unwrap();
+        purge_item_tx(&tx, &mut schema, "item-a").unwrap();
+        let query = ChangesSince {
+            since,
+            limit: 10,
+            client_id: None,
+        };
+        let purged = changes_since(&tx, &schema, query).unwrap();
+        let expected = json!([
+            {"id": edge_id, "purged": true},
+            {"id": "item-a", "purged": true},
+        ]);
+        assert_eq!(purged["items"], expected);
+    }
+
+    #[test]
+    fn test_acknowledge_changes() {
+        let mut conn = new_conn();
+        let cli = command_line_interface::tests::test_cli();
+        let db_key = DatabaseKey::from("".to_string()).unwrap();
+        let tx = conn.transaction().unwrap();
+        let mut schema = database_api::get_schema(&tx).unwrap();
+        let last = database_api::get_last_change_seq(&tx).unwrap();
+        let mut acknowledge = |change_seq: i64| {
+            let query = AcknowledgeChanges {
+                client_id: "laptop-client".to_string(),
+                change_seq,
+                name: Some("Laptop".to_string()),
+            };
+            acknowledge_changes_tx(&tx, &mut schema, query, "", &cli, &db_key)
+        };
+        assert!(acknowledge(last + 100).is_err());
+        let result = acknowledge(last - 1).unwrap();
+        assert_eq!(result["ackedChangeSeq"], json!(last - 1));
+        // Going back doesn't lose the acknowledgement
+        let result = acknowledge(last - 5).unwrap();
+        assert_eq!(result["ackedChangeSeq"], json!(last - 1));
+        let acked = database_api::get_min_acknowledged_change_seq(&tx).unwrap();
+        assert_eq!(acked, Some(last - 1));
+        // Acknowledging the last change doesn't make a new one
+        let last = database_api::get_last_change_seq(&tx).unwrap();
+        acknowledge(last).unwrap();
+        let query = ChangesSince {
+            since: last,
+            limit: 100,
+            client_id: None,
+        };
+        let changes = changes_since(&tx, &schema, query).unwrap();
+        assert_eq!(changes["items"], json!([]));
+        assert_eq!(database_api::get_last_change_seq(&tx).unwrap(), last);
+
+        let rowid = database_api::get_item_rowid(&tx, "laptop-client")
+            .unwrap()
+            .unwrap();
+        let schema = database_api::get_schema(&tx).unwrap();
+        let props = get_item_properties(&tx, rowid, &schema).unwrap();
+        assert_eq!(props["name"], json!("Laptop"));
+        assert!(props["dateAcked"].as_i64().is_some());
+    }
+
+    #[test]
+    fn test_get_items() {
+        let mut conn = new_conn();
+        let cli = command_line_interface::tests::test_cli();
+        let db_key = DatabaseKey::from("".to_string()).unwrap();
+        let tx = conn.transaction().unwrap();
+        let mut schema = database_api::get_schema(&tx).unwrap();
+        for id in ["item-1", "item-2"] {
+            let item: CreateItem =
+                serde_json::from_value(json!({"id": id, "type": "Person"})).unwrap();
+            create_item_tx(&tx, &mut schema, item, "", &cli, &db_key).unwrap();
+        }
+        let query = GetItems {
+            ids: vec![
+                "item-2".to_string(),
+                "nope".to_string(),
+                "item-1".to_string(),
+            ],
+        };
+        let result = get_items_tx(&tx, &schema, query).unwrap();
+        let ids: Vec<&Value> = result["items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|i| &i["id"])
+            .collect();
+        assert_eq!(ids, vec![&json!("item-2"), &json!("item-1")]);
+        assert_eq!(result["missing"], json!(["nope"]));
+    }
+
+    #[test]
+    fn test_concurrent_edits() {
+        let mut conn = new_conn();
+        let cli = command_line_interface::tests::test_cli();
+        let db_key = DatabaseKey::from("".to_string()).unwrap();
+        let tx = conn.transaction().unwrap();
+        let mut schema = database_api::get_schema(&tx).unwrap();
+        add_property_schemas(
+            &tx,
+            &mut schema,
+            "Note",
+            &[("title", "Text"), ("content", "Text")],
+        );
+        let item: CreateItem =
+            serde_json::from_value(json!({"type": "Note", "dateModified": 100})).unwrap();
+        let id = create_item_tx(&tx, &mut schema, item, "", &cli, &db_key).unwrap();
+        let mut update = |fields: Value| {
+            let mut update: UpdateItem = serde_json::from_value(fields).unwrap();
+            update.id = id.clone();
+            update_item_tx(&tx, &mut schema, update, REVISION_AUTHOR_CLIENT).unwrap()
+        };
+
+        let result = update(json!({"id": "", "dateModified": 2000, "title": "A"}));
+        assert_eq!(result, json!({}));
+        // An older edit made offline only changes the properties that weren't changed since
+        let result = update(json!({"id": "", "dateModified": 1000, "title": "B", "content": "B"}));
+        assert_eq!(result, json!({"overriddenFields": ["title"]}));
+        let result = update(json!({"id": "", "dateModified": 1500, "title": "B", "deleted": true}));
+        assert_eq!(result, json!({"overriddenFields": ["title"]}));
+        let result = update(json!({"id": "", "dateModified": 1200, "deleted": false}));
+        assert_eq!(result, json!({"overriddenFields": ["deleted"]}));
+        // Updates without dateModified always win
+        let result = update(json!({"id": "", "title": "C"}));
+        assert_eq!(result, json!({}));
+
+        let item = &get_item_tx(&tx, &schema, &id).unwrap()[0];
+        assert_eq!(item["title"], json!("C"));
+        assert_eq!(item["content"], json!("B"));
+        assert_eq!(item["deleted"], json!(true));
+        assert!(item["dateModified"].as_i64().unwrap() > 2000);
+    }
+
+    #[test]
+    fn test_stale_edit_of_property_set_at_creation() {
+        let mut conn = new_conn();
+        let cli = command_line_interface::tests::test_cli();
+        let db_key = DatabaseKey::from("".to_string()).unwrap();
+        let tx = conn.transaction().unwrap();
+        let mut schema = database_api::get_schema(&tx).unwrap();
+        add_property_schemas(
+            &tx,
+            &mut schema,
+            "Note",
+            &[("title", "Text"), ("content", "Text")],
+        );
+        let item: CreateItem = serde_json::from_value(
+            json!({"type": "Note", "dateModified": 1000, "title": "created"}),
+        )
+        .unwrap();
+        let id = create_item_tx(&tx, &mut schema, item, "", &cli, &db_key).unwrap();
+
+        // The title was never updated, but it was set after this offline edit was made
+        let update: UpdateItem = serde_json::from_value(
+            json!({"id": id, "dateModified": 500, "title": "stale", "content": "new"}),
+        )
+        .unwrap();
+        let result = update_item_tx(&tx, &mut schema, update, REVISION_AUTHOR_CLIENT).unwrap();
+        assert_eq!(result, json!({"overriddenFields": ["title"]}));
+        let item = &get_item_tx(&tx, &schema, &id).unwrap()[0];
+        assert_eq!(item["title"], json!("created"));
+        assert_eq!(item["content"], json!("new"));
+    }
+
+    #[test]
+    fn test_apply_operations() {
+        let mut conn = new_conn();
+        let cli = command_line_interface::tests::test_cli();
+        let db_key = DatabaseKey::from("".to_string()).unwrap();
+        let tx = conn.transaction().unwrap();
+        let mut schema = database_api::get_schema(&tx).unwrap();
+        add_property_schemas(&tx, &mut schema, "Chat", &[("unreadCount", "Integer")]);
+        let log = json!({
+            "clientId": "phone",
+            "operations": [
+                {"opId": "op-1", "createItem": {"id": "offline-chat", "type": "Chat", "unreadCount": 1}},
+                {"opId": "op-2", "updateItem": {"id": "offline-chat", "unreadCount": {"$increment": 1}}},
+                {"opId": "op-3", "createEdge": {"_source": "offline-chat", "_target": "offline-chat", "_name": "self"}},
+                {"opId": "op-4", "deleteItem": "no-such-item"},
+                {"opId": "op-5", "search": {"type": "Chat"}},
+            ],
+        });
+        let mut apply = |log: &Value| {
+            let log: ApplyOperations = serde_json::from_value(log.clone()).unwrap();
+            apply_operations_tx(
+                &tx,
+                &mut schema,
+                log,
+                "",
+                &cli,
+                &db_key,
+                REVISION_AUTHOR_CLIENT,
+            )
+            .unwrap()
+        };
+
+        let first = apply(&log);
+        let results = first["operations"].as_array().unwrap();
+        assert_eq!(
+            results[0],
+            json!({"opId": "op-1", "result": "offline-chat"})
+        );
+        assert_eq!(results[3]["opId"], json!("op-4"));
+        assert_eq!(results[3]["error"]["code"], json!(404));
+
+        // Uploading the same log again only retries the failed operation
+        let second = apply(&log);
+        let results = second["operations"].as_array().unwrap();
+        assert_eq!(results[1]["duplicate"], json!(true));
+        assert_eq!(results[2]["result"], first["operations"][2]["result"]);
+        assert_eq!(results[3]["error"]["code"], json!(404));
+        // Searches are run again, not remembered
+        assert_eq!(results[4]["duplicate"], Value::Null);
+        assert_eq!(results[4]["result"].as_array().unwrap().len(), 1);
+        let applied = database_api::get_applied_operation(&tx, "phone", "op-5").unwrap();
+        assert_eq!(applied, None);
+
+        let rowid = database_api::get_item_rowid(&tx, "offline-chat")
+            .unwrap()
+            .unwrap();
+        let schema = database_api::get_schema(&tx).unwrap();
+        let props = get_item_properties(&tx, rowid, &schema).unwrap();
+        assert_eq!(props["unreadCount"], json!(2));
+        assert_eq!(get_outgoing_edges(&tx, rowid).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_property_operations() {
+        let mut conn = new_conn();
+        let cli = command_line_interface::tests::test_cli();
+        let db_key = DatabaseKey::from("".to_string()).unwrap();
+        let tx = conn.transaction().unwrap();
+        let mut schema = database_api::get_schema(&tx).unwrap();
+        let properties = [
+            ("unreadCount", "Integer"),
+            ("score", "Real"),
+            ("log", "Text"),
+            ("lastSeen", "DateTime"),
+        ];
+        add_property_schemas(&tx, &mut schema, "Chat", &properties);
+        let item: CreateItem =
+            serde_json::from_value(json!({"type": "Chat", "unreadCount": 2, "lastSeen": 100}))
+                .unwrap();
+        let id = create_item_tx(&tx, &mut schema, item, "", &cli, &db_key).unwrap();
+        let mut update = |fields: Value| {
+            let mut update: UpdateItem = serde_json::from_value(fields).unwrap();
+            update.id = id.clone();
+            update_item_tx(&tx, &mut schema, update, REVISION_AUTHOR_CLIENT)
+        };
+
+        update(
+            json!({"id": "", "unreadCount": {"$increment": 3}, "score": {"$decrement": 0.5},
+            "log": {"$append": "a"}, "lastSeen": {"$max": 50}}),
+        )
+        .unwrap();
+        update(
+            json!({"id": "", "unreadCount": {"$decrement": 1}, "log": {"$append": "b"},
+            "lastSeen": {"$max": 200}, "score": {"$setIfAbsent": 10.0}}),
+        )
+        .unwrap();
+        let err = update(json!({"id": "", "log": {"$increment": 1}})).unwrap_err();
+        assert_eq!(err.code, StatusCode::BAD_REQUEST);
+        let err = update(json!({"id": "", "log": {"$unknown": 1}})).unwrap_err();
+        assert_eq!(err.code, StatusCode::BAD_REQUEST);
+
+        // Overflowing to infinity fails instead of erasing the property
+        let err = update(json!({"id": "", "score": {"$decrement": f64::MAX}}))
+            .and_then(|_| update(json!({"id": "", "score": {"$decrement": f64::MAX}})));
+        assert_eq!(err.unwrap_err().code, StatusCode::BAD_REQUEST);
+
+        let item = &get_item_tx(&tx, &schema, &id).unwrap()[0];
+        assert_eq!(item["unreadCount"], json!(4));
+        assert_eq!(item["score"], json!(-0.5 - f64::MAX));
+        assert_eq!(item["log"], json!("ab"));
+        assert_eq!(item["lastSeen"], json!(200));
+    }
+
     #[test]
     fn test_schema_checking() -> Result<()> {
         let mut conn = new_conn();
@@ -479,9 +1881,7 @@ mod tests {
             .contains(expected_error));
         }
 
-        let mut bad_empty_schema = Schema {
-            property_types: HashMap::new(),
-        };
+        let mut bad_empty_schema = Schema::default();
         let create_item: CreateItem = serde_json::from_value(json).unwrap();
         let result = internal_api::create_item_tx(
             &tx,
@@ -498,6 +1898,405 @@ mod tests {
         tx.commit().unwrap();
     }
 
+    #[test]
+    fn test_schema_rules() {
+        let mut conn = new_conn();
+        let cli = command_line_interface::tests::test_cli();
+        let db_key = DatabaseKey::from("".to_string()).unwrap();
+        let tx = conn.transaction().unwrap();
+        let mut schema = database_api::get_schema(&tx).unwrap();
+
+        let schema_items = vec![
+            json!({"type": "ItemPropertySchema", "itemType": "Person", "propertyName": "email",
+                "valueType": "Text", "isRequired": true, "isUnique": true, "pattern": "^.+@.+$"}),
+            json!({"type": "ItemPropertySchema", "itemType": "Person", "propertyName": "age",
+                "valueType": "Integer", "minimum": 0, "maximum": 150}),
+        ];
+        for item in schema_items {
+            let item: CreateItem = serde_json::from_value(item).unwrap();
+            create_item_tx(&tx, &mut schema, item, "", &cli, &db_key).unwrap();
+        }
+        let mut create = |json: Value| {
+            let item: CreateItem = serde_json::from_value(json).unwrap();
+            create_item_tx(&tx, &mut schema, item, "", &cli, &db_key)
+        };
+
+        let err = create(json!({"type": "Person", "age": 20})).unwrap_err();
+        assert_eq!(err.code, StatusCode::BAD_REQUEST);
+        assert!(err.msg.contains("'required'"), "{}", err.msg);
+
+        let err = create(json!({"type": "Person", "email": "no-at-sign"})).unwrap_err();
+        assert!(err.msg.contains("'pattern'"), "{}", err.msg);
+
+        let err = create(json!({"type": "Person", "email": "a@b", "age": 200})).unwrap_err();
+        assert!(err.msg.contains("'range'"), "{}", err.msg);
+
+        let id = create(json!({"type": "Person", "email": "a@b", "age": 20})).unwrap();
+        let err = create(json!({"type": "Person", "email": "a@b"})).unwrap_err();
+        assert!(err.msg.contains("'unique'"), "{}", err.msg);
+
+        // Rules are specific to the item type
+        create(json!({"type": "Company", "email": "a@b"})).unwrap();
+
+        let mut fields = HashMap::new();
+        fields.insert("email".to_string(), Value::Null);
+        let err = update_item_tx(
+            &tx,
+            &mut schema,
+            update_item(&id, fields),
+            REVISION_AUTHOR_CLIENT,
+        )
+        .unwrap_err();
+        assert!(err.msg.contains("'required'"), "{}", err.msg);
+
+        // Re-writing its own unique value is fine
+        let mut fields = HashMap::new();
+        fields.insert("email".to_string(), json!("a@b"));
+        update_item_tx(
+            &tx,
+            &mut schema,
+            update_item(&id, fields),
+            REVISION_AUTHOR_CLIENT,
+        )
+        .unwrap();
+
+        // A deleted item cannot be restored once another item took its unique value
+        delete_item_tx(&tx, &mut schema, &id, REVISION_AUTHOR_CLIENT).unwrap();
+        let item = json!({"type": "Person", "email": "a@b"});
+        let item: CreateItem = serde_json::from_value(item).unwrap();
+        create_item_tx(&tx, &mut schema, item, "", &cli, &db_key).unwrap();
+        let err = restore_item_tx(&tx, &mut schema, &id, REVISION_AUTHOR_CLIENT).unwrap_err();
+        assert_eq!(err.code, StatusCode::CONFLICT);
+        assert!(err.msg.contains("'unique'"), "{}", err.msg);
+
+        // Rules are read back from the database
+        let schema = database_api::get_schema(&tx).unwrap();
+        let rules = schema.item_rules.get("Person").unwrap();
+        assert!(rules.get("email").unwrap().unique);
+        assert_eq!(rules.get("age").unwrap().maximum, Some(150.));
+    }
+
+    #[test]
+    fn test_revisions() {
+        let mut conn = new_conn();
+        let cli = command_line_interface::tests::test_cli();
+        let db_key = DatabaseKey::from("".to_string()).unwrap();
+        let tx = conn.transaction().unwrap();
+        let mut schema = database_api::get_schema(&tx).unwrap();
+        let mut create = |json: Value| {
+            let item: CreateItem = serde_json::from_value(json).unwrap();
+            create_item_tx(&tx, &mut schema, item, "", &cli, &db_key).unwrap()
+        };
+        create(json!({"type": "ItemTypeSchema", "itemType": "Note", "keepRevisions": true}));
+        create(json!({"type": "ItemTypeSchema", "itemType": "Edge", "keepRevisions": true}));
+        create(json!({"type": "ItemPropertySchema", "itemType": "Note",
+            "propertyName": "title", "valueType": "Text"}));
+        let note = create(json!({"type": "Note", "title": "first"}));
+        let person = create(json!({"type": "Person"}));
+        let edge: CreateEdge = serde_json::from_value(json!({
+            "_source": note, "_target": person, "_name": "author",
+        }))
+        .unwrap();
+        let edge = create_edge(&tx, edge).unwrap();
+
+        let mut fields = HashMap::new();
+        fields.insert("title".to_string(), json!("second"));
+        let plugin_author = "plugin:my_plugin";
+        update_item_tx(&tx, &mut schema, update_item(&note, fields), plugin_author).unwrap();
+        delete_item_tx(&tx, &mut schema, &person, REVISION_AUTHOR_CLIENT).unwrap();
+
+        let get = |id: &str| {
+            let query = GetRevisions { id: id.to_string() };
+            get_revisions(&tx, query).unwrap()
+        };
+        let revisions = get(&note);
+        assert_eq!(revisions.len(), 1);
+        assert_eq!(revisions[0]["author"], json!(plugin_author));
+        assert_eq!(revisions[0]["item"]["title"], json!("first"));
+        // Revisions are only kept for item types that opt in
+        assert!(get(&person).is_empty());
+
+        let query = RestoreRevision {
+            id: note.clone(),
+            revision: revisions[0]["revision"].as_i64().unwrap(),
+        };
+        restore_revision(&tx, &mut schema, query, REVISION_AUTHOR_CLIENT).unwrap();
+        let item = get_item_tx(&tx, &schema, &note).unwrap();
+        assert_eq!(item[0]["title"], json!("first"));
+        let revisions = get(&note);
+        assert_eq!(revisions.len(), 2);
+        assert_eq!(revisions[0]["item"]["title"], json!("second"));
+        assert_eq!(revisions[0]["author"], json!(REVISION_AUTHOR_CLIENT));
+
+        // Purging removes the revisions of the item and of the edges removed with it
+        delete_item_tx(&tx, &mut schema, &edge, REVISION_AUTHOR_CLIENT).unwrap();
+        assert_eq!(get(&edge).len(), 1);
+        purge_item_tx(&tx, &mut schema, &note).unwrap();
+        let count: i64 = tx
+            .query_row("SELECT COUNT(*) FROM revisions;", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 0);
+    }
+
+    #[test]
+    fn test_restore_and_purge() {
+        let mut conn = new_conn();
+        let cli = command_line_interface::tests::test_cli();
+        let db_key = DatabaseKey::from("".to_string()).unwrap();
+        let tx = conn.transaction().unwrap();
+        let mut schema = database_api::get_schema(&tx).unwrap();
+        let mut create = |json: Value| {
+            let item: CreateItem = serde_json::from_value(json).unwrap();
+            create_item_tx(&tx, &mut schema, item, "", &cli, &db_key).unwrap()
+        };
+        let person = create(json!({"type": "Person"}));
+        let sha256 = "0".repeat(64);
+        let file = create(json!({"type": "File", "sha256": sha256}));
+        let edge: CreateEdge = serde_json::from_value(json!({
+            "_source": person, "_target": file, "_name": "photo",
+        }))
+        .unwrap();
+        let edge = create_edge(&tx, edge).unwrap();
+
+        delete_item_tx(&tx, &mut schema, &person, REVISION_AUTHOR_CLIENT).unwrap();
+        restore_item_tx(&tx, &mut schema, &person, REVISION_AUTHOR_CLIENT).unwrap();
+        let item = get_item_tx(&tx, &schema, &person).unwrap();
+        assert_eq!(item[0]["deleted"], json!(false));
+
+        let orphaned_files = purge_item_tx(&tx, &mut schema, &file).unwrap();
+        assert_eq!(orphaned_files, vec![sha256]);
+        assert!(get_item_tx(&tx, &schema, &file).unwrap().is_empty());
+        assert!(get_item_tx(&tx, &schema, &edge).unwrap().is_empty());
+        let get_edges_query: GetEdges = serde_json::from_value(json!({
+            "item": person, "direction": "Outgoing", "expandItems": false,
+        }))
+        .unwrap();
+        assert!(get_edges(&tx, get_edges_query, &schema).unwrap().is_empty());
+
+        let err = purge_item_tx(&tx, &mut schema, &file).unwrap_err();
+        assert_eq!(err.code, StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn test_bulk_modes() {
+        let mut conn = new_conn();
+        let cli = command_line_interface::tests::test_cli();
+        let db_key = DatabaseKey::from("".to_string()).unwrap();
+        let tx = conn.transaction().unwrap();
+        let mut schema = database_api::get_schema(&tx).unwrap();
+        let payload = json!({
+            "createItems": [
+                {"type": "Person", "id": "valid-person-1"},
+                {"type": "Person", "unknownProperty": 1},
+                {"id": "missing-type-person"},
+            ],
+            "deleteItems": ["valid-person-1", "nonexistent-item"],
+        });
+        let mut bulk = |payload: Value| {
+            let entries: BulkEntries = serde_json::from_value(payload).unwrap();
+            let author = REVISION_AUTHOR_CLIENT;
+            bulk_entries_tx(&tx, &mut schema, entries, "", &cli, &db_key, author)
+        };
+
+        let err = bulk(payload.clone()).unwrap_err();
+        assert!(err.msg.contains("createItems[2]"), "{}", err.msg);
+        // Failures while performing an entry name it as well
+        let err = bulk(json!({"deleteItems": ["nonexistent-item"]})).unwrap_err();
+        assert_eq!(err.code, StatusCode::NOT_FOUND);
+        assert!(err.msg.ends_with(" at deleteItems[0]"), "{}", err.msg);
+
+        let mut partial = payload;
+        partial["mode"] = json!("partial");
+        let result = bulk(partial).unwrap();
+        let created = &result["createItems"];
+        assert_eq!(created[0], json!("valid-person-1"));
+        assert_eq!(created[1]["error"]["code"], json!(400));
+        assert_eq!(created[1]["error"]["path"], json!("createItems[1]"));
+        assert_eq!(created[2]["error"]["path"], json!("createItems[2]"));
+        let deleted = &result["deleteItems"];
+        assert_eq!(deleted[0], json!({}));
+        assert_eq!(deleted[1]["error"]["code"], json!(404));
+
+        // Failed entries are rolled back, successful ones are kept
+        let item = get_item_tx(&tx, &schema, "valid-person-1").unwrap();
+        assert_eq!(item[0]["deleted"], json!(true));
+        let search_query: Search = serde_json::from_value(json!({"type": "Person"})).unwrap();
+        assert_eq!(search(&tx, &schema, search_query).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_bulk_references() {
+        let mut conn = new_conn();
+        let cli = command_line_interface::tests::test_cli();
+        let db_key = DatabaseKey::from("".to_string()).unwrap();
+        let tx = conn.transaction().unwrap();
+        let mut schema = database_api::get_schema(&tx).unwrap();
+        let mut bulk = |payload: Value| {
+            let entries: BulkEntries = serde_json::from_value(payload).unwrap();
+            let author = REVISION_AUTHOR_CLIENT;
+            bulk_entries_tx(&tx, &mut schema, entries, "", &cli, &db_key, author)
+        };
+        let result = bulk(json!({
+            "createItems": [
+                {"type": "Person", "$ref": "alice"},
+                {"type": "Person", "$ref": "bob", "id": "bob-item-id"},
+            ],
+            "updateItems": [{"id": {"$ref": "alice"}, "deleted": true}],
+            "createEdges": [
+                {"_source": {"$ref": "alice"}, "_target": {"$ref": "bob"}, "_name": "friend"},
+            ],
+        }))
+        .unwrap();
+        let alice = result["references"]["alice"].as_str().unwrap().to_string();
+        assert_eq!(result["createItems"], json!([alice, "bob-item-id"]));
+        assert_eq!(result["references"]["bob"], json!("bob-item-id"));
+
+        let err = bulk(json!({"deleteItems": [{"$ref": "carol"}]})).unwrap_err();
+        assert_eq!(err.code, StatusCode::BAD_REQUEST);
+        assert!(err.msg.contains("deleteItems[0]"), "{}", err.msg);
+
+        let get_edges_query: GetEdges = serde_json::from_value(json!({
+            "item": alice, "direction": "Outgoing", "expandItems": false,
+        }))
+        .unwrap();
+        let edges = get_edges(&tx, get_edges_query, &schema).unwrap();
+        assert_eq!(edges[0]["item"]["id"], json!("bob-item-id"));
+    }
+
+    #[test]
+    fn test_bulk_operations() {
+        let mut conn = new_conn();
+        let cli = command_line_interface::tests::test_cli();
+        let db_key = DatabaseKey::from("".to_string()).unwrap();
+        let tx = conn.transaction().unwrap();
+        let mut schema = database_api::get_schema(&tx).unwrap();
+        let mut bulk = |payload: Value| {
+            let entries: BulkEntries = serde_json::from_value(payload).unwrap();
+            let author = REVISION_AUTHOR_CLIENT;
+            bulk_entries_tx(&tx, &mut schema, entries, "", &cli, &db_key, author)
+        };
+        bulk(json!({"createItems": [{"id": "person-1", "type": "Person"}]})).unwrap();
+
+        let result = bulk(json!({
+            "operations": [
+                {"deleteItem": "person-1"},
+                {"search": {"id": "person-1", "deleted": false}},
+                {"createItem": {"type": "Person", "$ref": "alice"}},
+                {"search": {"type": "Person", "deleted": false}},
+            ],
+        }))
+        .unwrap();
+        let alice = result["references"]["alice"].clone();
+        let operations = result["operations"].as_array().unwrap();
+        assert_eq!(operations[0], json!({}));
+        assert_eq!(operations[1], json!([]));
+        assert_eq!(operations[2], alice);
+        assert_eq!(operations[3].as_array().unwrap().len(), 1);
+        assert_eq!(operations[3][0]["id"], alice);
+
+        let result = bulk(json!({
+            "mode": "partial",
+            "operations": [
+                {"updateItem": {"id": "no-such-item", "deleted": true}},
+                {"deleteItem": alice},
+            ],
+        }))
+        .unwrap();
+        assert_eq!(
+            result["operations"][0]["error"]["path"],
+            json!("operations[0]")
+        );
+        assert_eq!(result["operations"][1], json!({}));
+
+        let err = bulk(json!({
+            "operations": [
+                {"createItem": {"type": "Person"}},
+                {"updateItem": {"id": "no-such-item", "deleted": true}},
+            ],
+        }))
+        .unwrap_err();
+        assert_eq!(err.code, StatusCode::NOT_FOUND);
+        assert!(err.msg.ends_with(" at operations[1]"), "{}", err.msg);
+
+        let err = bulk(json!({
+            "deleteItems": ["person-1"],
+            "operations": [{"deleteItem": "person-1"}],
+        }))
+        .unwrap_err();
+        assert_eq!(err.code, StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn test_schema_defaults_and_formulas() {
+        let mut conn = new_conn();
+        let cli = command_line_interface::tests::test_cli();
+        let db_key = DatabaseKey::from("".to_string()).unwrap();
+        let tx = conn.transaction().unwrap();
+        let mut schema = database_api::get_schema(&tx).unwrap();
+
+        let schema_items = vec![
+            json!({"type": "ItemPropertySchema", "itemType": "Contact", "propertyName": "firstName",
+                "valueType": "Text"}),
+            json!({"type": "ItemPropertySchema", "itemType": "Contact", "propertyName": "lastName",
+                "valueType": "Text"}),
+            json!({"type": "ItemPropertySchema", "itemType": "Contact", "propertyName": "starred",
+                "valueType": "Bool", "defaultValue": "false"}),
+            json!({"type": "ItemPropertySchema", "itemType": "Contact", "propertyName": "fullName",
+                "valueType": "Text", "formula": "{firstName} {lastName}"}),
+            json!({"type": "ItemPropertySchema", "itemType": "Contact", "propertyName": "handle",
+                "valueType": "Text", "formula": "{fullName|lowercase}", "isUnique": true}),
+        ];
+        for item in schema_items {
+            let item: CreateItem = serde_json::from_value(item).unwrap();
+            create_item_tx(&tx, &mut schema, item, "", &cli, &db_key).unwrap();
+        }
+        let item: CreateItem = serde_json::from_value(json!({"type": "ItemPropertySchema",
+            "itemType": "Contact", "propertyName": "rating", "valueType": "Integer",
+            "defaultValue": "many"}))
+        .unwrap();
+        let err = create_item_tx(&tx, &mut schema, item, "", &cli, &db_key).unwrap_err();
+        assert_eq!(err.code, StatusCode::BAD_REQUEST);
+
+        let item: CreateItem =
+            serde_json::from_value(json!({"type": "Contact", "firstName": "Ada"})).unwrap();
+        let id = create_item_tx(&tx, &mut schema, item, "", &cli, &db_key).unwrap();
+        let item = get_item_tx(&tx, &schema, &id).unwrap();
+        assert_eq!(item[0]["starred"], json!(false));
+        assert_eq!(item[0]["fullName"], json!("Ada"));
+
+        let mut fields = HashMap::new();
+        fields.insert("lastName".to_string(), json!("Lovelace"));
+        update_item_tx(
+            &tx,
+            &mut schema,
+            update_item(&id, fields),
+            REVISION_AUTHOR_CLIENT,
+        )
+        .unwrap();
+        let item = get_item_tx(&tx, &schema, &id).unwrap();
+        assert_eq!(item[0]["fullName"], json!("Ada Lovelace"));
+        assert_eq!(item[0]["handle"], json!("ada lovelace"));
+
+        // Computed values are checked against the Schema rules
+        let item: CreateItem = serde_json::from_value(
+            json!({"type": "Contact", "firstName": "ADA", "lastName": "LOVELACE"}),
+        )
+        .unwrap();
+        let err = create_item_tx(&tx, &mut schema, item, "", &cli, &db_key).unwrap_err();
+        assert!(err.msg.contains("'unique'"), "{}", err.msg);
+
+        let mut fields = HashMap::new();
+        fields.insert("fullName".to_string(), json!("Someone Else"));
+        let err = update_item_tx(
+            &tx,
+            &mut schema,
+            update_item(&id, fields),
+            REVISION_AUTHOR_CLIENT,
+        )
+        .unwrap_err();
+        assert!(err.msg.contains("'formula'"), "{}", err.msg);
+    }
+
     #[test]
     fn test_edge_search() {
         let mut conn = new_conn();
@@ -1,6 +1,8 @@
 extern crate rusqlite;
 
 mod api_model;
+mod archive;
+mod change_events;
 mod command_line_interface;
 mod constants;
 pub mod database_api;
@@ -9,11 +11,17 @@ mod database_utils;
 mod email;
 mod error;
 mod file_api;
+mod garbage_collection;
 mod global_static;
 mod internal_api;
 mod plugin_auth_crypto;
 mod plugin_run;
+mod replication;
 mod schema;
+mod schema_bundle;
+mod schema_cache;
+mod schema_export;
+mod sync_scope;
 mod triggers;
 mod warp_api;
 mod warp_endpoints;
@@ -27,7 +35,6 @@ use log::info;
 use std::fs::create_dir_all;
 use std::io::Write;
 use std::path::PathBuf;
-use std::str::FromStr;
 
 #[tokio::main]
 async fn main() {
@@ -48,16 +55,22 @@ async fn main() {
 
     create_config_directory(constants::DATABASE_DIR);
     create_config_directory(constants::FILES_DIR);
+    // Fail early if the Schema bundle cannot be read
+    lazy_static::initialize(&warp_endpoints::SCHEMA_BUNDLE);
+
+    if let Some(command) = &cli_options.command {
+        if let Err(err) = warp_endpoints::run_command(command, &cli_options) {
+            error!("Command failed, {}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
 
     // Start web framework
     warp_api::run_server(cli_options).await;
 }
 
 fn create_config_directory(path: &str) {
-    if let Ok(path) = PathBuf::from_str(path) {
-        create_dir_all(path).expect("Failed to create database directory");
-    } else {
-        error!("Failed to parse database directory {:?}", path);
-        std::process::exit(1)
-    };
+    let path = PathBuf::from(path);
+    create_dir_all(path).expect("Failed to create database directory");
 }
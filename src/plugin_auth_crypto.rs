@@ -9,7 +9,6 @@ use chacha20poly1305::Key;
 use chacha20poly1305::XChaCha20Poly1305;
 use chacha20poly1305::XNonce;
 use rusqlite::Connection;
-use std::ops::Not;
 use warp::hyper::StatusCode;
 use zeroize::Zeroize;
 
@@ -20,13 +19,24 @@ pub fn generate_xchacha20poly1305_cipher() -> XChaCha20Poly1305 {
 }
 
 /// Given a `database_key` from an already authorized request,
-/// create a new PluginAuth to be passed to external Plugins.
-pub fn create_plugin_auth(database_key: &str) -> Result<PluginAuth> {
+/// create a new PluginAuth to be passed to external Plugin `plugin_name`.
+///
+/// The encrypted permissions consist of the length of the key (one byte),
+/// the key itself and the name of the plugin, so that requests made by the plugin
+/// can be attributed to it (see `extract_plugin_name`).
+pub fn create_plugin_auth(database_key: &str, plugin_name: &str) -> Result<PluginAuth> {
     let nonce: [u8; 24] = rand::random();
     let nonce = XNonce::from_slice(&nonce); // MUST be unique
     let cipher = &global_static::CIPHER;
-    let database_key: Vec<u8> = hex::decode(database_key)?;
-    let encrypted = cipher.encrypt(nonce, database_key.as_slice())?;
+    let mut database_key: Vec<u8> = hex::decode(database_key)?;
+    let mut permissions = Vec::with_capacity(1 + database_key.len() + plugin_name.len());
+    permissions.push(database_key.len() as u8);
+    permissions.extend_from_slice(&database_key);
+    permissions.extend_from_slice(plugin_name.as_bytes());
+    database_key.zeroize();
+    let encrypted = cipher.encrypt(nonce, permissions.as_slice());
+    permissions.zeroize();
+    let encrypted = encrypted?;
     Ok(PluginAuth {
         data: PluginAuthData {
             nonce: hex::encode(nonce),
@@ -37,6 +47,17 @@ pub fn create_plugin_auth(database_key: &str) -> Result<PluginAuth> {
 
 /// Given a request that uses PluginAuth, decrypt its `database_key`.
 pub fn extract_database_key(plugin_auth: &PluginAuth) -> Result<DatabaseKey> {
+    let (database_key, _plugin_name) = decrypt_plugin_auth(plugin_auth)?;
+    Ok(database_key)
+}
+
+/// Given a request that uses PluginAuth, find the name of the plugin that makes it.
+pub fn extract_plugin_name(plugin_auth: &PluginAuth) -> Result<String> {
+    let (_database_key, plugin_name) = decrypt_plugin_auth(plugin_auth)?;
+    Ok(plugin_name)
+}
+
+fn decrypt_plugin_auth(plugin_auth: &PluginAuth) -> Result<(DatabaseKey, String)> {
     if plugin_auth.data.nonce.len() != 48 {
         return Err(Error {
             code: StatusCode::BAD_REQUEST,
@@ -47,19 +68,25 @@ pub fn extract_database_key(plugin_auth: &PluginAuth) -> Result<DatabaseKey> {
     let encrypted_permissions = hex::decode(&plugin_auth.data.encrypted_permissions)?;
     let nonce = XNonce::from_slice(&nonce);
     let cipher = &global_static::CIPHER;
-    let decrypted = cipher.decrypt(nonce, encrypted_permissions.as_ref())?;
-    if decrypted.len() != 32 && decrypted.is_empty().not() {
+    let mut decrypted = cipher.decrypt(nonce, encrypted_permissions.as_ref())?;
+    let key_length = decrypted.first().copied().unwrap_or(0) as usize;
+    if (key_length != 32 && key_length != 0) || decrypted.len() < 1 + key_length {
+        decrypted.zeroize();
         return Err(Error {
             code: StatusCode::BAD_REQUEST,
-            msg: format!("Key has incorrect length: {}", decrypted.len()),
+            msg: format!("Key has incorrect length: {}", key_length),
         });
     }
-    Ok(DatabaseKey {
-        database_key: hex::encode_upper(&decrypted),
-    })
+    let database_key = DatabaseKey {
+        database_key: hex::encode_upper(&decrypted[1..1 + key_length]),
+    };
+    let plugin_name = String::from_utf8_lossy(&decrypted[1 + key_length..]).to_string();
+    decrypted.zeroize();
+    Ok((database_key, plugin_name))
 }
 
 /// Database key stored in raw String format (as supplied to sqlcipher).
+#[derive(Clone)]
 pub struct DatabaseKey {
     /// PRIVATE key (intentionally not public)!
     /// The wrapper struct only exposes "safe" methods to work with the key.
@@ -90,7 +117,7 @@ impl DatabaseKey {
     pub fn from(mut key_as_hex_string: String) -> Result<DatabaseKey> {
         key_as_hex_string.make_ascii_uppercase();
         for c in key_as_hex_string.chars() {
-            if !(('A'..='Z').contains(&c) || ('0'..='9').contains(&c)) {
+            if !(c.is_ascii_uppercase() || c.is_ascii_digit()) {
                 key_as_hex_string.zeroize();
                 return Err(Error {
                     code: StatusCode::BAD_REQUEST,
@@ -103,8 +130,8 @@ impl DatabaseKey {
             database_key: key_as_hex_string,
         })
     }
-    pub fn create_plugin_auth(&self) -> Result<PluginAuth> {
-        create_plugin_auth(&self.database_key)
+    pub fn create_plugin_auth(&self, plugin_name: &str) -> Result<PluginAuth> {
+        create_plugin_auth(&self.database_key, plugin_name)
     }
 }
 
@@ -116,15 +143,18 @@ mod tests {
     fn test_encrypt_decrypt() {
         let db_key = "0".repeat(64);
         let db_key_struct = DatabaseKey::from(db_key.clone()).unwrap();
-        let auth = db_key_struct.create_plugin_auth().unwrap();
+        let plugin_name = "my_plugin";
+        let auth = db_key_struct.create_plugin_auth(plugin_name).unwrap();
         assert!(!auth.data.nonce.contains(&db_key));
         assert!(!auth.data.encrypted_permissions.contains(&db_key));
         assert_eq!(
             auth.data.encrypted_permissions.len(),
-            db_key.len() + 32,
-            "Encrypted permissions should include the 16-byte (32 hex characters) TAG."
+            2 + db_key.len() + 2 * plugin_name.len() + 32,
+            "Encrypted permissions should include the key length, the plugin name \
+            and the 16-byte (32 hex characters) TAG."
         );
         let decrypted = extract_database_key(&auth).unwrap();
         assert_eq!(decrypted.database_key, db_key);
+        assert_eq!(extract_plugin_name(&auth).unwrap(), plugin_name);
     }
 }
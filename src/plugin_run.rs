@@ -40,7 +40,7 @@ pub fn run_plugin_container(
         ),
     })?;
     let target_item_json = serde_json::to_string(&target_item)?;
-    let auth = database_key.create_plugin_auth()?;
+    let auth = database_key.create_plugin_auth(&container_image)?;
     let auth = serde_json::to_string(&auth)?;
 
     let script_override = cli_options
@@ -306,6 +306,6 @@ pub fn escape_bash_arg(str: &str) -> String {
         str.to_string()
     } else {
         let quoted = str.replace("'", "'\\''"); // end quoting, append the literal, start quoting
-        return format!("'{}'", quoted);
+        format!("'{}'", quoted)
     }
 }
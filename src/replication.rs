@@ -0,0 +1,673 @@
+//
+// Pod-to-pod replication: pulling the changes of another Pod into this one
+//
+
+use crate::api_model::CreateEdge;
+use crate::api_model::CreateItem;
+use crate::api_model::Replicate;
+use crate::api_model::UpdateItem;
+use crate::command_line_interface::CliOptions;
+use crate::constants;
+use crate::database_api;
+use crate::database_utils::get_item_from_rowid;
+use crate::error::Error;
+use crate::error::Result;
+use crate::internal_api;
+use crate::plugin_auth_crypto::DatabaseKey;
+use crate::schema::Schema;
+use bytes::Bytes;
+use chrono::Utc;
+use log::info;
+use log::warn;
+use rusqlite::Transaction as Tx;
+use serde::Deserialize;
+use serde_json::json;
+use serde_json::Map;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncWrite;
+use tokio::net::TcpStream;
+use tokio_rustls::rustls::ClientConfig;
+use tokio_rustls::webpki::DNSNameRef;
+use tokio_rustls::TlsConnector;
+use warp::http::header::CONTENT_TYPE;
+use warp::http::header::HOST;
+use warp::http::status::StatusCode;
+use warp::http::Request;
+use warp::http::Uri;
+use warp::hyper;
+use warp::hyper::Body;
+
+/// Properties of `File` items that only make sense for the files stored in this Pod
+pub const LOCAL_FILE_PROPERTIES: &[&str] = &["key", "nonce"];
+
+/// A page of changes of a remote Pod, as returned by its `changes_since` endpoint
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PeerChanges {
+    pub items: Vec<Map<String, Value>>,
+    /// The last change of the peer that is included
+    pub change_seq: i64,
+    /// Whether the peer has more changes after this page
+    pub has_more: bool,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct ReplicationReport {
+    pub applied: usize,
+    /// Changes that were not applied: items that are the same in both Pods,
+    /// item types that are not replicated, and edges to items that don't exist
+    pub skipped: usize,
+    /// Items that exist in both Pods with different types, which are not applied
+    pub conflicts: Vec<String>,
+    pub change_seq: i64,
+}
+
+pub enum Outcome {
+    Created,
+    /// An existing item that was different
+    Updated,
+    Skipped,
+    /// An edge whose source or target doesn't exist (yet)
+    MissingEnds(Map<String, Value>),
+    /// An item that was not applied, for the given reason
+    Conflict(String),
+}
+
+/// Fetch a page of changes of the peer after its change `since`
+pub async fn fetch_changes(peer: &Replicate, since: i64, cli: &CliOptions) -> Result<PeerChanges> {
+    let url = format!(
+        "{}/v4/{}/changes_since",
+        peer.url.trim_end_matches('/'),
+        peer.owner_key
+    );
+    let request = json!({
+        "auth": { "type": "ClientAuth", "databaseKey": peer.database_key },
+        "payload": { "since": since, "limit": constants::REPLICATION_PAGE_SIZE },
+    });
+    let changes: PeerChanges =
+        serde_json::from_value(post_json(&url, &request, cli.insecure_replication_http).await?)
+            .map_err(|err| peer_error(format!("Unexpected changes from {}, {}", peer.url, err)))?;
+    info!("Fetched {} changes from {}", changes.items.len(), peer.url);
+    Ok(changes)
+}
+
+/// Apply a page of changes of a peer, and remember how far they go for the next replication.
+/// Ids and dates of the items are preserved, concurrent edits are resolved
+/// the same way as for clients (see `internal_api::update_item_tx`).
+///
+/// Edges whose source or target doesn't exist yet are kept in `pending_edges`
+/// and tried again after the next pages. As long as there are any,
+/// the checkpoint is not moved, so that an interrupted replication doesn't lose them.
+#[allow(clippy::too_many_arguments)]
+pub fn apply_changes(
+    tx: &Tx,
+    schema: &mut Schema,
+    peer: &Replicate,
+    changes: PeerChanges,
+    pending_edges: &mut Vec<Map<String, Value>>,
+    pod_owner: &str,
+    cli: &CliOptions,
+    database_key: &DatabaseKey,
+) -> Result<ReplicationReport> {
+    let author = format!(
+        "{}{}",
+        constants::REVISION_AUTHOR_REPLICATION_PREFIX,
+        peer.url
+    );
+    let mut report = ReplicationReport {
+        change_seq: changes.change_seq,
+        ..Default::default()
+    };
+    let apply = |schema: &mut Schema, item| {
+        apply_item(tx, schema, item, &author, pod_owner, cli, database_key)
+    };
+    for item in changes.items {
+        let id = item
+            .get("id")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string();
+        match apply(schema, item)? {
+            Outcome::Created | Outcome::Updated => report.applied += 1,
+            Outcome::Skipped => report.skipped += 1,
+            Outcome::MissingEnds(item) => pending_edges.push(item),
+            Outcome::Conflict(reason) => {
+                warn!("Not replicating item {} from {}, {}", id, peer.url, reason);
+                report.conflicts.push(format!("{}: {}", id, reason));
+            }
+        }
+    }
+    // Edges come before the items they connect if those items were changed afterwards
+    for item in std::mem::take(pending_edges) {
+        let id = item
+            .get("id")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string();
+        match apply(schema, item)? {
+            Outcome::Created | Outcome::Updated => report.applied += 1,
+            Outcome::Skipped => report.skipped += 1,
+            Outcome::MissingEnds(item) if changes.has_more => pending_edges.push(item),
+            Outcome::MissingEnds(item) => {
+                warn!(
+                    "Skipping replication of edge {:?} from {}, its source or target does not exist",
+                    item.get("id"),
+                    peer.url
+                );
+                report.skipped += 1;
+            }
+            Outcome::Conflict(reason) => {
+                warn!("Not replicating item {} from {}, {}", id, peer.url, reason);
+                report.conflicts.push(format!("{}: {}", id, reason));
+            }
+        }
+    }
+    if pending_edges.is_empty() {
+        let now = Utc::now().timestamp_millis();
+        database_api::set_replication_checkpoint(
+            tx,
+            &peer.url,
+            &peer.owner_key,
+            changes.change_seq,
+            now,
+        )?;
+    }
+    Ok(report)
+}
+
+/// Apply an item of another Pod, keeping its id and dates.
+/// Also used to import archives, which contain items in the same form.
+pub fn apply_item(
+    tx: &Tx,
+    schema: &mut Schema,
+    item: Map<String, Value>,
+    author: &str,
+    pod_owner: &str,
+    cli: &CliOptions,
+    database_key: &DatabaseKey,
+) -> Result<Outcome> {
+    let string_field = |name: &str| {
+        item.get(name)
+            .and_then(Value::as_str)
+            .map(|s| s.to_string())
+            .ok_or_else(|| peer_error(format!("Replicated item has no {}", name)))
+    };
+    if item.get("purged") == Some(&Value::Bool(true)) {
+        // Purging is an erasure in the peer only, local items are kept
+        return Ok(Outcome::Skipped);
+    }
+    let id = string_field("id")?;
+    let item_type = string_field("type")?;
+    if constants::REPLICATION_EXCLUDED_ITEM_TYPES.contains(&item_type.as_str()) {
+        return Ok(Outcome::Skipped);
+    }
+    let original = item.clone();
+    let mut item = without_local_properties(schema, &item_type, item);
+    let edge = match (
+        item.remove("_source"),
+        item.remove("_target"),
+        item.remove("_edge"),
+    ) {
+        (Some(Value::String(source)), Some(Value::String(target)), Some(Value::String(name))) => {
+            Some(CreateEdge {
+                source,
+                target,
+                name,
+                self_id: Some(id.clone()),
+            })
+        }
+        _ => None,
+    };
+
+    if let Some(rowid) = database_api::get_item_rowid(tx, &id)? {
+        let local = match get_item_from_rowid(tx, schema, rowid)? {
+            Value::Object(local) => without_local_properties(schema, &item_type, local),
+            _ => Map::new(),
+        };
+        let local_type = local
+            .get("type")
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        if local_type != item_type {
+            return Ok(Outcome::Conflict(format!(
+                "exists with type {} instead of {}, skipped",
+                local_type, item_type
+            )));
+        }
+        if local == item {
+            // Unchanged, e.g. a change that was replicated from this Pod in the first place
+            return Ok(Outcome::Skipped);
+        }
+        let mut fields: HashMap<String, Value> = item.into_iter().collect();
+        // Properties that the item doesn't have in the peer are erased
+        for name in local.keys() {
+            fields.entry(name.to_string()).or_insert(Value::Null);
+        }
+        fields.remove("id");
+        let update = UpdateItem {
+            id,
+            if_date_server_modified: None,
+            fields,
+        };
+        internal_api::update_item_tx(tx, schema, update, author)?;
+        return Ok(Outcome::Updated);
+    }
+
+    if let Some(edge) = &edge {
+        let source = database_api::get_item_rowid(tx, &edge.source)?;
+        let target = database_api::get_item_rowid(tx, &edge.target)?;
+        if source.is_none() || target.is_none() {
+            return Ok(Outcome::MissingEnds(original));
+        }
+    }
+    let create: CreateItem = serde_json::from_value(Value::Object(item))?;
+    internal_api::create_item_tx(tx, schema, create, pod_owner, cli, database_key)?;
+    if let Some(edge) = edge {
+        internal_api::create_edge(tx, edge)?;
+    }
+    Ok(Outcome::Created)
+}
+
+/// Remove what each Pod has on its own: `dateServerModified`, computed properties,
+/// and the `key` and `nonce` that local files are encrypted with (see `file_api`)
+fn without_local_properties(
+    schema: &Schema,
+    item_type: &str,
+    mut item: Map<String, Value>,
+) -> Map<String, Value> {
+    item.remove("dateServerModified");
+    if item_type == "File" {
+        for name in LOCAL_FILE_PROPERTIES {
+            item.remove(*name);
+        }
+    }
+    match schema.item_rules.get(item_type) {
+        Some(rules) => item
+            .into_iter()
+            .filter(|(name, _)| rules.get(name).map(|r| r.formula.is_none()).unwrap_or(true))
+            .collect(),
+        None => item,
+    }
+}
+
+/// Post JSON to another Pod. Plain http is only allowed with `allow_http`,
+/// the request contains the database key of the other Pod
+async fn post_json(url: &str, body: &Value, allow_http: bool) -> Result<Value> {
+    let uri: Uri = url
+        .parse()
+        .map_err(|err| bad_request(format!("Invalid url {}, {}", url, err)))?;
+    let https = match uri.scheme_str() {
+        Some("https") => true,
+        Some("http") if allow_http => false,
+        Some("http") => {
+            return Err(bad_request(format!(
+                "Url {} is not https, see --insecure-replication-http",
+                url
+            )))
+        }
+        _ => return Err(bad_request(format!("Url {} is not https", url))),
+    };
+    let (host, authority) = match (uri.host(), uri.authority()) {
+        (Some(host), Some(authority)) => (host.to_string(), authority.to_string()),
+        _ => return Err(bad_request(format!("Url {} has no host", url))),
+    };
+    let port = uri.port_u16().unwrap_or(if https { 443 } else { 80 });
+    let path = uri.path_and_query().map(|p| p.as_str()).unwrap_or("/");
+    let request = Request::post(path)
+        .header(HOST, authority)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .map_err(|err| peer_error(format!("Failed to build request to {}, {}", url, err)))?;
+
+    let connect_timeout = constants::REPLICATION_CONNECT_TIMEOUT_SECONDS;
+    let response_timeout = constants::REPLICATION_RESPONSE_TIMEOUT_SECONDS;
+    let stream = with_timeout(connect_timeout, url, async {
+        TcpStream::connect((host.as_str(), port))
+            .await
+            .map_err(|err| peer_error(format!("Failed to connect to {}, {}", url, err)))
+    })
+    .await?;
+    let (status, bytes) = if https {
+        let mut config = ClientConfig::new();
+        config
+            .root_store
+            .add_server_trust_anchors(&webpki_roots::TLS_SERVER_ROOTS);
+        let connector = TlsConnector::from(Arc::new(config));
+        let dns_name = DNSNameRef::try_from_ascii_str(&host)
+            .map_err(|err| peer_error(format!("Invalid host {}, {}", host, err)))?;
+        let stream = with_timeout(connect_timeout, url, async {
+            connector
+                .connect(dns_name, stream)
+                .await
+                .map_err(|err| peer_error(format!("TLS connection to {} failed, {}", url, err)))
+        })
+        .await?;
+        with_timeout(response_timeout, url, send_request(stream, request, url)).await?
+    } else {
+        with_timeout(response_timeout, url, send_request(stream, request, url)).await?
+    };
+
+    if !status.is_success() {
+        let body = String::from_utf8_lossy(&bytes);
+        return Err(peer_error(format!(
+            "{} responded {}, {}",
+            url, status, body
+        )));
+    }
+    serde_json::from_slice(&bytes)
+        .map_err(|err| peer_error(format!("Invalid JSON response of {}, {}", url, err)))
+}
+
+/// Send the request and read the whole response
+async fn send_request<S>(
+    stream: S,
+    request: Request<Body>,
+    url: &str,
+) -> Result<(StatusCode, Bytes)>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (mut sender, connection) = hyper::client::conn::handshake(stream)
+        .await
+        .map_err(|err| peer_error(format!("HTTP handshake failed, {}", err)))?;
+    tokio::spawn(async move {
+        if let Err(err) = connection.await {
+            warn!("Connection to replicated Pod failed, {}", err);
+        }
+    });
+    let response = sender
+        .send_request(request)
+        .await
+        .map_err(|err| peer_error(format!("HTTP request failed, {}", err)))?;
+    let status = response.status();
+    let bytes = hyper::body::to_bytes(response.into_body())
+        .await
+        .map_err(|err| peer_error(format!("Failed to read response of {}, {}", url, err)))?;
+    Ok((status, bytes))
+}
+
+/// Fail with `504 Gateway Timeout` if the peer at `url` doesn't answer within `seconds`,
+/// so that an unresponsive peer doesn't block replication forever
+async fn with_timeout<T>(
+    seconds: u64,
+    url: &str,
+    future: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    match tokio::time::timeout(Duration::from_secs(seconds), future).await {
+        Ok(result) => result,
+        Err(_) => Err(Error {
+            code: StatusCode::GATEWAY_TIMEOUT,
+            msg: format!("{} did not respond within {} seconds", url, seconds),
+        }),
+    }
+}
+
+fn peer_error(msg: String) -> Error {
+    Error {
+        code: StatusCode::BAD_GATEWAY,
+        msg,
+    }
+}
+
+fn bad_request(msg: String) -> Error {
+    Error {
+        code: StatusCode::BAD_REQUEST,
+        msg,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api_model::ChangesSince;
+    use crate::command_line_interface;
+    use crate::database_api::tests::new_conn;
+
+    #[test]
+    fn test_apply_changes() {
+        let cli = command_line_interface::tests::test_cli();
+        let db_key = DatabaseKey::from("".to_string()).unwrap();
+        let peer = Replicate {
+            url: "http://peer.example".to_string(),
+            owner_key: "peer-owner".to_string(),
+            database_key: "".to_string(),
+        };
+
+        let mut source_conn = new_conn();
+        let source_tx = source_conn.transaction().unwrap();
+        let mut source_schema = database_api::get_schema(&source_tx).unwrap();
+        for item in [
+            json!({"id": "person-one", "type": "Person"}),
+            json!({"id": "person-two", "type": "Person"}),
+        ] {
+            let item: CreateItem = serde_json::from_value(item).unwrap();
+            internal_api::create_item_tx(&source_tx, &mut source_schema, item, "", &cli, &db_key)
+                .unwrap();
+        }
+        let edge = CreateEdge {
+            source: "person-one".to_string(),
+            target: "person-two".to_string(),
+            name: "friend".to_string(),
+            self_id: None,
+        };
+        let edge_id = internal_api::create_edge(&source_tx, edge).unwrap();
+        let changes = internal_api::changes_since(
+            &source_tx,
+            &source_schema,
+            ChangesSince {
+                since: 0,
+                limit: 1000,
+                client_id: None,
+            },
+        )
+        .unwrap();
+        let mut items: Vec<Map<String, Value>> =
+            serde_json::from_value(changes["items"].clone()).unwrap();
+        // Plugin runs belong to the Pod that runs the plugin
+        let plugin_run = json!({"id": "plugin-run-1", "type": "PluginRun"});
+        items.push(serde_json::from_value(plugin_run).unwrap());
+        // Clients of the peer acknowledge changes there, not here
+        let sync_client = json!({"id": "peer-laptop", "type": "SyncClient", "ackedChangeSeq": 1});
+        items.push(serde_json::from_value(sync_client).unwrap());
+        let change_seq = changes["changeSeq"].as_i64().unwrap();
+        let peer_changes = || PeerChanges {
+            items: items.clone(),
+            change_seq,
+            has_more: false,
+        };
+
+        let mut conn = new_conn();
+        let tx = conn.transaction().unwrap();
+        let mut schema = database_api::get_schema(&tx).unwrap();
+        let mut pending_edges = Vec::new();
+        let mut apply = |changes| {
+            let pending_edges = &mut pending_edges;
+            apply_changes(
+                &tx,
+                &mut schema,
+                &peer,
+                changes,
+                pending_edges,
+                "",
+                &cli,
+                &db_key,
+            )
+            .unwrap()
+        };
+        let report = apply(peer_changes());
+        // Schema items exist in both Pods from the start
+        assert_eq!(report.applied, 3);
+        assert_eq!(report.skipped, items.len() - 3);
+        assert_eq!(report.change_seq, change_seq);
+        assert!(database_api::get_item_rowid(&tx, "person-one")
+            .unwrap()
+            .is_some());
+        assert!(database_api::get_item_rowid(&tx, "plugin-run-1")
+            .unwrap()
+            .is_none());
+        assert!(database_api::get_item_rowid(&tx, "peer-laptop")
+            .unwrap()
+            .is_none());
+        assert_eq!(
+            database_api::get_min_acknowledged_change_seq(&tx).unwrap(),
+            None
+        );
+        let edge_rowid = database_api::get_item_rowid(&tx, &edge_id)
+            .unwrap()
+            .unwrap();
+        let edge = database_api::get_self_edge(&tx, edge_rowid)
+            .unwrap()
+            .unwrap();
+        assert_eq!(edge.name, "friend");
+        let checkpoint =
+            database_api::get_replication_checkpoint(&tx, &peer.url, &peer.owner_key).unwrap();
+        assert_eq!(checkpoint, report.change_seq);
+
+        // Applying the same changes again is a no-op
+        let report = apply(peer_changes());
+        assert_eq!(report.applied, 0);
+        assert_eq!(report.skipped, items.len());
+        assert!(pending_edges.is_empty());
+    }
+
+    #[test]
+    fn test_apply_edge_before_its_target_in_a_later_page() {
+        let cli = command_line_interface::tests::test_cli();
+        let db_key = DatabaseKey::from("".to_string()).unwrap();
+        let peer = Replicate {
+            url: "http://peer.example".to_string(),
+            owner_key: "peer-owner".to_string(),
+            database_key: "".to_string(),
+        };
+        let item = |value: Value| serde_json::from_value(value).unwrap();
+        let first_page = PeerChanges {
+            items: vec![
+                item(json!({"id": "person-one", "type": "Person"})),
+                item(
+                    json!({"id": "edge-1", "type": "Edge", "_source": "person-one",
+                    "_target": "person-two", "_edge": "friend"}),
+                ),
+            ],
+            change_seq: 2,
+            has_more: true,
+        };
+        let second_page = PeerChanges {
+            items: vec![item(json!({"id": "person-two", "type": "Person"}))],
+            change_seq: 3,
+            has_more: false,
+        };
+
+        let mut conn = new_conn();
+        let tx = conn.transaction().unwrap();
+        let mut schema = database_api::get_schema(&tx).unwrap();
+        let mut pending_edges = Vec::new();
+        let mut apply = |changes| {
+            let pending_edges = &mut pending_edges;
+            apply_changes(
+                &tx,
+                &mut schema,
+                &peer,
+                changes,
+                pending_edges,
+                "",
+                &cli,
+                &db_key,
+            )
+            .unwrap()
+        };
+        let checkpoint = |tx: &Tx| {
+            database_api::get_replication_checkpoint(tx, &peer.url, &peer.owner_key).unwrap()
+        };
+
+        let report = apply(first_page);
+        assert_eq!(report.applied, 1);
+        // The edge waits for its target, an interrupted replication starts over from here
+        assert_eq!(checkpoint(&tx), 0);
+        let report = apply(second_page);
+        assert_eq!(report.applied, 2);
+        assert!(database_api::get_item_rowid(&tx, "edge-1")
+            .unwrap()
+            .is_some());
+        assert!(pending_edges.is_empty());
+        assert_eq!(checkpoint(&tx), 3);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_changes_requires_https() {
+        let mut cli = command_line_interface::tests::test_cli();
+        let peer = Replicate {
+            url: "http://127.0.0.1:1".to_string(),
+            owner_key: "peer-owner".to_string(),
+            database_key: "".to_string(),
+        };
+        let err = fetch_changes(&peer, 0, &cli).await.err().unwrap();
+        assert_eq!(err.code, StatusCode::BAD_REQUEST);
+        // Allowed, but there is no Pod to connect to
+        cli.insecure_replication_http = true;
+        let err = fetch_changes(&peer, 0, &cli).await.err().unwrap();
+        assert_eq!(err.code, StatusCode::BAD_GATEWAY);
+    }
+
+    #[test]
+    fn test_apply_file_keeps_local_key_and_nonce() {
+        let cli = command_line_interface::tests::test_cli();
+        let db_key = DatabaseKey::from("".to_string()).unwrap();
+        let mut conn = new_conn();
+        let tx = conn.transaction().unwrap();
+        let mut schema = database_api::get_schema(&tx).unwrap();
+        let local = json!({"id": "file-1", "type": "File", "sha256": "abc",
+            "key": "local-key", "nonce": "local-nonce", "dateModified": 1000});
+        let local: CreateItem = serde_json::from_value(local).unwrap();
+        internal_api::create_item_tx(&tx, &mut schema, local, "", &cli, &db_key).unwrap();
+
+        let peer_file = |id: &str| {
+            let item = json!({"id": id, "type": "File", "sha256": "def",
+                "key": "peer-key", "nonce": "peer-nonce", "dateModified": 2000});
+            serde_json::from_value(item).unwrap()
+        };
+        for id in ["file-1", "file-2"] {
+            apply_item(&tx, &mut schema, peer_file(id), "peer", "", &cli, &db_key).unwrap();
+        }
+
+        let strings = |id: &str| {
+            let rowid = database_api::get_item_rowid(&tx, id).unwrap().unwrap();
+            database_api::get_strings_for_item(&tx, rowid).unwrap()
+        };
+        let file = strings("file-1");
+        assert_eq!(file["sha256"], "def");
+        assert_eq!(file["key"], "local-key");
+        assert_eq!(file["nonce"], "local-nonce");
+        let file = strings("file-2");
+        assert_eq!(file["sha256"], "def");
+        assert!(!file.contains_key("key"));
+        assert!(!file.contains_key("nonce"));
+    }
+
+    #[test]
+    fn test_apply_item_of_other_type() {
+        let cli = command_line_interface::tests::test_cli();
+        let db_key = DatabaseKey::from("".to_string()).unwrap();
+        let mut conn = new_conn();
+        let tx = conn.transaction().unwrap();
+        let mut schema = database_api::get_schema(&tx).unwrap();
+        let local = json!({"id": "item-1", "type": "Person", "dateModified": 1000});
+        let local: CreateItem = serde_json::from_value(local).unwrap();
+        internal_api::create_item_tx(&tx, &mut schema, local, "", &cli, &db_key).unwrap();
+
+        let peer_item = json!({"id": "item-1", "type": "Note", "dateModified": 2000});
+        let peer_item = serde_json::from_value(peer_item).unwrap();
+        let outcome = apply_item(&tx, &mut schema, peer_item, "peer", "", &cli, &db_key).unwrap();
+        assert!(matches!(outcome, Outcome::Conflict(_)));
+        let rowid = database_api::get_item_rowid(&tx, "item-1")
+            .unwrap()
+            .unwrap();
+        let base = database_api::get_item_base(&tx, rowid).unwrap().unwrap();
+        assert_eq!(base._type, "Person");
+        assert_eq!(base.date_modified, 1000);
+    }
+}
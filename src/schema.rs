@@ -4,8 +4,11 @@ use lazy_static::lazy_static;
 use regex::Regex;
 use serde::Deserialize;
 use serde::Serialize;
+use serde_json::Map;
+use serde_json::Value;
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::sync::Arc;
 use warp::http::StatusCode;
 
 /// See `README.md#understanding-the-schema` to understand possible
@@ -26,6 +29,20 @@ impl std::fmt::Display for SchemaPropertyType {
 }
 
 impl SchemaPropertyType {
+    /// Parse a textual value, e.g. a `defaultValue` declared in the Schema,
+    /// into the JSON value of this type.
+    pub fn parse_value(&self, str: &str) -> std::result::Result<Value, String> {
+        let value = match self {
+            SchemaPropertyType::Text => Some(Value::String(str.to_string())),
+            SchemaPropertyType::Integer | SchemaPropertyType::DateTime => {
+                str.parse::<i64>().ok().map(Value::from)
+            }
+            SchemaPropertyType::Real => str.parse::<f64>().ok().map(Value::from),
+            SchemaPropertyType::Bool => str.parse::<bool>().ok().map(Value::from),
+        };
+        value.ok_or_else(|| format!("Failed to parse value {} as {}", str, self))
+    }
+
     pub fn from_string(str: &str) -> std::result::Result<SchemaPropertyType, String> {
         match str {
             "Text" => Ok(SchemaPropertyType::Text),
@@ -42,9 +59,222 @@ impl SchemaPropertyType {
     }
 }
 
-#[derive(Debug)]
+/// Constraints that an `ItemPropertySchema` item can declare
+/// for a property of one particular item type.
+/// See `docs/HTTP_API.md#schema-api` for their meaning.
+#[derive(Debug, Clone, Default)]
+pub struct PropertyRules {
+    pub required: bool,
+    pub unique: bool,
+    pub pattern: Option<Regex>,
+    pub minimum: Option<f64>,
+    pub maximum: Option<f64>,
+    /// Value set on item creation if the property is not given
+    pub default: Option<Value>,
+    /// If set, the property is computed from other properties and cannot be written directly
+    pub formula: Option<Formula>,
+}
+
+impl PartialEq for PropertyRules {
+    fn eq(&self, other: &Self) -> bool {
+        self.required == other.required
+            && self.unique == other.unique
+            && self.pattern.as_ref().map(Regex::as_str) == other.pattern.as_ref().map(Regex::as_str)
+            && self.minimum == other.minimum
+            && self.maximum == other.maximum
+            && self.default == other.default
+            && self.formula == other.formula
+    }
+}
+
+impl PropertyRules {
+    /// Combine rules of several `ItemPropertySchema` items declaring the same property.
+    /// Flags accumulate, and values declared later take precedence.
+    pub fn merge(&mut self, other: PropertyRules) {
+        self.required |= other.required;
+        self.unique |= other.unique;
+        if other.pattern.is_some() {
+            self.pattern = other.pattern;
+        }
+        if other.minimum.is_some() {
+            self.minimum = other.minimum;
+        }
+        if other.maximum.is_some() {
+            self.maximum = other.maximum;
+        }
+        if other.default.is_some() {
+            self.default = other.default;
+        }
+        if other.formula.is_some() {
+            self.formula = other.formula;
+        }
+    }
+}
+
+/// The Schema of a database.
+///
+/// Clones share their content until it is changed, so that a Schema can be cloned cheaply
+/// for each request (see `SchemaCache`), and the changes can be detected with `is_changed_from`.
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct Schema {
-    pub property_types: HashMap<String, SchemaPropertyType>,
+    pub property_types: Arc<HashMap<String, SchemaPropertyType>>,
+    /// Rules of properties, indexed by item type and then by property name.
+    pub item_rules: Arc<HashMap<String, HashMap<String, PropertyRules>>>,
+    /// Item types for which revisions are kept when items are updated,
+    /// as declared by `ItemTypeSchema` items.
+    pub revision_item_types: Arc<HashSet<String>>,
+}
+
+impl Schema {
+    pub fn add_property_type(&mut self, property: String, value_type: SchemaPropertyType) {
+        Arc::make_mut(&mut self.property_types).insert(property, value_type);
+    }
+
+    pub fn add_property_rules(&mut self, item_type: &str, property: &str, rules: PropertyRules) {
+        Arc::make_mut(&mut self.item_rules)
+            .entry(item_type.to_string())
+            .or_default()
+            .entry(property.to_string())
+            .or_default()
+            .merge(rules);
+    }
+
+    pub fn add_revision_item_type(&mut self, item_type: String) {
+        Arc::make_mut(&mut self.revision_item_types).insert(item_type);
+    }
+
+    /// Whether this Schema was changed (or replaced) since it was cloned from `original`
+    pub fn is_changed_from(&self, original: &Schema) -> bool {
+        !Arc::ptr_eq(&self.property_types, &original.property_types)
+            || !Arc::ptr_eq(&self.item_rules, &original.item_rules)
+            || !Arc::ptr_eq(&self.revision_item_types, &original.revision_item_types)
+    }
+}
+
+/// Formula of a computed property, a template like `"{firstName} {lastName}"`
+/// where each `{property}` is replaced by the value of that property of the item.
+/// A property can be followed by a modifier, e.g. `{email|domain}`,
+/// see `FormulaModifier` for the supported ones.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Formula {
+    parts: Vec<FormulaPart>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum FormulaPart {
+    Literal(String),
+    Property(String, Option<FormulaModifier>),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum FormulaModifier {
+    /// Part of an email address after the `@`
+    Domain,
+    Lowercase,
+    Uppercase,
+    Trim,
+}
+
+impl Formula {
+    pub fn parse(formula: &str) -> Result<Formula> {
+        let error = |msg: &str| Error {
+            code: StatusCode::BAD_REQUEST,
+            msg: format!("Invalid property formula {}, {}", formula, msg),
+        };
+        let mut parts = Vec::new();
+        let mut rest = formula;
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                parts.push(FormulaPart::Literal(rest[..start].to_string()));
+            }
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| error("unclosed '{'"))?;
+            let placeholder = &rest[start + 1..start + end];
+            let (name, modifier) = match placeholder.split_once('|') {
+                Some((name, modifier)) => (name, Some(modifier)),
+                None => (placeholder, None),
+            };
+            validate_property_name_syntax(name)?;
+            let modifier = match modifier {
+                None => None,
+                Some("domain") => Some(FormulaModifier::Domain),
+                Some("lowercase") => Some(FormulaModifier::Lowercase),
+                Some("uppercase") => Some(FormulaModifier::Uppercase),
+                Some("trim") => Some(FormulaModifier::Trim),
+                Some(other) => return Err(error(&format!("unknown modifier {}", other))),
+            };
+            parts.push(FormulaPart::Property(name.to_string(), modifier));
+            rest = &rest[start + end + 1..];
+        }
+        if rest.contains('}') {
+            return Err(error("unexpected '}'"));
+        }
+        if !rest.is_empty() {
+            parts.push(FormulaPart::Literal(rest.to_string()));
+        }
+        Ok(Formula { parts })
+    }
+
+    /// Names of the properties that the formula uses
+    pub fn properties(&self) -> impl Iterator<Item = &str> {
+        self.parts.iter().filter_map(|part| match part {
+            FormulaPart::Property(name, _) => Some(name.as_str()),
+            FormulaPart::Literal(_) => None,
+        })
+    }
+
+    /// Compute the value from the item's properties.
+    /// Missing properties are treated as empty, and the result is trimmed.
+    /// `None` is returned if the result is empty.
+    pub fn compute(&self, properties: &Map<String, Value>) -> Option<String> {
+        let mut result = String::new();
+        for part in &self.parts {
+            match part {
+                FormulaPart::Literal(literal) => result.push_str(literal),
+                FormulaPart::Property(name, modifier) => {
+                    let value = match properties.get(name) {
+                        None | Some(Value::Null) => String::new(),
+                        Some(Value::String(s)) => s.to_string(),
+                        Some(other) => other.to_string(),
+                    };
+                    let value = match modifier {
+                        None => value,
+                        Some(FormulaModifier::Domain) => match value.rsplit_once('@') {
+                            Some((_, domain)) => domain.to_string(),
+                            None => String::new(),
+                        },
+                        Some(FormulaModifier::Lowercase) => value.to_lowercase(),
+                        Some(FormulaModifier::Uppercase) => value.to_uppercase(),
+                        Some(FormulaModifier::Trim) => value.trim().to_string(),
+                    };
+                    result.push_str(&value);
+                }
+            }
+        }
+        let result = result.trim();
+        if result.is_empty() {
+            None
+        } else {
+            Some(result.to_string())
+        }
+    }
+}
+
+/// Parse a `defaultValue` declared in the Schema for a property of type `value_type`.
+pub fn parse_default_value(value_type: &SchemaPropertyType, value: &str) -> Result<Value> {
+    value_type.parse_value(value).map_err(|msg| Error {
+        code: StatusCode::BAD_REQUEST,
+        msg: format!("Invalid default value, {}", msg),
+    })
+}
+
+/// Validate and compile a `pattern` constraint declared in the Schema.
+pub fn parse_property_pattern(pattern: &str) -> Result<Regex> {
+    Regex::new(pattern).map_err(|err| Error {
+        code: StatusCode::BAD_REQUEST,
+        msg: format!("Invalid property pattern {}, {}", pattern, err),
+    })
 }
 
 /// Validation of _new_ item ids. Note that it is not applied to already existing
@@ -276,3 +506,41 @@ const SQLITE_RESERVED_KEYWORDS: &[&str] = &[
     "WITH",
     "WITHOUT",
 ];
+
+#[cfg(test)]
+mod tests {
+    use super::Formula;
+    use crate::error::Result;
+    use serde_json::json;
+
+    #[test]
+    fn test_formula() -> Result<()> {
+        let full_name = Formula::parse("{firstName} {lastName}")?;
+        assert_eq!(
+            full_name.properties().collect::<Vec<_>>(),
+            vec!["firstName", "lastName"]
+        );
+        let props = json!({"firstName": "Ada", "lastName": "Lovelace"});
+        let props = props.as_object().unwrap();
+        assert_eq!(full_name.compute(props), Some("Ada Lovelace".to_string()));
+        let props = json!({"lastName": "Lovelace"});
+        assert_eq!(
+            full_name.compute(props.as_object().unwrap()),
+            Some("Lovelace".to_string())
+        );
+        let props = json!({});
+        assert_eq!(full_name.compute(props.as_object().unwrap()), None);
+
+        let domain = Formula::parse("{email|domain}")?;
+        let props = json!({"email": "ada@example.com"});
+        assert_eq!(
+            domain.compute(props.as_object().unwrap()),
+            Some("example.com".to_string())
+        );
+
+        assert!(Formula::parse("{firstName").is_err());
+        assert!(Formula::parse("firstName}").is_err());
+        assert!(Formula::parse("{email|reverse}").is_err());
+        Ok(())
+    }
+}
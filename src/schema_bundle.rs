@@ -0,0 +1,249 @@
+//
+// Schema bundles: a file of Schema items applied to databases when they are opened
+//
+
+use crate::api_model::Bulk;
+use crate::api_model::CreateItem;
+use crate::command_line_interface::CliOptions;
+use crate::database_api;
+use crate::error::Error;
+use crate::error::ErrorContext;
+use crate::error::Result;
+use crate::internal_api;
+use crate::plugin_auth_crypto::DatabaseKey;
+use crate::schema::Schema;
+use crate::triggers::SchemaItem;
+use log::info;
+use log::warn;
+use rusqlite::Transaction as Tx;
+use warp::http::StatusCode;
+
+/// Read a Schema bundle file, either in JSON or (if the extension says so) YAML format.
+pub fn load_schema_bundle(path: &str) -> Result<Vec<CreateItem>> {
+    let content = std::fs::read_to_string(path).map_err(|err| Error {
+        code: StatusCode::INTERNAL_SERVER_ERROR,
+        msg: format!("Failed to read Schema bundle file {}, {}", path, err),
+    })?;
+    let is_yaml = path.ends_with(".yaml") || path.ends_with(".yml");
+    parse_schema_bundle(&content, is_yaml).context(|| format!("Schema bundle file {}", path))
+}
+
+/// Parse a Schema bundle. The format is that of a `bulk` request payload
+/// where only `createItems` of type "ItemPropertySchema" are allowed.
+pub fn parse_schema_bundle(content: &str, is_yaml: bool) -> Result<Vec<CreateItem>> {
+    let bulk: Bulk = if is_yaml {
+        serde_yaml::from_str(content)?
+    } else {
+        let json = &mut serde_json::Deserializer::from_str(content);
+        serde_path_to_error::deserialize(json)?
+    };
+    if !bulk.update_items.is_empty()
+        || !bulk.delete_items.is_empty()
+        || !bulk.create_edges.is_empty()
+        || !bulk.search.is_empty()
+    {
+        return Err(Error {
+            code: StatusCode::BAD_REQUEST,
+            msg: "Schema bundle can only contain createItems".to_string(),
+        });
+    }
+    if let Some(item) = bulk
+        .create_items
+        .iter()
+        .find(|item| item._type != "ItemPropertySchema")
+    {
+        return Err(Error {
+            code: StatusCode::BAD_REQUEST,
+            msg: format!(
+                "Schema bundle can only contain items of type ItemPropertySchema, found {}",
+                item._type
+            ),
+        });
+    }
+    Ok(bulk.create_items)
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct SchemaBundleReport {
+    pub added: usize,
+    pub existing: usize,
+    pub conflicts: Vec<String>,
+}
+
+/// Add Schema items from a bundle to the database, idempotently.
+///
+/// Items that are already declared the same way (for the same `itemType` and `propertyName`,
+/// with the same `valueType` and rules) are skipped. Items that cannot be added,
+/// e.g. because the property is already defined with a different type or different rules,
+/// are reported as conflicts and do not stop other items from being added.
+pub fn apply_schema_bundle(
+    tx: &Tx,
+    schema: &mut Schema,
+    items: &[CreateItem],
+    pod_owner: &str,
+    cli: &CliOptions,
+    database_key: &DatabaseKey,
+) -> Result<SchemaBundleReport> {
+    let mut report = SchemaBundleReport::default();
+    for item in items {
+        let parsed: SchemaItem = serde_json::from_value(serde_json::to_value(item)?)
+            .context(|| format!("Parsing of Schema bundle item {:?}", item))?;
+        let already_declared = !database_api::get_schema_items_by_item_type_and_prop(
+            tx,
+            &parsed.item_type,
+            &parsed.property_name,
+        )?
+        .is_empty();
+        let id_taken = match &item.id {
+            Some(id) => database_api::get_item_rowid(tx, id)?.is_some(),
+            None => false,
+        };
+        if already_declared {
+            match declaration_mismatch(schema, &parsed) {
+                Some(mismatch) => report.conflicts.push(format!(
+                    "{}.{}: {}",
+                    parsed.item_type, parsed.property_name, mismatch
+                )),
+                None => report.existing += 1,
+            }
+            continue;
+        }
+        if id_taken {
+            report.existing += 1;
+            continue;
+        }
+        let result = database_api::in_savepoint(tx, || {
+            internal_api::create_item_tx(tx, schema, item.clone(), pod_owner, cli, database_key)
+        });
+        match result {
+            Ok(_id) => report.added += 1,
+            Err(err) => report.conflicts.push(format!(
+                "{}.{}: {}",
+                parsed.item_type, parsed.property_name, err.msg
+            )),
+        }
+    }
+    info!(
+        "Applied Schema bundle for owner {}: {} items added, {} already existed, {} conflicts",
+        pod_owner,
+        report.added,
+        report.existing,
+        report.conflicts.len()
+    );
+    for conflict in &report.conflicts {
+        warn!("Schema bundle conflict, {}", conflict);
+    }
+    Ok(report)
+}
+
+/// How a Schema bundle item differs from the existing declaration of its property,
+/// `None` if it declares the same `valueType` and rules
+fn declaration_mismatch(schema: &Schema, item: &SchemaItem) -> Option<String> {
+    let declared_type = schema.property_types.get(&item.property_name);
+    if declared_type != Some(&item.value_type) {
+        let declared_type = declared_type.map(|t| t.to_string()).unwrap_or_default();
+        return Some(format!(
+            "already declared with valueType {}, not {}",
+            declared_type, item.value_type
+        ));
+    }
+    let rules = match item.property_rules() {
+        Ok(rules) => rules,
+        Err(err) => return Some(err.msg),
+    };
+    let declared_rules = schema
+        .item_rules
+        .get(&item.item_type)
+        .and_then(|rules| rules.get(&item.property_name))
+        .cloned()
+        .unwrap_or_default();
+    if declared_rules != rules {
+        return Some(format!(
+            "already declared with different rules {:?}",
+            declared_rules
+        ));
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command_line_interface;
+    use crate::database_api::tests::new_conn;
+    use crate::schema::SchemaPropertyType;
+
+    #[test]
+    fn test_parse_formats() -> Result<()> {
+        let json = r#"{"createItems": [
+            {"type": "ItemPropertySchema", "itemType": "Person",
+             "propertyName": "email", "valueType": "Text", "isUnique": true}
+        ]}"#;
+        let yaml = "
+createItems:
+  - type: ItemPropertySchema
+    itemType: Person
+    propertyName: email
+    valueType: Text
+    isUnique: true
+";
+        let from_json = parse_schema_bundle(json, false)?;
+        let from_yaml = parse_schema_bundle(yaml, true)?;
+        assert_eq!(from_json.len(), 1);
+        assert_eq!(
+            serde_json::to_value(&from_json)?,
+            serde_json::to_value(&from_yaml)?
+        );
+
+        let not_schema = r#"{"createItems": [{"type": "Person"}]}"#;
+        assert!(parse_schema_bundle(not_schema, false).is_err());
+        let not_create = r#"{"deleteItems": ["12345678"]}"#;
+        assert!(parse_schema_bundle(not_create, false).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_idempotently() -> Result<()> {
+        let mut conn = new_conn();
+        let tx = conn.transaction()?;
+        let cli = command_line_interface::tests::test_cli();
+        let database_key = DatabaseKey::from("".to_string())?;
+        let bundle = r#"{"createItems": [
+            {"type": "ItemPropertySchema", "itemType": "Person",
+             "propertyName": "email", "valueType": "Text"},
+            {"type": "ItemPropertySchema", "itemType": "Person",
+             "propertyName": "dateCreated", "valueType": "Text"}
+        ]}"#;
+        let items = parse_schema_bundle(bundle, false)?;
+
+        let mut schema = database_api::get_schema(&tx)?;
+        let report = apply_schema_bundle(&tx, &mut schema, &items, "", &cli, &database_key)?;
+        assert_eq!(report.added, 1);
+        assert_eq!(report.conflicts.len(), 1, "{:?}", report.conflicts);
+
+        let mut schema = database_api::get_schema(&tx)?;
+        assert_eq!(
+            schema.property_types.get("email"),
+            Some(&SchemaPropertyType::Text)
+        );
+        let report = apply_schema_bundle(&tx, &mut schema, &items, "", &cli, &database_key)?;
+        assert_eq!(report.added, 0);
+        assert_eq!(report.existing, 1);
+        assert_eq!(report.conflicts.len(), 1);
+
+        // The same property declared differently is a conflict, not an existing item
+        let bundle = r#"{"createItems": [
+            {"type": "ItemPropertySchema", "itemType": "Person",
+             "propertyName": "email", "valueType": "Integer"},
+            {"type": "ItemPropertySchema", "itemType": "Person",
+             "propertyName": "email", "valueType": "Text", "isUnique": true}
+        ]}"#;
+        let items = parse_schema_bundle(bundle, false)?;
+        let report = apply_schema_bundle(&tx, &mut schema, &items, "", &cli, &database_key)?;
+        assert_eq!(report.added, 0);
+        assert_eq!(report.existing, 0);
+        assert_eq!(report.conflicts.len(), 2, "{:?}", report.conflicts);
+        assert!(report.conflicts[0].contains("valueType Text, not Integer"));
+        Ok(())
+    }
+}
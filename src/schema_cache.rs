@@ -0,0 +1,80 @@
+use crate::database_api;
+use crate::error::Result;
+use crate::schema::Schema;
+use rusqlite::Transaction as Tx;
+use std::collections::HashMap;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::RwLock;
+
+/// Parsed Schema of each owner's database, so that it doesn't need
+/// to be read from the database on every request.
+///
+/// Requests that change the Schema (e.g. when a new `ItemPropertySchema` item is added)
+/// need to `invalidate` the owner's entry after their transaction is committed,
+/// see `Schema::is_changed_from`.
+#[derive(Default)]
+pub struct SchemaCache {
+    schemas: RwLock<HashMap<String, Arc<Schema>>>,
+    /// Incremented on every invalidation. A Schema read from the database is only cached
+    /// if no invalidation happened in the meantime, as it might have been read
+    /// before the invalidating transaction was committed.
+    generation: AtomicU64,
+}
+
+impl SchemaCache {
+    /// Get the owner's Schema, reading it within the transaction `tx` if it is not cached yet.
+    /// This should be called before any other reads in the transaction.
+    pub fn get(&self, owner: &str, tx: &Tx) -> Result<Arc<Schema>> {
+        if let Some(schema) = self.schemas.read()?.get(owner) {
+            return Ok(schema.clone());
+        }
+        let generation = self.generation.load(Ordering::SeqCst);
+        let schema = Arc::new(database_api::get_schema(tx)?);
+        let mut schemas = self.schemas.write()?;
+        if self.generation.load(Ordering::SeqCst) == generation {
+            schemas.insert(owner.to_string(), schema.clone());
+        }
+        Ok(schema)
+    }
+
+    pub fn invalidate(&self, owner: &str) -> Result<()> {
+        let mut schemas = self.schemas.write()?;
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        schemas.remove(owner);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SchemaCache;
+    use crate::database_api::tests::new_conn;
+    use crate::error::Result;
+    use crate::schema::Schema;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_get_and_invalidate() -> Result<()> {
+        let mut conn = new_conn();
+        let cache = SchemaCache::default();
+        let tx = conn.transaction()?;
+        let first = cache.get("owner", &tx)?;
+        assert!(Arc::ptr_eq(&first, &cache.get("owner", &tx)?));
+        assert!(!Arc::ptr_eq(&first, &cache.get("other", &tx)?));
+
+        cache.invalidate("owner")?;
+        let second = cache.get("owner", &tx)?;
+        assert!(!Arc::ptr_eq(&first, &second));
+        assert_eq!(first, second);
+
+        // Requests get clones of the cached Schema, changes of which are detected
+        let mut schema = Schema::clone(&second);
+        assert!(!schema.is_changed_from(&second));
+        schema.add_revision_item_type("Note".to_string());
+        assert!(schema.is_changed_from(&second));
+        assert!(!second.revision_item_types.contains("Note"));
+        Ok(())
+    }
+}
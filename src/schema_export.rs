@@ -0,0 +1,189 @@
+//
+// Export of the Schema as JSON Schema / OpenAPI definitions, for generating typed client models
+//
+
+use crate::api_model::SchemaExportFormat;
+use crate::database_api::EdgeType;
+use crate::schema::PropertyRules;
+use crate::schema::Schema;
+use crate::schema::SchemaPropertyType;
+use serde_json::json;
+use serde_json::Map;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+
+/// Properties that every item has, independently of the Schema
+const ITEM_BASE_PROPERTIES: &[(&str, SchemaPropertyType)] = &[
+    ("id", SchemaPropertyType::Text),
+    ("dateCreated", SchemaPropertyType::DateTime),
+    ("dateModified", SchemaPropertyType::DateTime),
+    ("dateServerModified", SchemaPropertyType::DateTime),
+    ("deleted", SchemaPropertyType::Bool),
+];
+
+/// Render the Schema as a document with one definition per item type.
+///
+/// Item types are those that have properties declared in the Schema
+/// (`ItemPropertySchema` items with an `itemType`), and those that are used in edges.
+/// Edges are not properties of an item, they're described in an `x-edges` extension
+/// of each definition, mapping the edge name to the possible target item types.
+pub fn export_schema(schema: &Schema, edges: &[EdgeType], format: SchemaExportFormat) -> Value {
+    let ref_prefix = match format {
+        SchemaExportFormat::JsonSchema => "#/$defs/",
+        SchemaExportFormat::OpenApi => "#/components/schemas/",
+    };
+    let mut item_types: BTreeSet<&str> = schema.item_rules.keys().map(|t| t.as_str()).collect();
+    let mut item_edges: BTreeMap<&str, BTreeMap<&str, BTreeSet<&str>>> = BTreeMap::new();
+    for edge in edges {
+        item_types.insert(&edge.source_type);
+        item_types.insert(&edge.target_type);
+        item_edges
+            .entry(&edge.source_type)
+            .or_default()
+            .entry(&edge.name)
+            .or_default()
+            .insert(&edge.target_type);
+    }
+
+    let mut definitions = Map::new();
+    for item_type in item_types {
+        let mut definition = item_type_definition(schema, item_type);
+        if let Some(edges) = item_edges.get(item_type) {
+            let edges: Map<String, Value> = edges
+                .iter()
+                .map(|(name, targets)| {
+                    let targets: Vec<Value> = targets
+                        .iter()
+                        .map(|t| json!({ "$ref": format!("{}{}", ref_prefix, t) }))
+                        .collect();
+                    (name.to_string(), json!({ "anyOf": targets }))
+                })
+                .collect();
+            definition.insert("x-edges".to_string(), Value::Object(edges));
+        }
+        definitions.insert(item_type.to_string(), Value::Object(definition));
+    }
+
+    match format {
+        SchemaExportFormat::JsonSchema => json!({
+            "$schema": "https://json-schema.org/draft/2020-12/schema",
+            "$defs": definitions,
+        }),
+        SchemaExportFormat::OpenApi => json!({
+            "openapi": "3.0.3",
+            "info": {
+                "title": "Pod Schema",
+                "version": std::env!("CARGO_PKG_VERSION"),
+            },
+            "paths": {},
+            "components": { "schemas": definitions },
+        }),
+    }
+}
+
+fn item_type_definition(schema: &Schema, item_type: &str) -> Map<String, Value> {
+    let mut properties = Map::new();
+    let mut required = vec![json!("id"), json!("type")];
+    for (name, value_type) in ITEM_BASE_PROPERTIES {
+        properties.insert(name.to_string(), type_definition(value_type));
+    }
+    properties.insert(
+        "type".to_string(),
+        json!({ "type": "string", "enum": [item_type] }),
+    );
+    let rules: BTreeMap<&String, &PropertyRules> = schema
+        .item_rules
+        .get(item_type)
+        .map(|rules| rules.iter().collect())
+        .unwrap_or_default();
+    for (name, rules) in rules {
+        let value_type = if let Some(t) = schema.property_types.get(name) {
+            t
+        } else {
+            continue;
+        };
+        let mut definition = type_definition(value_type);
+        let object = definition
+            .as_object_mut()
+            .expect("type definition is an object");
+        if let Some(pattern) = &rules.pattern {
+            object.insert("pattern".to_string(), json!(pattern.as_str()));
+        }
+        if let Some(minimum) = rules.minimum {
+            object.insert("minimum".to_string(), json!(minimum));
+        }
+        if let Some(maximum) = rules.maximum {
+            object.insert("maximum".to_string(), json!(maximum));
+        }
+        if let Some(default) = &rules.default {
+            object.insert("default".to_string(), default.clone());
+        }
+        if rules.formula.is_some() {
+            object.insert("readOnly".to_string(), json!(true));
+        }
+        if rules.unique {
+            object.insert("x-unique".to_string(), json!(true));
+        }
+        if rules.required {
+            required.push(json!(name));
+        }
+        properties.insert(name.to_string(), definition);
+    }
+    let mut result = Map::new();
+    result.insert("type".to_string(), json!("object"));
+    result.insert("properties".to_string(), Value::Object(properties));
+    result.insert("required".to_string(), Value::Array(required));
+    result
+}
+
+fn type_definition(value_type: &SchemaPropertyType) -> Value {
+    match value_type {
+        SchemaPropertyType::Text => json!({ "type": "string" }),
+        SchemaPropertyType::Integer => json!({ "type": "integer", "format": "int64" }),
+        SchemaPropertyType::Real => json!({ "type": "number", "format": "double" }),
+        SchemaPropertyType::Bool => json!({ "type": "boolean" }),
+        SchemaPropertyType::DateTime => json!({
+            "type": "integer",
+            "format": "int64",
+            "description": "DateTime, in milliseconds since the Unix epoch",
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_export_schema() {
+        let mut schema = Schema::default();
+        schema.add_property_type("email".to_string(), SchemaPropertyType::Text);
+        let rules = PropertyRules {
+            required: true,
+            ..Default::default()
+        };
+        schema.add_property_rules("Person", "email", rules);
+        let edges = vec![EdgeType {
+            source_type: "Person".to_string(),
+            name: "friend".to_string(),
+            target_type: "Person".to_string(),
+        }];
+
+        let json_schema = export_schema(&schema, &edges, SchemaExportFormat::JsonSchema);
+        let person = &json_schema["$defs"]["Person"];
+        assert_eq!(person["properties"]["email"], json!({"type": "string"}));
+        assert_eq!(person["required"], json!(["id", "type", "email"]));
+        assert_eq!(
+            person["x-edges"]["friend"],
+            json!({"anyOf": [{"$ref": "#/$defs/Person"}]})
+        );
+
+        let open_api = export_schema(&schema, &edges, SchemaExportFormat::OpenApi);
+        let person = &open_api["components"]["schemas"]["Person"];
+        assert_eq!(
+            person["x-edges"]["friend"],
+            json!({"anyOf": [{"$ref": "#/components/schemas/Person"}]})
+        );
+    }
+}
@@ -0,0 +1,416 @@
+//
+// Sync scopes: the part of the database that a client syncs
+//
+
+use crate::api_model::ChangesSince;
+use crate::api_model::SetSyncScope;
+use crate::api_model::SyncScope;
+use crate::database_api;
+use crate::database_api::DbTime;
+use crate::database_api::ItemBase;
+use crate::database_api::Rowid;
+use crate::database_api::SyncScopeItem;
+use crate::database_utils::add_item_edge_properties;
+use crate::database_utils::item_base_to_json;
+use crate::error::Error;
+use crate::error::Result;
+use crate::schema::Schema;
+use chrono::Utc;
+use log::info;
+use rusqlite::Transaction as Tx;
+use serde_json::json;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use warp::http::status::StatusCode;
+
+const DAY_MILLIS: DbTime = 24 * 3600 * 1000;
+
+/// Set the sync scope of a client, or remove it to sync everything again
+pub fn set_sync_scope_tx(tx: &Tx, query: SetSyncScope) -> Result<()> {
+    let root = query.scope.as_ref().and_then(|scope| scope.root.as_ref());
+    if let Some(root) = root {
+        if database_api::get_item_rowid(tx, root)?.is_none() {
+            return Err(Error {
+                code: StatusCode::NOT_FOUND,
+                msg: format!("Root item {} of the sync scope not found", root),
+            });
+        }
+    }
+    let scope = match &query.scope {
+        Some(scope) => Some(serde_json::to_string(scope)?),
+        None => None,
+    };
+    let date = Utc::now().timestamp_millis();
+    database_api::set_sync_scope(tx, &query.client_id, scope.as_deref(), date)
+}
+
+pub fn get_sync_scope(tx: &Tx, client_id: &str) -> Result<Option<SyncScope>> {
+    match database_api::get_sync_scope(tx, client_id)? {
+        Some(scope) => Ok(Some(serde_json::from_str(&scope)?)),
+        None => Ok(None),
+    }
+}
+
+/// Items in the scope, by rowid.
+/// Besides the items matching the scope, this includes the edges between them,
+/// and the Schema items that clients always need.
+pub fn items_in_scope(tx: &Tx, scope: &SyncScope, now: DbTime) -> Result<HashMap<Rowid, String>> {
+    let within_days = scope
+        .modified_within_days
+        .map(|days| now - DbTime::from(days) * DAY_MILLIS);
+    let after = scope.date_modified_after.max(within_days);
+    let before = scope.date_modified_before;
+    let candidates = match &scope.root {
+        Some(root) => reachable_items(tx, root, scope.max_depth)?
+            .into_iter()
+            .filter(|item| {
+                let types = scope.types.as_ref();
+                types.map(|t| t.contains(&item._type)).unwrap_or(true)
+            })
+            .filter(|item| {
+                after
+                    .map(|after| item.date_modified >= after)
+                    .unwrap_or(true)
+            })
+            .filter(|item| {
+                before
+                    .map(|before| item.date_modified < before)
+                    .unwrap_or(true)
+            })
+            .collect(),
+        None => database_api::get_item_bases_of_types(tx, scope.types.as_deref(), after, before)?,
+    };
+    let mut result: HashMap<Rowid, String> = candidates
+        .into_iter()
+        .map(|item| (item.rowid, item.id))
+        .collect();
+    let schema_types = [
+        "ItemPropertySchema".to_string(),
+        "ItemTypeSchema".to_string(),
+    ];
+    for item in database_api::get_item_bases_of_types(tx, Some(&schema_types), None, None)? {
+        result.insert(item.rowid, item.id);
+    }
+    let items: Vec<Rowid> = result.keys().copied().collect();
+    result.extend(database_api::get_edges_between(tx, &items)?);
+    Ok(result)
+}
+
+/// Items reachable from `root` by following at most `max_depth` outgoing edges
+fn reachable_items(tx: &Tx, root: &str, max_depth: u32) -> Result<Vec<ItemBase>> {
+    let root = match database_api::get_item_rowid(tx, root)? {
+        Some(rowid) => rowid,
+        None => return Ok(Vec::new()),
+    };
+    let mut visited = HashSet::new();
+    visited.insert(root);
+    let mut frontier = vec![root];
+    for _ in 0..max_depth {
+        let mut next = Vec::new();
+        for rowid in frontier {
+            for edge in database_api::get_outgoing_edges(tx, rowid)? {
+                if visited.insert(edge.item) {
+                    next.push(edge.item);
+                }
+            }
+        }
+        frontier = next;
+    }
+    let mut result = Vec::with_capacity(visited.len());
+    for rowid in visited {
+        if let Some(item) = database_api::get_item_base(tx, rowid)? {
+            result.push(item);
+        }
+    }
+    Ok(result)
+}
+
+/// `changes_since` for a client with a sync scope.
+///
+/// Changed items outside the scope are left out. Once there are no more changes,
+/// the items that entered the scope without being changed
+/// (e.g. because the scope changed) are added, and the items that left the scope
+/// are returned as `{"id": ..., "leftScope": true}`, at most `limit` items in total:
+/// `hasMore` stays true as long as some of them are left for the next requests.
+/// What the client was told is remembered with a new change number, which becomes
+/// the returned `changeSeq`, so that retries with the previous `since` get it again.
+pub fn changes_in_scope(
+    tx: &Tx,
+    schema: &Schema,
+    client_id: &str,
+    scope: &SyncScope,
+    query: ChangesSince,
+) -> Result<Value> {
+    info!(
+        "Getting changes since {} in the sync scope of client {}",
+        query.since, client_id
+    );
+    let now = Utc::now().timestamp_millis();
+    let in_scope = items_in_scope(tx, scope, now)?;
+    let mut changes =
+        database_api::get_changes_since(tx, query.since, query.limit.saturating_add(1))?;
+    let mut has_more = changes.len() as u64 > query.limit;
+    changes.truncate(query.limit as usize);
+    let mut change_seq = match changes.last() {
+        Some((change_seq, _)) => *change_seq,
+        None => query.since.max(database_api::get_last_change_seq(tx)?),
+    };
+    let mut items = Vec::new();
+    let mut sent = HashSet::new();
+    for (_, base) in changes {
+        if in_scope.contains_key(&base.rowid) {
+            sent.insert(base.rowid);
+            items.push(item_to_json(tx, schema, base)?);
+        }
+    }
+    if !has_more {
+        let known: HashMap<String, SyncScopeItem> =
+            database_api::get_sync_scope_items(tx, client_id)?
+                .into_iter()
+                .map(|item| (item.id.to_string(), item))
+                .collect();
+        // Items the client was already told about after `since`, sent again on retries
+        let mut entered = Vec::new();
+        let mut left = Vec::new();
+        // Items whose membership the client has not been told about yet
+        let mut new_entered = Vec::new();
+        let mut new_left = Vec::new();
+        for (rowid, id) in &in_scope {
+            match known.get(id) {
+                Some(item) if item.in_scope => {
+                    if item.change_seq > query.since {
+                        entered.push(*rowid);
+                    }
+                }
+                _ => new_entered.push((*rowid, id.to_string())),
+            }
+        }
+        let ids_in_scope: HashSet<&String> = in_scope.values().collect();
+        for item in known.values() {
+            if ids_in_scope.contains(&item.id) {
+                continue;
+            }
+            if item.in_scope {
+                new_left.push(item.id.to_string());
+            } else if item.change_seq > query.since {
+                left.push(item.id.to_string());
+            }
+        }
+        // New members that were just sent as changed items only need to be remembered,
+        // the others are sent page by page within the same `limit` as changed items
+        let mut membership_changes = Vec::new();
+        new_entered.sort_unstable();
+        let (sent_entered, new_entered): (Vec<_>, Vec<_>) = new_entered
+            .into_iter()
+            .partition(|(rowid, _)| sent.contains(rowid));
+        membership_changes.extend(sent_entered.into_iter().map(|(_, id)| (id, true)));
+        new_left.sort();
+        let mut remaining =
+            (query.limit as usize).saturating_sub(items.len() + entered.len() + left.len());
+        for (rowid, id) in new_entered {
+            if remaining == 0 {
+                has_more = true;
+                break;
+            }
+            remaining -= 1;
+            membership_changes.push((id, true));
+            entered.push(rowid);
+        }
+        for id in new_left {
+            if remaining == 0 {
+                has_more = true;
+                break;
+            }
+            remaining -= 1;
+            membership_changes.push((id.to_string(), false));
+            left.push(id);
+        }
+        if !membership_changes.is_empty() {
+            change_seq = database_api::next_change_seq(tx)?;
+            for (id, in_scope) in membership_changes {
+                let item = SyncScopeItem {
+                    id,
+                    in_scope,
+                    change_seq,
+                };
+                database_api::set_sync_scope_item(tx, client_id, &item)?;
+            }
+        }
+        entered.sort_unstable();
+        for rowid in entered {
+            if sent.contains(&rowid) {
+                continue;
+            }
+            if let Some(base) = database_api::get_item_base(tx, rowid)? {
+                items.push(item_to_json(tx, schema, base)?);
+            }
+        }
+        left.sort();
+        for id in left {
+            items.push(json!({"id": id, "leftScope": true}));
+        }
+    }
+    Ok(json!({
+        "items": items,
+        "changeSeq": change_seq,
+        "hasMore": has_more,
+    }))
+}
+
+fn item_to_json(tx: &Tx, schema: &Schema, base: ItemBase) -> Result<Value> {
+    let rowid = base.rowid;
+    let mut object_map = item_base_to_json(tx, base, schema)?;
+    add_item_edge_properties(tx, &mut object_map, rowid)?;
+    Ok(Value::Object(object_map))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api_model::CreateEdge;
+    use crate::api_model::CreateItem;
+    use crate::command_line_interface;
+    use crate::database_api::tests::new_conn;
+    use crate::internal_api;
+    use crate::plugin_auth_crypto::DatabaseKey;
+
+    #[test]
+    fn test_changes_in_scope() {
+        let mut conn = new_conn();
+        let cli = command_line_interface::tests::test_cli();
+        let db_key = DatabaseKey::from("".to_string()).unwrap();
+        let tx = conn.transaction().unwrap();
+        let mut schema = database_api::get_schema(&tx).unwrap();
+        for id in ["person-root", "person-friend", "person-other"] {
+            let item: CreateItem =
+                serde_json::from_value(json!({"id": id, "type": "Person"})).unwrap();
+            internal_api::create_item_tx(&tx, &mut schema, item, "", &cli, &db_key).unwrap();
+        }
+        let edge = CreateEdge {
+            source: "person-root".to_string(),
+            target: "person-friend".to_string(),
+            name: "friend".to_string(),
+            self_id: None,
+        };
+        let edge_id = internal_api::create_edge(&tx, edge).unwrap();
+        let set_scope = |scope: Value| {
+            let query: SetSyncScope =
+                serde_json::from_value(json!({"clientId": "phone", "scope": scope})).unwrap();
+            set_sync_scope_tx(&tx, query).unwrap();
+        };
+        let changes_page = |since: i64, limit: u64| {
+            let query = ChangesSince {
+                since,
+                limit,
+                client_id: Some("phone".to_string()),
+            };
+            internal_api::changes_since(&tx, &schema, query).unwrap()
+        };
+        let changes = |since: i64| changes_page(since, 1000);
+        let ids = |changes: &Value| -> Vec<String> {
+            let items = changes["items"].as_array().unwrap();
+            let items = items
+                .iter()
+                .filter(|item| item["type"] != "ItemPropertySchema")
+                .filter(|item| item["type"] != "ItemTypeSchema");
+            items
+                .map(|item| item["id"].as_str().unwrap().to_string())
+                .collect()
+        };
+
+        set_scope(json!({"root": "person-root", "maxDepth": 1}));
+        let first = changes(0);
+        assert_eq!(ids(&first), vec!["person-friend", &edge_id, "person-root"]);
+        let since = first["changeSeq"].as_i64().unwrap();
+        assert_eq!(changes(since)["items"], json!([]));
+
+        set_scope(json!({"root": "person-root", "maxDepth": 0}));
+        let left = changes(since);
+        let mut expected = vec![
+            json!({"id": edge_id, "leftScope": true}),
+            json!({"id": "person-friend", "leftScope": true}),
+        ];
+        expected.sort_by_key(|item| item["id"].as_str().unwrap().to_string());
+        assert_eq!(left["items"], json!(expected));
+        // A retry gets the same answer, the next request nothing
+        assert_eq!(changes(since)["items"], left["items"]);
+        let since = left["changeSeq"].as_i64().unwrap();
+        assert_eq!(changes(since)["items"], json!([]));
+
+        set_scope(json!({"types": ["Person"]}));
+        let entered = changes(since);
+        // The edge is back as well, as both its ends are
+        let expected = vec!["person-friend", "person-other", &edge_id];
+        assert_eq!(ids(&entered), expected);
+
+        // Items that left the scope are paged like changed items
+        let since = entered["changeSeq"].as_i64().unwrap();
+        set_scope(json!({"root": "person-root", "maxDepth": 0}));
+        let first = changes_page(since, 2);
+        assert_eq!(first["items"].as_array().unwrap().len(), 2);
+        assert_eq!(first["hasMore"], json!(true));
+        assert_eq!(changes_page(since, 2)["items"], first["items"]);
+        let rest = changes_page(first["changeSeq"].as_i64().unwrap(), 2);
+        assert_eq!(rest["items"].as_array().unwrap().len(), 1);
+        assert_eq!(rest["hasMore"], json!(false));
+        let mut left = ids(&first);
+        left.extend(ids(&rest));
+        left.sort();
+        let mut expected = vec![
+            edge_id,
+            "person-friend".to_string(),
+            "person-other".to_string(),
+        ];
+        expected.sort();
+        assert_eq!(left, expected);
+    }
+
+    #[test]
+    fn test_items_in_scope_by_type_and_date() {
+        let mut conn = new_conn();
+        let cli = command_line_interface::tests::test_cli();
+        let db_key = DatabaseKey::from("".to_string()).unwrap();
+        let tx = conn.transaction().unwrap();
+        let mut schema = database_api::get_schema(&tx).unwrap();
+        for (id, _type, date) in [
+            ("person-old", "Person", 1000),
+            ("person-new", "Person", 3000),
+            ("person-future", "Person", 5000),
+            ("note-new", "Note", 3000),
+        ] {
+            let item = json!({"id": id, "type": _type, "dateModified": date});
+            let item: CreateItem = serde_json::from_value(item).unwrap();
+            internal_api::create_item_tx(&tx, &mut schema, item, "", &cli, &db_key).unwrap();
+        }
+        let edge = CreateEdge {
+            source: "person-new".to_string(),
+            target: "person-old".to_string(),
+            name: "friend".to_string(),
+            self_id: None,
+        };
+        let edge_id = internal_api::create_edge(&tx, edge).unwrap();
+        let item = json!({"id": "note-revisions", "type": "ItemTypeSchema", "itemType": "Note"});
+        let item: CreateItem = serde_json::from_value(item).unwrap();
+        internal_api::create_item_tx(&tx, &mut schema, item, "", &cli, &db_key).unwrap();
+
+        let scope = json!({"types": ["Person"], "dateModifiedAfter": 2000,
+            "dateModifiedBefore": 4000});
+        let scope: SyncScope = serde_json::from_value(scope).unwrap();
+        let in_scope = items_in_scope(&tx, &scope, 10_000).unwrap();
+        // The edge is out of scope with its target
+        for id in ["person-old", "person-future", "note-new", &edge_id] {
+            assert!(!in_scope.values().any(|v| v == id), "{} in scope", id);
+        }
+        // Besides the matching item, Schema items are always in scope
+        for id in ["person-new", "note-revisions"] {
+            assert!(in_scope.values().any(|v| v == id), "{} not in scope", id);
+        }
+
+        let scope: SyncScope = serde_json::from_value(json!({"modifiedWithinDays": 1})).unwrap();
+        let in_scope = items_in_scope(&tx, &scope, 1000 + DAY_MILLIS).unwrap();
+        for id in ["person-old", "person-new", "note-new", &edge_id] {
+            assert!(in_scope.values().any(|v| v == id), "{} not in scope", id);
+        }
+    }
+}
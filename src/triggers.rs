@@ -11,6 +11,9 @@ use crate::error::ErrorContext;
 use crate::error::Result;
 use crate::plugin_auth_crypto::DatabaseKey;
 use crate::plugin_run;
+use crate::schema;
+use crate::schema::Formula;
+use crate::schema::PropertyRules;
 use crate::schema::Schema;
 use crate::schema::SchemaPropertyType;
 use crate::triggers::SchemaAdditionChange::*;
@@ -25,6 +28,100 @@ pub struct SchemaItem {
     pub item_type: String,
     pub property_name: String,
     pub value_type: SchemaPropertyType,
+    #[serde(default)]
+    pub is_required: bool,
+    #[serde(default)]
+    pub is_unique: bool,
+    pub pattern: Option<String>,
+    pub minimum: Option<f64>,
+    pub maximum: Option<f64>,
+    pub default_value: Option<String>,
+    pub formula: Option<String>,
+}
+
+impl SchemaItem {
+    /// Validate the constraints declared by this Schema item against its `value_type`
+    pub fn property_rules(&self) -> Result<PropertyRules> {
+        let numeric = matches!(
+            self.value_type,
+            SchemaPropertyType::Integer | SchemaPropertyType::Real | SchemaPropertyType::DateTime
+        );
+        if (self.minimum.is_some() || self.maximum.is_some()) && !numeric {
+            return Err(Error {
+                code: StatusCode::BAD_REQUEST,
+                msg: format!(
+                    "Schema for property {} declares a range, which is not supported for type {}",
+                    self.property_name, self.value_type
+                ),
+            });
+        }
+        if self.pattern.is_some() && self.value_type != SchemaPropertyType::Text {
+            return Err(Error {
+                code: StatusCode::BAD_REQUEST,
+                msg: format!(
+                    "Schema for property {} declares a pattern, which is only supported for type Text",
+                    self.property_name
+                ),
+            });
+        }
+        if self.formula.is_some() && self.value_type != SchemaPropertyType::Text {
+            return Err(Error {
+                code: StatusCode::BAD_REQUEST,
+                msg: format!(
+                    "Schema for property {} declares a formula, which is only supported for type Text",
+                    self.property_name
+                ),
+            });
+        }
+        if self.formula.is_some() && self.default_value.is_some() {
+            return Err(Error {
+                code: StatusCode::BAD_REQUEST,
+                msg: format!(
+                    "Schema for property {} declares both a formula and a default value",
+                    self.property_name
+                ),
+            });
+        }
+        let pattern = self
+            .pattern
+            .as_deref()
+            .map(schema::parse_property_pattern)
+            .transpose()?;
+        let default = self
+            .default_value
+            .as_deref()
+            .map(|d| schema::parse_default_value(&self.value_type, d))
+            .transpose()?;
+        let formula = self.formula.as_deref().map(Formula::parse).transpose()?;
+        if let Some(formula) = &formula {
+            if formula.properties().any(|p| p == self.property_name) {
+                return Err(Error {
+                    code: StatusCode::BAD_REQUEST,
+                    msg: format!(
+                        "Formula of property {} cannot refer to the property itself",
+                        self.property_name
+                    ),
+                });
+            }
+        }
+        Ok(PropertyRules {
+            required: self.is_required,
+            unique: self.is_unique,
+            pattern,
+            minimum: self.minimum,
+            maximum: self.maximum,
+            default,
+            formula,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ItemTypeSchemaItem {
+    pub item_type: String,
+    #[serde(default)]
+    pub keep_revisions: bool,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -55,8 +152,10 @@ pub fn add_item_as_schema_opt(
         let json = serde_json::to_value(item)?;
         let parsed: SchemaItem = serde_json::from_value(json)
             .context(|| format!("Parsing of Schema item {:?}, {}:{}", item, file!(), line!()))?;
+        let rules = parsed.property_rules()?;
         if let Some(old) = schema.property_types.get(&parsed.property_name) {
             if old == &parsed.value_type {
+                schema.add_property_rules(&parsed.item_type, &parsed.property_name, rules);
                 Ok(OldSchemaIgnored)
             } else {
                 Err(Error {
@@ -65,11 +164,18 @@ pub fn add_item_as_schema_opt(
                 })
             }
         } else {
-            schema
-                .property_types
-                .insert(parsed.property_name, parsed.value_type);
+            schema.add_property_rules(&parsed.item_type, &parsed.property_name, rules);
+            schema.add_property_type(parsed.property_name, parsed.value_type);
             Ok(NewSchemaAdded)
         }
+    } else if item._type == "ItemTypeSchema" {
+        let json = serde_json::to_value(item)?;
+        let parsed: ItemTypeSchemaItem = serde_json::from_value(json)
+            .context(|| format!("Parsing of Schema item {:?}, {}:{}", item, file!(), line!()))?;
+        if parsed.keep_revisions && !item.deleted {
+            schema.add_revision_item_type(parsed.item_type);
+        }
+        Ok(NewSchemaAdded)
     } else {
         Ok(NotASchema)
     }
@@ -113,17 +219,12 @@ mod tests {
     use crate::schema::SchemaPropertyType;
     use crate::triggers::SchemaAdditionChange;
     use serde_json::json;
-    use std::collections::HashMap;
 
     #[test]
     fn my_test() -> Result<()> {
         // let mut minimal_schema = database_api::get_schema(&tx).unwrap();
-        let mut schema = Schema {
-            property_types: HashMap::new(),
-        };
-        schema
-            .property_types
-            .insert("age".to_string(), SchemaPropertyType::Integer);
+        let mut schema = Schema::default();
+        schema.add_property_type("age".to_string(), SchemaPropertyType::Integer);
 
         let json = json!({
             "type": "Something"
@@ -170,4 +271,29 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_schema_rules_validation() {
+        let mut schema = Schema::default();
+        let invalid = vec![
+            json!({"type": "ItemPropertySchema", "itemType": "Person",
+                "propertyName": "age", "valueType": "Integer", "pattern": "[0-9]+"}),
+            json!({"type": "ItemPropertySchema", "itemType": "Person",
+                "propertyName": "name", "valueType": "Text", "minimum": 1}),
+            json!({"type": "ItemPropertySchema", "itemType": "Person",
+                "propertyName": "name", "valueType": "Text", "pattern": "(unclosed"}),
+        ];
+        for json in invalid {
+            let create_item: CreateItem = serde_json::from_value(json).unwrap();
+            let result = add_item_as_schema_opt(&mut schema, &create_item);
+            assert!(result.is_err(), "result should be an error {:?}", result);
+        }
+        assert!(schema.property_types.is_empty());
+
+        let json = json!({"type": "ItemPropertySchema", "itemType": "Person",
+            "propertyName": "name", "valueType": "Text", "isRequired": true});
+        let create_item: CreateItem = serde_json::from_value(json).unwrap();
+        add_item_as_schema_opt(&mut schema, &create_item).unwrap();
+        assert!(schema.item_rules["Person"]["name"].required);
+    }
 }
@@ -1,8 +1,13 @@
+use crate::api_model::Subscribe;
+use crate::change_events;
+use crate::change_events::ChangeEvent;
 use crate::command_line_interface;
 use crate::command_line_interface::CliOptions;
 use crate::error::Result;
 use crate::internal_api;
+use crate::schema_cache::SchemaCache;
 use crate::warp_endpoints;
+use futures::Stream;
 use log::error;
 use log::info;
 use log::warn;
@@ -14,15 +19,25 @@ use std::path::PathBuf;
 use std::str::FromStr;
 use std::sync::Arc;
 use std::sync::RwLock;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
 use warp::http;
 use warp::http::header::HeaderMap;
 use warp::http::header::HeaderValue;
+use warp::http::header::CONTENT_TYPE;
 use warp::http::status::StatusCode;
 use warp::hyper::body::Bytes;
 use warp::reply::Response;
+use warp::sse::Event;
 use warp::Filter;
 use warp::Reply;
 
+/// Content type of newline-delimited JSON responses, requested with the `Accept` header
+const NDJSON_CONTENT_TYPE: &str = "application/x-ndjson";
+
+/// Content type of exported Pod archives
+const TAR_CONTENT_TYPE: &str = "application/x-tar";
+
 /// Start web framework with specified APIs.
 pub async fn run_server(cli_options: CliOptions) {
     let package_name = env!("CARGO_PKG_NAME").to_uppercase();
@@ -40,6 +55,7 @@ pub async fn run_server(cli_options: CliOptions) {
         .and(warp::post());
 
     let initialized_databases_arc = Arc::new(RwLock::new(HashSet::<String>::new()));
+    let schema_cache_arc = Arc::new(SchemaCache::default());
     let cli_options_arc = Arc::new(cli_options.clone());
 
     let version = warp::path("version")
@@ -48,6 +64,7 @@ pub async fn run_server(cli_options: CliOptions) {
         .map(internal_api::get_project_version);
 
     let init_db = initialized_databases_arc.clone();
+    let schemas = schema_cache_arc.clone();
     let cli_options_arc_clone = cli_options_arc.clone();
     let create_item = items_api
         .and(warp::path!(String / "create_item"))
@@ -55,40 +72,56 @@ pub async fn run_server(cli_options: CliOptions) {
         .and(warp::body::bytes())
         .map(move |owner: String, body: Bytes| {
             let cli = cli_options_arc_clone.deref();
-            let result = warp_endpoints::create_item(owner, init_db.deref(), body, cli);
+            let result =
+                warp_endpoints::create_item(owner, init_db.deref(), schemas.deref(), body, cli);
             let result = result.map(|result| warp::reply::json(&result));
             respond_with_result(result)
         });
 
     let init_db = initialized_databases_arc.clone();
+    let schemas = schema_cache_arc.clone();
     let get_item = items_api
         .and(warp::path!(String / "get_item"))
         .and(warp::path::end())
         .and(warp::body::bytes())
         .map(move |owner: String, body: Bytes| {
-            let result = warp_endpoints::get_item(owner, init_db.deref(), body);
+            let result = warp_endpoints::get_item(owner, init_db.deref(), schemas.deref(), body);
             let result = result.map(|result| warp::reply::json(&result));
             respond_with_result(result)
         });
 
     let init_db = initialized_databases_arc.clone();
+    let schemas = schema_cache_arc.clone();
+    let get_items = items_api
+        .and(warp::path!(String / "get_items"))
+        .and(warp::path::end())
+        .and(warp::body::bytes())
+        .map(move |owner: String, body: Bytes| {
+            let result = warp_endpoints::get_items(owner, init_db.deref(), schemas.deref(), body);
+            let result = result.map(|result| warp::reply::json(&result));
+            respond_with_result(result)
+        });
+
+    let init_db = initialized_databases_arc.clone();
+    let schemas = schema_cache_arc.clone();
     let update_item = items_api
         .and(warp::path!(String / "update_item"))
         .and(warp::path::end())
         .and(warp::body::bytes())
         .map(move |owner: String, body: Bytes| {
-            let result = warp_endpoints::update_item(owner, init_db.deref(), body);
-            let result = result.map(|()| warp::reply::json(&serde_json::json!({})));
+            let result = warp_endpoints::update_item(owner, init_db.deref(), schemas.deref(), body);
+            let result = result.map(|result| warp::reply::json(&result));
             respond_with_result(result)
         });
 
     let init_db = initialized_databases_arc.clone();
+    let schemas = schema_cache_arc.clone();
     let get_edges = items_api
         .and(warp::path!(String / "get_edges"))
         .and(warp::path::end())
         .and(warp::body::bytes())
         .map(move |owner: String, body: Bytes| {
-            let result = warp_endpoints::get_edges(owner, init_db.deref(), body);
+            let result = warp_endpoints::get_edges(owner, init_db.deref(), schemas.deref(), body);
             let result = result.map(|result| warp::reply::json(&result));
             respond_with_result(result)
         });
@@ -105,6 +138,7 @@ pub async fn run_server(cli_options: CliOptions) {
         });
 
     let init_db = initialized_databases_arc.clone();
+    let schemas = schema_cache_arc.clone();
     let cli_options_arc_clone = cli_options_arc.clone();
     let bulk_action = items_api
         .and(warp::path!(String / "bulk"))
@@ -112,29 +146,218 @@ pub async fn run_server(cli_options: CliOptions) {
         .and(warp::body::bytes())
         .map(move |owner: String, body: Bytes| {
             let cli = cli_options_arc_clone.deref();
-            let result = warp_endpoints::bulk(owner, init_db.deref(), body, cli);
+            let result = warp_endpoints::bulk(owner, init_db.deref(), schemas.deref(), body, cli);
+            let result = result.map(|value| warp::reply::json(&value));
+            respond_with_result(result)
+        });
+
+    let init_db = initialized_databases_arc.clone();
+    let schemas = schema_cache_arc.clone();
+    let cli_options_arc_clone = cli_options_arc.clone();
+    let apply_operations = items_api
+        .and(warp::path!(String / "apply_operations"))
+        .and(warp::path::end())
+        .and(warp::body::bytes())
+        .map(move |owner: String, body: Bytes| {
+            let cli = cli_options_arc_clone.deref();
+            let result = warp_endpoints::apply_operations(
+                owner,
+                init_db.deref(),
+                schemas.deref(),
+                body,
+                cli,
+            );
             let result = result.map(|value| warp::reply::json(&value));
             respond_with_result(result)
         });
 
     let init_db = initialized_databases_arc.clone();
+    let schemas = schema_cache_arc.clone();
     let delete_item = items_api
         .and(warp::path!(String / "delete_item"))
         .and(warp::path::end())
         .and(warp::body::bytes())
         .map(move |owner: String, body: Bytes| {
-            let result = warp_endpoints::delete_item(owner, init_db.deref(), body);
+            let result = warp_endpoints::delete_item(owner, init_db.deref(), schemas.deref(), body);
+            let result = result.map(|()| warp::reply::json(&serde_json::json!({})));
+            respond_with_result(result)
+        });
+
+    let init_db = initialized_databases_arc.clone();
+    let schemas = schema_cache_arc.clone();
+    let restore_item = items_api
+        .and(warp::path!(String / "restore_item"))
+        .and(warp::path::end())
+        .and(warp::body::bytes())
+        .map(move |owner: String, body: Bytes| {
+            let result =
+                warp_endpoints::restore_item(owner, init_db.deref(), schemas.deref(), body);
+            let result = result.map(|()| warp::reply::json(&serde_json::json!({})));
+            respond_with_result(result)
+        });
+
+    let init_db = initialized_databases_arc.clone();
+    let schemas = schema_cache_arc.clone();
+    let purge_item = items_api
+        .and(warp::path!(String / "purge_item"))
+        .and(warp::path::end())
+        .and(warp::body::bytes())
+        .map(move |owner: String, body: Bytes| {
+            let result = warp_endpoints::purge_item(owner, init_db.deref(), schemas.deref(), body);
+            let result = result.map(|()| warp::reply::json(&serde_json::json!({})));
+            respond_with_result(result)
+        });
+
+    let init_db = initialized_databases_arc.clone();
+    let get_revisions = items_api
+        .and(warp::path!(String / "get_revisions"))
+        .and(warp::path::end())
+        .and(warp::body::bytes())
+        .map(move |owner: String, body: Bytes| {
+            let result = warp_endpoints::get_revisions(owner, init_db.deref(), body);
+            let result = result.map(|result| warp::reply::json(&result));
+            respond_with_result(result)
+        });
+
+    let init_db = initialized_databases_arc.clone();
+    let schemas = schema_cache_arc.clone();
+    let restore_revision = items_api
+        .and(warp::path!(String / "restore_revision"))
+        .and(warp::path::end())
+        .and(warp::body::bytes())
+        .map(move |owner: String, body: Bytes| {
+            let result =
+                warp_endpoints::restore_revision(owner, init_db.deref(), schemas.deref(), body);
             let result = result.map(|()| warp::reply::json(&serde_json::json!({})));
             respond_with_result(result)
         });
 
     let init_db = initialized_databases_arc.clone();
+    let schemas = schema_cache_arc.clone();
     let search = items_api
         .and(warp::path!(String / "search"))
         .and(warp::path::end())
+        .and(warp::header::optional::<String>("accept"))
+        .and(warp::body::bytes())
+        .map(move |owner: String, accept: Option<String>, body: Bytes| {
+            if accept
+                .map(|a| a.contains(NDJSON_CONTENT_TYPE))
+                .unwrap_or(false)
+            {
+                let result =
+                    warp_endpoints::search_ndjson(owner, init_db.deref(), schemas.deref(), body);
+                let result = result.map(|body| {
+                    let content_type = HeaderValue::from_static(NDJSON_CONTENT_TYPE);
+                    let mut response = Response::new(body);
+                    response.headers_mut().insert(CONTENT_TYPE, content_type);
+                    response
+                });
+                return respond_with_result(result);
+            }
+            let result = warp_endpoints::search(owner, init_db.deref(), schemas.deref(), body);
+            let result = result.map(|result| warp::reply::json(&result));
+            respond_with_result(result)
+        });
+
+    let init_db = initialized_databases_arc.clone();
+    let schemas = schema_cache_arc.clone();
+    let changes_since = items_api
+        .and(warp::path!(String / "changes_since"))
+        .and(warp::path::end())
         .and(warp::body::bytes())
         .map(move |owner: String, body: Bytes| {
-            let result = warp_endpoints::search(owner, init_db.deref(), body);
+            let result =
+                warp_endpoints::changes_since(owner, init_db.deref(), schemas.deref(), body);
+            let result = result.map(|result| warp::reply::json(&result));
+            respond_with_result(result)
+        });
+
+    let init_db = initialized_databases_arc.clone();
+    let set_sync_scope = items_api
+        .and(warp::path!(String / "set_sync_scope"))
+        .and(warp::path::end())
+        .and(warp::body::bytes())
+        .map(move |owner: String, body: Bytes| {
+            let result = warp_endpoints::set_sync_scope(owner, init_db.deref(), body);
+            let result = result.map(|()| warp::reply::json(&serde_json::json!({})));
+            respond_with_result(result)
+        });
+
+    let init_db = initialized_databases_arc.clone();
+    let schemas = schema_cache_arc.clone();
+    let cli_options_arc_clone = cli_options_arc.clone();
+    let acknowledge_changes = items_api
+        .and(warp::path!(String / "acknowledge_changes"))
+        .and(warp::path::end())
+        .and(warp::body::bytes())
+        .map(move |owner: String, body: Bytes| {
+            let cli = cli_options_arc_clone.deref();
+            let result = warp_endpoints::acknowledge_changes(
+                owner,
+                init_db.deref(),
+                schemas.deref(),
+                body,
+                cli,
+            );
+            let result = result.map(|result| warp::reply::json(&result));
+            respond_with_result(result)
+        });
+
+    let init_db = initialized_databases_arc.clone();
+    let schemas = schema_cache_arc.clone();
+    let cli_options_arc_clone = cli_options_arc.clone();
+    let replicate = items_api
+        .and(warp::path!(String / "replicate"))
+        .and(warp::path::end())
+        .and(warp::body::bytes())
+        .map(move |owner: String, body: Bytes| {
+            let cli = cli_options_arc_clone.deref();
+            let result =
+                warp_endpoints::replicate(owner, init_db.deref(), schemas.deref(), body, cli);
+            let result = result.map(|result| warp::reply::json(&result));
+            respond_with_result(result)
+        });
+
+    let init_db = initialized_databases_arc.clone();
+    let schemas = schema_cache_arc.clone();
+    let export = items_api
+        .and(warp::path!(String / "export"))
+        .and(warp::path::end())
+        .and(warp::body::bytes())
+        .map(move |owner: String, body: Bytes| {
+            let result = warp_endpoints::export(owner, init_db.deref(), schemas.deref(), body);
+            let result = result.map(|body| {
+                let content_type = HeaderValue::from_static(TAR_CONTENT_TYPE);
+                let mut response = Response::new(body);
+                response.headers_mut().insert(CONTENT_TYPE, content_type);
+                response
+            });
+            respond_with_result(result)
+        });
+
+    let init_db = initialized_databases_arc.clone();
+    let subscribe = items_api
+        .and(warp::path!(String / "subscribe"))
+        .and(warp::path::end())
+        .and(warp::body::bytes())
+        .map(move |owner: String, body: Bytes| {
+            let result = warp_endpoints::subscribe(owner, init_db.deref(), body);
+            let result = result.map(|(subscription, receiver)| {
+                let events = change_event_stream(subscription, receiver);
+                warp::sse::reply(warp::sse::keep_alive().stream(events))
+            });
+            respond_with_result(result)
+        });
+
+    let init_db = initialized_databases_arc.clone();
+    let schemas = schema_cache_arc.clone();
+    let export_schema = items_api
+        .and(warp::path!(String / "export_schema"))
+        .and(warp::path::end())
+        .and(warp::body::bytes())
+        .map(move |owner: String, body: Bytes| {
+            let result =
+                warp_endpoints::export_schema(owner, init_db.deref(), schemas.deref(), body);
             let result = result.map(|result| warp::reply::json(&result));
             respond_with_result(result)
         });
@@ -185,6 +408,28 @@ pub async fn run_server(cli_options: CliOptions) {
             respond_with_result(result)
         });
 
+    let init_db = initialized_databases_arc.clone();
+    let files_manifest = items_api
+        .and(warp::path!(String / "files_manifest"))
+        .and(warp::path::end())
+        .and(warp::body::bytes())
+        .map(move |owner: String, body: Bytes| {
+            let result = warp_endpoints::files_manifest(owner, init_db.deref(), body);
+            let result = result.map(|result| warp::reply::json(&result));
+            respond_with_result(result)
+        });
+
+    let init_db = initialized_databases_arc.clone();
+    let files_exist = items_api
+        .and(warp::path!(String / "files_exist"))
+        .and(warp::path::end())
+        .and(warp::body::bytes())
+        .map(move |owner: String, body: Bytes| {
+            let result = warp_endpoints::files_exist(owner, init_db.deref(), body);
+            let result = result.map(|result| warp::reply::json(&result));
+            respond_with_result(result)
+        });
+
     let init_db = initialized_databases_arc.clone();
     let cli_options_arc_clone = cli_options_arc.clone();
     let send_email = items_api
@@ -227,15 +472,30 @@ pub async fn run_server(cli_options: CliOptions) {
     });
     let sensitive_filters = sensitive_filters
         .or(get_item.with(&headers))
+        .or(get_items.with(&headers))
         .or(bulk_action.with(&headers))
+        .or(apply_operations.with(&headers))
         .or(update_item.with(&headers))
         .or(delete_item.with(&headers))
+        .or(restore_item.with(&headers))
+        .or(purge_item.with(&headers))
+        .or(get_revisions.with(&headers))
+        .or(restore_revision.with(&headers))
         .or(search.with(&headers))
+        .or(changes_since.with(&headers))
+        .or(acknowledge_changes.with(&headers))
+        .or(set_sync_scope.with(&headers))
+        .or(subscribe.with(&headers))
+        .or(replicate.with(&headers))
+        .or(export.with(&headers))
         .or(get_edges.with(&headers))
+        .or(export_schema.with(&headers))
         .or(create_edge.with(&headers))
         .or(upload_file.with(&headers))
         .or(upload_file_b.with(&headers))
         .or(get_file.with(&headers))
+        .or(files_manifest.with(&headers))
+        .or(files_exist.with(&headers))
         .or(send_email.with(&headers))
         .or(origin_request.with(&headers));
 
@@ -296,6 +556,30 @@ pub async fn run_server(cli_options: CliOptions) {
     }
 }
 
+/// Server-sent events for the changes that match the subscription.
+/// A subscriber that falls behind gets a "lagged" event, and should re-sync with `changes_since`.
+fn change_event_stream(
+    subscription: Subscribe,
+    receiver: broadcast::Receiver<ChangeEvent>,
+) -> impl Stream<Item = serde_json::Result<Event>> {
+    let state = (subscription, receiver);
+    futures::stream::unfold(state, |(subscription, mut receiver)| async move {
+        loop {
+            let event = match receiver.recv().await {
+                Ok(event) if change_events::matches(&subscription, &event) => {
+                    Event::default().event("change").json_data(event)
+                }
+                Ok(_) => continue,
+                Err(RecvError::Lagged(skipped)) => {
+                    Ok(Event::default().event("lagged").data(skipped.to_string()))
+                }
+                Err(RecvError::Closed) => return None,
+            };
+            return Some((event, (subscription, receiver)));
+        }
+    })
+}
+
 fn respond_with_result<T: Reply>(result: Result<T>) -> Response {
     match result {
         Err(err) => {
@@ -1,15 +1,32 @@
+use crate::api_model::AcknowledgeChanges;
+use crate::api_model::ApplyOperations;
 use crate::api_model::AuthKey;
-use crate::api_model::Bulk;
+use crate::api_model::BulkEntries;
+use crate::api_model::ChangesSince;
 use crate::api_model::CreateEdge;
 use crate::api_model::CreateItem;
+use crate::api_model::ExportArchive;
+use crate::api_model::ExportSchema;
+use crate::api_model::FilesExist;
+use crate::api_model::FilesManifest;
 use crate::api_model::GetEdges;
 use crate::api_model::GetFile;
+use crate::api_model::GetItems;
+use crate::api_model::GetRevisions;
 use crate::api_model::PayloadWrapper;
+use crate::api_model::Replicate;
+use crate::api_model::RestoreRevision;
 use crate::api_model::Search;
 use crate::api_model::SendEmail;
+use crate::api_model::SetSyncScope;
+use crate::api_model::Subscribe;
 use crate::api_model::UpdateItem;
+use crate::archive;
+use crate::change_events;
+use crate::change_events::ChangeEvent;
 use crate::command_line_interface;
 use crate::command_line_interface::CliOptions;
+use crate::command_line_interface::Command;
 use crate::constants;
 use crate::database_api;
 use crate::database_migrate_refinery;
@@ -18,12 +35,21 @@ use crate::error::Error;
 use crate::error::ErrorContext;
 use crate::error::Result;
 use crate::file_api;
+use crate::garbage_collection;
+use crate::global_static;
 use crate::internal_api;
 use crate::plugin_auth_crypto;
 use crate::plugin_auth_crypto::DatabaseKey;
+use crate::replication;
+use crate::schema::Schema;
+use crate::schema_bundle;
+use crate::schema_cache::SchemaCache;
+use crate::sync_scope;
+use chrono::Utc;
 use lazy_static::lazy_static;
 use log::error;
 use log::info;
+use log::warn;
 use rusqlite::Connection;
 use rusqlite::Transaction;
 use serde_json::Value;
@@ -31,11 +57,19 @@ use sha2::digest::generic_array::typenum::U32;
 use sha2::digest::generic_array::GenericArray;
 use sha2::Digest;
 use std::collections::HashSet;
+use std::fs::File;
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::io::Write;
 use std::ops::Deref;
 use std::path::PathBuf;
 use std::sync::RwLock;
+use std::time::Duration;
+use std::time::Instant;
+use tokio::sync::broadcast;
 use warp::http::status::StatusCode;
 use warp::hyper::body::Bytes;
+use warp::hyper::Body;
 
 //
 // Items API:
@@ -44,6 +78,7 @@ use warp::hyper::body::Bytes;
 pub fn get_item(
     owner: String,
     init_db: &RwLock<HashSet<String>>,
+    schemas: &SchemaCache,
     body: Bytes,
 ) -> Result<Vec<Value>> {
     let body = &mut serde_json::Deserializer::from_slice(body.deref());
@@ -52,15 +87,32 @@ pub fn get_item(
     let payload = body.payload;
     let database_key = auth_to_database_key(auth)?;
     let mut conn: Connection = check_owner_and_initialize_db(&owner, init_db, &database_key)?;
-    in_transaction(&mut conn, |tx| {
-        let schema = database_api::get_schema(tx)?;
-        internal_api::get_item_tx(tx, &schema, &payload)
+    in_schema_transaction(&mut conn, &owner, schemas, |tx, schema| {
+        internal_api::get_item_tx(tx, schema, &payload)
+    })
+}
+
+pub fn get_items(
+    owner: String,
+    init_db: &RwLock<HashSet<String>>,
+    schemas: &SchemaCache,
+    body: Bytes,
+) -> Result<Value> {
+    let body = &mut serde_json::Deserializer::from_slice(body.deref());
+    let body: PayloadWrapper<GetItems> = serde_path_to_error::deserialize(body)?;
+    let auth = body.auth;
+    let payload = body.payload;
+    let database_key = auth_to_database_key(auth)?;
+    let mut conn: Connection = check_owner_and_initialize_db(&owner, init_db, &database_key)?;
+    in_schema_transaction(&mut conn, &owner, schemas, |tx, schema| {
+        internal_api::get_items_tx(tx, schema, payload)
     })
 }
 
 pub fn create_item(
     owner: String,
     init_db: &RwLock<HashSet<String>>,
+    schemas: &SchemaCache,
     body: Bytes,
     cli: &CliOptions,
 ) -> Result<String> {
@@ -70,53 +122,166 @@ pub fn create_item(
     let payload = body.payload;
     let database_key = auth_to_database_key(auth)?;
     let mut conn: Connection = check_owner_and_initialize_db(&owner, init_db, &database_key)?;
-    in_transaction(&mut conn, |tx| {
-        let mut schema = database_api::get_schema(tx)?;
-        internal_api::create_item_tx(tx, &mut schema, payload, &owner, cli, &database_key)
+    in_schema_transaction(&mut conn, &owner, schemas, |tx, schema| {
+        internal_api::create_item_tx(tx, schema, payload, &owner, cli, &database_key)
     })
 }
 
-pub fn update_item(owner: String, init_db: &RwLock<HashSet<String>>, body: Bytes) -> Result<()> {
+pub fn update_item(
+    owner: String,
+    init_db: &RwLock<HashSet<String>>,
+    schemas: &SchemaCache,
+    body: Bytes,
+) -> Result<Value> {
     let body = &mut serde_json::Deserializer::from_slice(body.deref());
     let body: PayloadWrapper<UpdateItem> = serde_path_to_error::deserialize(body)?;
     let auth = body.auth;
     let payload = body.payload;
+    let author = auth_to_author(&auth)?;
     let database_key = auth_to_database_key(auth)?;
     let mut conn: Connection = check_owner_and_initialize_db(&owner, init_db, &database_key)?;
-    in_transaction(&mut conn, |tx| {
-        let schema = database_api::get_schema(tx)?;
-        internal_api::update_item_tx(tx, &schema, &payload.id, payload.fields)
+    in_schema_transaction(&mut conn, &owner, schemas, |tx, schema| {
+        internal_api::update_item_tx(tx, schema, payload, &author)
     })
 }
 
 pub fn bulk(
     owner: String,
     init_db: &RwLock<HashSet<String>>,
+    schemas: &SchemaCache,
     body: Bytes,
     cli: &CliOptions,
 ) -> Result<Value> {
     let body = &mut serde_json::Deserializer::from_slice(body.deref());
-    let body: PayloadWrapper<Bulk> = serde_path_to_error::deserialize(body)?;
+    let body: PayloadWrapper<BulkEntries> = serde_path_to_error::deserialize(body)?;
+    let auth = body.auth;
+    let payload = body.payload;
+    let author = auth_to_author(&auth)?;
+    let database_key = auth_to_database_key(auth)?;
+    let mut conn: Connection = check_owner_and_initialize_db(&owner, init_db, &database_key)?;
+    in_schema_transaction(&mut conn, &owner, schemas, |tx, schema| {
+        internal_api::bulk_entries_tx(tx, schema, payload, &owner, cli, &database_key, &author)
+    })
+}
+
+pub fn apply_operations(
+    owner: String,
+    init_db: &RwLock<HashSet<String>>,
+    schemas: &SchemaCache,
+    body: Bytes,
+    cli: &CliOptions,
+) -> Result<Value> {
+    let body = &mut serde_json::Deserializer::from_slice(body.deref());
+    let body: PayloadWrapper<ApplyOperations> = serde_path_to_error::deserialize(body)?;
+    let auth = body.auth;
+    let payload = body.payload;
+    let author = auth_to_author(&auth)?;
+    let database_key = auth_to_database_key(auth)?;
+    let mut conn: Connection = check_owner_and_initialize_db(&owner, init_db, &database_key)?;
+    in_schema_transaction(&mut conn, &owner, schemas, |tx, schema| {
+        internal_api::apply_operations_tx(tx, schema, payload, &owner, cli, &database_key, &author)
+    })
+}
+
+pub fn delete_item(
+    owner: String,
+    init_db: &RwLock<HashSet<String>>,
+    schemas: &SchemaCache,
+    body: Bytes,
+) -> Result<()> {
+    let body = &mut serde_json::Deserializer::from_slice(body.deref());
+    let body: PayloadWrapper<String> = serde_path_to_error::deserialize(body)?;
     let auth = body.auth;
     let payload = body.payload;
+    let author = auth_to_author(&auth)?;
     let database_key = auth_to_database_key(auth)?;
     let mut conn: Connection = check_owner_and_initialize_db(&owner, init_db, &database_key)?;
-    in_transaction(&mut conn, |tx| {
-        let mut schema = database_api::get_schema(tx)?;
-        internal_api::bulk_tx(tx, &mut schema, payload, &owner, cli, &database_key)
+    in_schema_transaction(&mut conn, &owner, schemas, |tx, schema| {
+        internal_api::delete_item_tx(tx, schema, &payload, &author)
     })
 }
 
-pub fn delete_item(owner: String, init_db: &RwLock<HashSet<String>>, body: Bytes) -> Result<()> {
+pub fn restore_item(
+    owner: String,
+    init_db: &RwLock<HashSet<String>>,
+    schemas: &SchemaCache,
+    body: Bytes,
+) -> Result<()> {
     let body = &mut serde_json::Deserializer::from_slice(body.deref());
     let body: PayloadWrapper<String> = serde_path_to_error::deserialize(body)?;
     let auth = body.auth;
     let payload = body.payload;
+    let author = auth_to_author(&auth)?;
     let database_key = auth_to_database_key(auth)?;
     let mut conn: Connection = check_owner_and_initialize_db(&owner, init_db, &database_key)?;
-    in_transaction(&mut conn, |tx| {
-        let schema = database_api::get_schema(tx)?;
-        internal_api::delete_item_tx(tx, &schema, &payload)
+    in_schema_transaction(&mut conn, &owner, schemas, |tx, schema| {
+        internal_api::restore_item_tx(tx, schema, &payload, &author)
+    })
+}
+
+pub fn purge_item(
+    owner: String,
+    init_db: &RwLock<HashSet<String>>,
+    schemas: &SchemaCache,
+    body: Bytes,
+) -> Result<()> {
+    let body = &mut serde_json::Deserializer::from_slice(body.deref());
+    let body: PayloadWrapper<String> = serde_path_to_error::deserialize(body)?;
+    let auth = body.auth;
+    let payload = body.payload;
+    let database_key = auth_to_database_key(auth)?;
+    let mut conn: Connection = check_owner_and_initialize_db(&owner, init_db, &database_key)?;
+    let orphaned_files = in_schema_transaction(&mut conn, &owner, schemas, |tx, schema| {
+        internal_api::purge_item_tx(tx, schema, &payload)
+    })?;
+    delete_orphaned_files(&owner, orphaned_files);
+    Ok(())
+}
+
+/// Delete the files of purged items. The purge is already committed at this point,
+/// so failures are only logged, and the remaining files are still deleted.
+fn delete_orphaned_files(owner: &str, orphaned_files: Vec<String>) {
+    for sha256 in orphaned_files {
+        if let Err(err) = file_api::delete_file(owner, &sha256) {
+            error!(
+                "Failed to delete file {} of owner {}, {}",
+                sha256, owner, err
+            );
+        }
+    }
+}
+
+pub fn get_revisions(
+    owner: String,
+    init_db: &RwLock<HashSet<String>>,
+    body: Bytes,
+) -> Result<Vec<Value>> {
+    let body = &mut serde_json::Deserializer::from_slice(body.deref());
+    let body: PayloadWrapper<GetRevisions> = serde_path_to_error::deserialize(body)?;
+    let auth = body.auth;
+    let payload = body.payload;
+    let database_key = auth_to_database_key(auth)?;
+    let mut conn: Connection = check_owner_and_initialize_db(&owner, init_db, &database_key)?;
+    in_transaction(&mut conn, &owner, |tx| {
+        internal_api::get_revisions(tx, payload)
+    })
+}
+
+pub fn restore_revision(
+    owner: String,
+    init_db: &RwLock<HashSet<String>>,
+    schemas: &SchemaCache,
+    body: Bytes,
+) -> Result<()> {
+    let body = &mut serde_json::Deserializer::from_slice(body.deref());
+    let body: PayloadWrapper<RestoreRevision> = serde_path_to_error::deserialize(body)?;
+    let auth = body.auth;
+    let payload = body.payload;
+    let author = auth_to_author(&auth)?;
+    let database_key = auth_to_database_key(auth)?;
+    let mut conn: Connection = check_owner_and_initialize_db(&owner, init_db, &database_key)?;
+    in_schema_transaction(&mut conn, &owner, schemas, |tx, schema| {
+        internal_api::restore_revision(tx, schema, payload, &author)
     })
 }
 
@@ -131,12 +296,15 @@ pub fn create_edge(
     let payload = body.payload;
     let database_key = auth_to_database_key(auth)?;
     let mut conn: Connection = check_owner_and_initialize_db(&owner, init_db, &database_key)?;
-    in_transaction(&mut conn, |tx| internal_api::create_edge(tx, payload))
+    in_transaction(&mut conn, &owner, |tx| {
+        internal_api::create_edge(tx, payload)
+    })
 }
 
 pub fn get_edges(
     owner: String,
     init_db: &RwLock<HashSet<String>>,
+    schemas: &SchemaCache,
     body: Bytes,
 ) -> Result<Vec<Value>> {
     let body = &mut serde_json::Deserializer::from_slice(body.deref());
@@ -145,22 +313,328 @@ pub fn get_edges(
     let payload = body.payload;
     let database_key = auth_to_database_key(auth)?;
     let mut conn: Connection = check_owner_and_initialize_db(&owner, init_db, &database_key)?;
-    in_transaction(&mut conn, |tx| {
-        let schema = database_api::get_schema(tx)?;
-        internal_api::get_edges(tx, payload, &schema)
+    in_schema_transaction(&mut conn, &owner, schemas, |tx, schema| {
+        internal_api::get_edges(tx, payload, schema)
     })
 }
 
-pub fn search(owner: String, init_db: &RwLock<HashSet<String>>, body: Bytes) -> Result<Vec<Value>> {
+pub fn search(
+    owner: String,
+    init_db: &RwLock<HashSet<String>>,
+    schemas: &SchemaCache,
+    body: Bytes,
+) -> Result<Vec<Value>> {
     let body = &mut serde_json::Deserializer::from_slice(body.deref());
     let body: PayloadWrapper<Search> = serde_path_to_error::deserialize(body)?;
     let auth = body.auth;
     let payload = body.payload;
     let database_key = auth_to_database_key(auth)?;
     let mut conn: Connection = check_owner_and_initialize_db(&owner, init_db, &database_key)?;
-    in_transaction(&mut conn, |tx| {
-        let schema = database_api::get_schema(tx)?;
-        internal_api::search(tx, &schema, payload)
+    in_schema_transaction(&mut conn, &owner, schemas, |tx, schema| {
+        internal_api::search(tx, schema, payload)
+    })
+}
+
+/// Search items and stream them as newline-delimited JSON, one item per line.
+///
+/// Items are read in batches, each in a short transaction (see `internal_api::SearchBatches`),
+/// and sent on a blocking thread. The first batch is read before responding,
+/// so that errors of the query itself, like an unknown property or a wrong database key,
+/// are reported as usual; later errors can only abort the response.
+pub fn search_ndjson(
+    owner: String,
+    init_db: &RwLock<HashSet<String>>,
+    schemas: &SchemaCache,
+    body: Bytes,
+) -> Result<Body> {
+    let body = &mut serde_json::Deserializer::from_slice(body.deref());
+    let body: PayloadWrapper<Search> = serde_path_to_error::deserialize(body)?;
+    let auth = body.auth;
+    let payload = body.payload;
+    let database_key = auth_to_database_key(auth)?;
+    let mut conn: Connection = check_owner_and_initialize_db(&owner, init_db, &database_key)?;
+    let schema = in_transaction(&mut conn, &owner, |tx| schemas.get(&owner, tx))?;
+    let mut batches = internal_api::SearchBatches::new(payload);
+    let first_batch = in_transaction(&mut conn, &owner, |tx| batches.next_batch(tx, &schema))?;
+    let (mut sender, response_body) = Body::channel();
+    let runtime = tokio::runtime::Handle::current();
+    tokio::task::spawn_blocking(move || {
+        let mut send_line = |item: Value| {
+            let mut line = serde_json::to_vec(&item)?;
+            line.push(b'\n');
+            runtime
+                .block_on(sender.send_data(line.into()))
+                .map_err(|err| Error {
+                    code: StatusCode::INTERNAL_SERVER_ERROR,
+                    msg: format!("Failed to stream search results, {}", err),
+                })
+        };
+        let mut batch = Ok(first_batch);
+        while let Ok(Some(items)) = batch {
+            if let Err(err) = items.into_iter().try_for_each(&mut send_line) {
+                batch = Err(err);
+                break;
+            }
+            batch = in_transaction(&mut conn, &owner, |tx| batches.next_batch(tx, &schema));
+        }
+        if let Err(err) = batch {
+            warn!("Aborting streamed search of owner {}: {}", owner, err);
+            sender.abort();
+        }
+    });
+    Ok(response_body)
+}
+
+/// Stream the owner's database as a tar archive, see `archive::export`.
+/// Errors that happen after the response started abort it,
+/// so that a truncated archive can't be mistaken for a complete one.
+pub fn export(
+    owner: String,
+    init_db: &RwLock<HashSet<String>>,
+    schemas: &SchemaCache,
+    body: Bytes,
+) -> Result<Body> {
+    let body = &mut serde_json::Deserializer::from_slice(body.deref());
+    let body: PayloadWrapper<ExportArchive> = serde_path_to_error::deserialize(body)?;
+    let auth = body.auth;
+    let payload = body.payload;
+    let database_key = auth_to_database_key(auth)?;
+    let mut conn: Connection = check_owner_and_initialize_db(&owner, init_db, &database_key)?;
+    let schema = in_transaction(&mut conn, &owner, |tx| schemas.get(&owner, tx))?;
+    let (sender, response_body) = Body::channel();
+    let mut writer = BodyWriter {
+        sender,
+        runtime: tokio::runtime::Handle::current(),
+    };
+    tokio::task::spawn_blocking(move || {
+        let buffered = BufWriter::new(&mut writer);
+        let result = archive::export(&conn, &schema, &owner, &payload, buffered);
+        if let Err(err) = result {
+            warn!("Aborting export of owner {}: {}", owner, err);
+            writer.sender.abort();
+        }
+    });
+    Ok(response_body)
+}
+
+/// Blocking writer into a streamed response body
+struct BodyWriter {
+    sender: warp::hyper::body::Sender,
+    runtime: tokio::runtime::Handle,
+}
+
+impl Write for BodyWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let data = Bytes::copy_from_slice(buf);
+        self.runtime
+            .block_on(self.sender.send_data(data))
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::BrokenPipe, err))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Run a command given on the command line, see `command_line_interface::Command`
+pub fn run_command(command: &Command, cli: &CliOptions) -> Result<()> {
+    match command {
+        Command::Export {
+            owner,
+            database_key,
+            output,
+            include_deleted,
+        } => {
+            check_owner(owner)?;
+            let database_path = format!("{}{}", owner, constants::DATABASE_SUFFIX);
+            if !PathBuf::from(constants::DATABASE_DIR)
+                .join(database_path)
+                .exists()
+            {
+                return Err(Error {
+                    code: StatusCode::NOT_FOUND,
+                    msg: format!("No database found for owner {}", owner),
+                });
+            }
+            let database_key = DatabaseKey::from(database_key.to_string())?;
+            let mut conn = initialize_db(owner, &RwLock::new(HashSet::new()), &database_key)?;
+            let file = File::create(output).map_err(|err| Error {
+                code: StatusCode::INTERNAL_SERVER_ERROR,
+                msg: format!("Failed to create {}, {}", output, err),
+            })?;
+            let options = ExportArchive {
+                include_deleted: *include_deleted,
+            };
+            let schema = in_transaction(&mut conn, owner, database_api::get_schema)?;
+            let report = archive::export(&conn, &schema, owner, &options, BufWriter::new(file))?;
+            info!("Exported {:?} to {}", report, output);
+            Ok(())
+        }
+        Command::Import {
+            owner,
+            database_key,
+            input,
+            dry_run,
+        } => {
+            check_owner(owner)?;
+            let database_key = DatabaseKey::from(database_key.to_string())?;
+            let mut conn = initialize_db(owner, &RwLock::new(HashSet::new()), &database_key)?;
+            let file = File::open(input).map_err(|err| Error {
+                code: StatusCode::BAD_REQUEST,
+                msg: format!("Failed to open {}, {}", input, err),
+            })?;
+            let schema = in_transaction(&mut conn, owner, database_api::get_schema)?;
+            let report = archive::import(
+                &conn,
+                schema,
+                owner,
+                cli,
+                &database_key,
+                *dry_run,
+                BufReader::new(file),
+            )?;
+            let report = serde_json::to_string(&report)?;
+            info!("Imported {} from {}", input, report);
+            Ok(())
+        }
+    }
+}
+
+pub fn changes_since(
+    owner: String,
+    init_db: &RwLock<HashSet<String>>,
+    schemas: &SchemaCache,
+    body: Bytes,
+) -> Result<Value> {
+    let body = &mut serde_json::Deserializer::from_slice(body.deref());
+    let body: PayloadWrapper<ChangesSince> = serde_path_to_error::deserialize(body)?;
+    let auth = body.auth;
+    let payload = body.payload;
+    let database_key = auth_to_database_key(auth)?;
+    let mut conn: Connection = check_owner_and_initialize_db(&owner, init_db, &database_key)?;
+    in_schema_transaction(&mut conn, &owner, schemas, |tx, schema| {
+        internal_api::changes_since(tx, schema, payload)
+    })
+}
+
+pub fn acknowledge_changes(
+    owner: String,
+    init_db: &RwLock<HashSet<String>>,
+    schemas: &SchemaCache,
+    body: Bytes,
+    cli: &CliOptions,
+) -> Result<Value> {
+    let body = &mut serde_json::Deserializer::from_slice(body.deref());
+    let body: PayloadWrapper<AcknowledgeChanges> = serde_path_to_error::deserialize(body)?;
+    let auth = body.auth;
+    let payload = body.payload;
+    let database_key = auth_to_database_key(auth)?;
+    let mut conn: Connection = check_owner_and_initialize_db(&owner, init_db, &database_key)?;
+    in_schema_transaction(&mut conn, &owner, schemas, |tx, schema| {
+        internal_api::acknowledge_changes_tx(tx, schema, payload, &owner, cli, &database_key)
+    })
+}
+
+pub fn set_sync_scope(owner: String, init_db: &RwLock<HashSet<String>>, body: Bytes) -> Result<()> {
+    let body = &mut serde_json::Deserializer::from_slice(body.deref());
+    let body: PayloadWrapper<SetSyncScope> = serde_path_to_error::deserialize(body)?;
+    let auth = body.auth;
+    let payload = body.payload;
+    let database_key = auth_to_database_key(auth)?;
+    let mut conn: Connection = check_owner_and_initialize_db(&owner, init_db, &database_key)?;
+    in_transaction(&mut conn, &owner, |tx| {
+        sync_scope::set_sync_scope_tx(tx, payload)
+    })
+}
+
+/// Pull the changes of another Pod into the owner's database, see `replication`
+pub fn replicate(
+    owner: String,
+    init_db: &RwLock<HashSet<String>>,
+    schemas: &SchemaCache,
+    body: Bytes,
+    cli: &CliOptions,
+) -> Result<Value> {
+    let body = &mut serde_json::Deserializer::from_slice(body.deref());
+    let body: PayloadWrapper<Replicate> = serde_path_to_error::deserialize(body)?;
+    let auth = body.auth;
+    let peer = body.payload;
+    let database_key = auth_to_database_key(auth)?;
+    let mut conn: Connection = check_owner_and_initialize_db(&owner, init_db, &database_key)?;
+    let mut since = in_transaction(&mut conn, &owner, |tx| {
+        database_api::get_replication_checkpoint(tx, &peer.url, &peer.owner_key)
+    })?;
+    let (mut applied, mut skipped) = (0, 0);
+    let mut conflicts = Vec::new();
+    let mut pending_edges = Vec::new();
+    loop {
+        // Each page is fetched before its transaction, to not block the database meanwhile
+        let changes = tokio::task::block_in_place(|| {
+            tokio::runtime::Handle::current()
+                .block_on(replication::fetch_changes(&peer, since, cli))
+        })?;
+        let has_more = changes.has_more;
+        let report = in_schema_transaction(&mut conn, &owner, schemas, |tx, schema| {
+            let pending_edges = &mut pending_edges;
+            replication::apply_changes(
+                tx,
+                schema,
+                &peer,
+                changes,
+                pending_edges,
+                &owner,
+                cli,
+                &database_key,
+            )
+        })?;
+        applied += report.applied;
+        skipped += report.skipped;
+        conflicts.extend(report.conflicts);
+        since = report.change_seq;
+        if !has_more {
+            break;
+        }
+    }
+    Ok(serde_json::json!({
+        "applied": applied,
+        "skipped": skipped,
+        "conflicts": conflicts,
+        "changeSeq": since,
+    }))
+}
+
+/// Check access to the owner's database and subscribe to its change events
+pub fn subscribe(
+    owner: String,
+    init_db: &RwLock<HashSet<String>>,
+    body: Bytes,
+) -> Result<(Subscribe, broadcast::Receiver<ChangeEvent>)> {
+    let body = &mut serde_json::Deserializer::from_slice(body.deref());
+    let body: PayloadWrapper<Subscribe> = serde_path_to_error::deserialize(body)?;
+    let auth = body.auth;
+    let payload = body.payload;
+    let database_key = auth_to_database_key(auth)?;
+    let conn: Connection = check_owner_and_initialize_db(&owner, init_db, &database_key)?;
+    conn.execute_batch("SELECT 1 FROM items;")?; // Check DB access
+    let receiver = change_events::subscribe(&owner)?;
+    Ok((payload, receiver))
+}
+
+pub fn export_schema(
+    owner: String,
+    init_db: &RwLock<HashSet<String>>,
+    schemas: &SchemaCache,
+    body: Bytes,
+) -> Result<Value> {
+    let body = &mut serde_json::Deserializer::from_slice(body.deref());
+    let body: PayloadWrapper<ExportSchema> = serde_path_to_error::deserialize(body)?;
+    let auth = body.auth;
+    let payload = body.payload;
+    let database_key = auth_to_database_key(auth)?;
+    let mut conn: Connection = check_owner_and_initialize_db(&owner, init_db, &database_key)?;
+    in_schema_transaction(&mut conn, &owner, schemas, |tx, schema| {
+        internal_api::export_schema(tx, schema, payload)
     })
 }
 
@@ -178,7 +652,7 @@ pub fn upload_file(
     let database_key = DatabaseKey::from(database_key)?;
     let mut conn: Connection = check_owner_and_initialize_db(&owner, init_db, &database_key)?;
     conn.execute_batch("SELECT 1 FROM items;")?; // Check DB access
-    in_transaction(&mut conn, |tx| {
+    in_transaction(&mut conn, &owner, |tx| {
         file_api::upload_file(tx, &owner, &expected_sha256, body)
     })
 }
@@ -200,7 +674,7 @@ pub fn upload_file_b(
     let database_key = auth_to_database_key(auth)?;
     let mut conn: Connection = check_owner_and_initialize_db(&owner, init_db, &database_key)?;
     conn.execute_batch("SELECT 1 FROM items;")?; // Check DB access
-    in_transaction(&mut conn, |tx| {
+    in_transaction(&mut conn, &owner, |tx| {
         file_api::upload_file(tx, &owner, &expected_sha256, body)
     })
 }
@@ -213,11 +687,38 @@ pub fn get_file(owner: String, init_db: &RwLock<HashSet<String>>, body: Bytes) -
     let database_key = auth_to_database_key(auth)?;
     let mut conn: Connection = check_owner_and_initialize_db(&owner, init_db, &database_key)?;
     conn.execute_batch("SELECT 1 FROM items;")?; // Check DB access
-    in_transaction(&mut conn, |tx| {
+    in_transaction(&mut conn, &owner, |tx| {
         file_api::get_file(tx, &owner, &payload.sha256)
     })
 }
 
+pub fn files_manifest(
+    owner: String,
+    init_db: &RwLock<HashSet<String>>,
+    body: Bytes,
+) -> Result<Value> {
+    let body = &mut serde_json::Deserializer::from_slice(body.deref());
+    let body: PayloadWrapper<FilesManifest> = serde_path_to_error::deserialize(body)?;
+    let auth = body.auth;
+    let payload = body.payload;
+    let database_key = auth_to_database_key(auth)?;
+    let mut conn: Connection = check_owner_and_initialize_db(&owner, init_db, &database_key)?;
+    in_transaction(&mut conn, &owner, |tx| {
+        file_api::files_manifest(tx, &owner, payload)
+    })
+}
+
+pub fn files_exist(owner: String, init_db: &RwLock<HashSet<String>>, body: Bytes) -> Result<Value> {
+    let body = &mut serde_json::Deserializer::from_slice(body.deref());
+    let body: PayloadWrapper<FilesExist> = serde_path_to_error::deserialize(body)?;
+    let auth = body.auth;
+    let payload = body.payload;
+    let database_key = auth_to_database_key(auth)?;
+    let conn: Connection = check_owner_and_initialize_db(&owner, init_db, &database_key)?;
+    conn.execute_batch("SELECT 1 FROM items;")?; // Check DB access
+    file_api::files_exist(&owner, payload)
+}
+
 //
 // Email API
 //
@@ -242,16 +743,63 @@ pub fn send_email(
 // helper functions:
 //
 
+/// Run a transaction on the owner's database.
+/// The changes it makes are published to subscribers after the commit.
 fn in_transaction<T, F: FnOnce(&Transaction) -> Result<T>>(
     conn: &mut Connection,
+    owner: &str,
     func: F,
 ) -> Result<T> {
     let tx = conn.transaction()?;
+    let tracker = change_events::start_tracking(owner, &tx)?;
     let result = func(&tx)?; // Note that this function needs to exit early in case of error
+    let events = match &tracker {
+        Some(tracker) => change_events::collect(&tx, tracker)?,
+        None => Vec::new(),
+    };
     tx.commit()?;
+    change_events::publish(owner, events);
     Ok(result)
 }
 
+/// Run a transaction with the owner's (cached) Schema, see `in_transaction`.
+/// If the transaction changes the Schema, the cached one is invalidated after the commit.
+fn in_schema_transaction<T, F: FnOnce(&Transaction, &mut Schema) -> Result<T>>(
+    conn: &mut Connection,
+    owner: &str,
+    schemas: &SchemaCache,
+    func: F,
+) -> Result<T> {
+    let mut changed = false;
+    let result = in_transaction(conn, owner, |tx| {
+        let cached = schemas.get(owner, tx)?;
+        // Cheap, the clone shares the cached Schema's content until it is changed
+        let mut schema = Schema::clone(&cached);
+        let result = func(tx, &mut schema);
+        changed = schema.is_changed_from(&cached);
+        result
+    })?;
+    if changed {
+        schemas.invalidate(owner)?;
+    }
+    Ok(result)
+}
+
+/// Who makes the request, as recorded in item revisions
+fn auth_to_author(auth: &AuthKey) -> Result<String> {
+    match auth {
+        AuthKey::ClientAuth(_) => Ok(constants::REVISION_AUTHOR_CLIENT.to_string()),
+        AuthKey::PluginAuth(p) => {
+            let plugin_name = plugin_auth_crypto::extract_plugin_name(p)?;
+            Ok(format!(
+                "{}{}",
+                constants::REVISION_AUTHOR_PLUGIN_PREFIX,
+                plugin_name
+            ))
+        }
+    }
+}
+
 fn auth_to_database_key(auth: AuthKey) -> Result<DatabaseKey> {
     match auth {
         AuthKey::ClientAuth(c) => DatabaseKey::from(c.database_key),
@@ -267,24 +815,118 @@ fn check_owner_and_initialize_db(
     database_key: &DatabaseKey,
 ) -> Result<Connection> {
     check_owner(owner)?;
-    initialize_db(owner, init_db, database_key)
+    let conn = initialize_db(owner, init_db, database_key)?;
+    let cli = &command_line_interface::PARSED;
+    let purge_days = cli.purge_deleted_after_days;
+    let forget_days = cli.forget_applied_operations_after_days;
+    if purge_days.is_some() || forget_days.is_some() {
+        schedule_garbage_collection(owner, database_key, purge_days, forget_days);
+    }
+    Ok(conn)
 }
 
-fn initialize_db(
+/// Purge items deleted more than `purge_days` ago, and forget operations applied
+/// more than `forget_days` ago, in a background thread,
+/// unless it has already been done recently for this owner.
+fn schedule_garbage_collection(
     owner: &str,
-    init_db: &RwLock<HashSet<String>>,
     database_key: &DatabaseKey,
-) -> Result<Connection> {
+    purge_days: Option<u32>,
+    forget_days: Option<u32>,
+) {
+    let interval = Duration::from_secs(constants::GARBAGE_COLLECTION_INTERVAL_SECONDS);
+    {
+        let mut last_run = match global_static::GARBAGE_COLLECTION_LAST_RUN.lock() {
+            Ok(last_run) => last_run,
+            Err(err) => {
+                error!("Failed to lock garbage collection state, {}", err);
+                return;
+            }
+        };
+        let now = Instant::now();
+        match last_run.get(owner) {
+            Some(time) if now.duration_since(*time) < interval => return,
+            _ => last_run.insert(owner.to_string(), now),
+        };
+    }
+    let owner = owner.to_string();
+    let database_key = database_key.clone();
+    std::thread::spawn(move || {
+        let days_ago =
+            |days: u32| Utc::now().timestamp_millis() - i64::from(days) * 24 * 3600 * 1000;
+        let mut conn = match open_database(&owner, &database_key) {
+            Ok(conn) => conn,
+            Err(err) => {
+                error!("Garbage collection failed for owner {}, {}", owner, err);
+                return;
+            }
+        };
+        // Each batch is purged in its own transaction, not to block writers for long
+        let deleted_before = purge_days.map(days_ago);
+        while let Some(deleted_before) = deleted_before {
+            let result = in_transaction(&mut conn, &owner, |tx| {
+                let mut schema = database_api::get_schema(tx)?;
+                let limit = constants::GARBAGE_COLLECTION_BATCH_SIZE;
+                garbage_collection::purge_deleted_items_tx(tx, &mut schema, deleted_before, limit)
+            });
+            match result {
+                Ok(report) => {
+                    delete_orphaned_files(&owner, report.orphaned_files);
+                    if report.finished {
+                        break;
+                    }
+                }
+                Err(err) => {
+                    error!("Garbage collection failed for owner {}, {}", owner, err);
+                    break;
+                }
+            }
+        }
+        if let Some(applied_before) = forget_days.map(days_ago) {
+            let result = in_transaction(&mut conn, &owner, |tx| {
+                garbage_collection::forget_applied_operations_tx(tx, applied_before)
+            });
+            if let Err(err) = result {
+                error!("Garbage collection failed for owner {}, {}", owner, err);
+            }
+        }
+    });
+}
+
+fn open_database(owner: &str, database_key: &DatabaseKey) -> Result<Connection> {
     let database_path = format!("{}{}", &owner, constants::DATABASE_SUFFIX);
     let database_path = PathBuf::from(constants::DATABASE_DIR).join(database_path);
-    let mut conn = Connection::open(database_path)
+    let conn = Connection::open(database_path)
         .context_str("Failed to open database file (does Pod have filesystem access?)")?;
     DatabaseKey::execute_sqlite_pragma(database_key, &conn)
         .context_str("Failed to open the database file (did databaseKey change between runs?)")?;
     conn.execute_batch("PRAGMA foreign_keys = ON;")?;
+    Ok(conn)
+}
+
+fn initialize_db(
+    owner: &str,
+    init_db: &RwLock<HashSet<String>>,
+    database_key: &DatabaseKey,
+) -> Result<Connection> {
+    let mut conn = open_database(owner, database_key)?;
     let mut init_db = init_db.write()?;
     if !init_db.contains(owner) {
         database_migrate_refinery::migrate(&mut conn)?;
+        if !SCHEMA_BUNDLE.is_empty() {
+            in_transaction(&mut conn, owner, |tx| {
+                let mut schema = database_api::get_schema(tx)?;
+                let cli = &command_line_interface::PARSED;
+                schema_bundle::apply_schema_bundle(
+                    tx,
+                    &mut schema,
+                    &SCHEMA_BUNDLE,
+                    owner,
+                    cli,
+                    database_key,
+                )
+            })?;
+        }
         init_db.insert(owner.to_string());
     }
     Ok(conn)
@@ -311,6 +953,22 @@ lazy_static! {
         allowed_owner_hashes_fn(&command_line_interface::PARSED);
 }
 
+fn schema_bundle_fn(cli_options: &CliOptions) -> Vec<CreateItem> {
+    if let Some(path) = &cli_options.schema_file {
+        schema_bundle::load_schema_bundle(path).unwrap_or_else(|err| {
+            error!("Failed to load Schema bundle, {}", err);
+            std::process::exit(1);
+        })
+    } else {
+        Vec::new()
+    }
+}
+
+lazy_static! {
+    pub static ref SCHEMA_BUNDLE: Vec<CreateItem> =
+        schema_bundle_fn(&command_line_interface::PARSED);
+}
+
 fn hash_of_hex(hex_string: &str) -> Result<GenericArray<u8, U32>> {
     let hex_string: Vec<u8> = hex::decode(hex_string)?;
     let mut hash = sha2::Sha256::new();